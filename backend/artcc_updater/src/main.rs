@@ -11,9 +11,11 @@ use model::{FlatFacility, FlatPosition};
 
 use reqwest::Client;
 use shared::error::InitializationError;
+use shared::health::{self, Checks, Health};
+use shared::telemetry::init_telemetry;
 use shared::vnas::api::minimal::Facility as MinimalFacility;
 use shared::vnas::api::minimal::{ArtccRoot as MinimalArtccRoot, ArtccRoot};
-use shared::{init_tracing_and_oltp, initialize_db, load_config};
+use shared::{initialize_db, load_config};
 use sqlx::{Pool, Postgres, Row};
 use thiserror::Error;
 use tower_http::trace::TraceLayer;
@@ -23,55 +25,54 @@ use tracing::{debug, error, info, instrument};
 pub struct AxumState {
     client: Client,
     db_pool: Pool<Postgres>,
+    health: Health,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    let (tracer_provider, meter_provider) = init_tracing_and_oltp("artcc_updater")?;
-
     let config = load_config().map_err(InitializationError::from)?;
+    let telemetry = init_telemetry("artcc_updater", &config.telemetry)?;
     info!(name: "config.loaded", config = ?config, "config loaded");
     let db_pool = initialize_db(&config.postgres, true).await?;
     let client = Client::new();
+    // Syncs are triggered externally, so only Postgres connectivity decides readiness
+    let health = Health::new("artcc_updater", &config.health, Checks::default())
+        .with_database(db_pool.clone());
 
     let app = Router::new()
-        .route("/health", get(health_check))
         .route("/update", get(update_data))
+        .merge(health::router(health.clone()))
+        .merge(telemetry.metrics_router())
         .layer(TraceLayer::new_for_http())
-        .with_state(AxumState { client, db_pool });
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shared::shutdown_listener(None))
-        .await
-        .unwrap();
-
-    if let Err(e) = tracer_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
-    }
+        .with_state(AxumState {
+            client,
+            db_pool,
+            health,
+        });
+    health::serve(
+        config.health.bind_address,
+        app,
+        shared::shutdown_listener(None),
+    )
+    .await?;
 
-    if let Err(e) = meter_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
-    }
+    telemetry.shutdown();
 
     Ok(())
 }
 
-async fn health_check() -> impl IntoResponse {
-    (StatusCode::OK, "Service is healthy!")
-}
-
 #[instrument(skip(state))]
 async fn update_data(State(state): State<AxumState>) -> impl IntoResponse {
     let res = fetch_and_process(&state.client, &state.db_pool).await;
     match res {
         Ok(()) => {
             info!(name: "artcc.updated", "ARTCC data sync was successful");
+            state.health.record_success(Utc::now());
             (StatusCode::OK, "ARTCC data sync was successful".to_string())
         }
         Err(ref e) => {
             error!(name: "artcc.updated", error = ?e, "failed to sync ARTCC data");
+            state.health.record_error(e);
             (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
         }
     }
@@ -336,4 +337,6 @@ enum AppError {
     Migration(#[from] sqlx::migrate::MigrateError),
     #[error("database error: {0}")]
     Db(#[from] sqlx::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
 }
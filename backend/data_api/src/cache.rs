@@ -0,0 +1,311 @@
+//! Public responses cached until the processor applies a newer datafeed, so repeated requests for
+//! the same interval don't each run a heavy aggregate. The processor notifies
+//! [`DATAFEED_PROCESSED_CHANNEL`] after each batch; in case notifications are missed, the latest
+//! datafeed is only trusted for [`CacheConfig::max_age_seconds`] before it's looked up again.
+//! Fields that depend on when a response is requested are filled in per request by [`PerRequest`],
+//! which also says when a response expires regardless of datafeeds, e.g. with an exclusion.
+
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::Serialize;
+use shared::{CacheConfig, DATAFEED_PROCESSED_CHANNEL};
+use sqlx::postgres::PgListener;
+use sqlx::{Pool, Postgres};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::{info, trace, warn};
+
+/// A cached response: the route and the interval it was requested for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    route: &'static str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+impl CacheKey {
+    pub fn new(route: &'static str, start: DateTime<Utc>, end: DateTime<Utc>) -> Self {
+        Self { route, start, end }
+    }
+}
+
+/// A cached response with fields that depend on when it's requested rather than on the datafeed
+pub trait PerRequest {
+    /// Updates those fields for a request made at `requested_at`
+    fn set_requested_at(&mut self, requested_at: DateTime<Utc>);
+
+    /// When the response stops being valid even without a newer datafeed, such as when an
+    /// exclusion it applied expires
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+}
+
+#[derive(Clone)]
+pub struct ResponseCache {
+    inner: Arc<Mutex<Inner>>,
+    config: CacheConfig,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// `updatedAt` of the latest processed datafeed, and when it was learned
+    latest: Option<(DateTime<Utc>, Instant)>,
+    /// Responses, all computed from the `latest` datafeed
+    responses: HashMap<CacheKey, CachedResponse>,
+    /// Bumped by [`ResponseCache::clear`], so responses built from data read before a clear
+    /// aren't cached after it
+    generation: u64,
+}
+
+struct CachedResponse {
+    response: Arc<dyn Any + Send + Sync>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            inner: Arc::default(),
+            config,
+        }
+    }
+
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(self.config.max_age_seconds)
+    }
+
+    /// The latest processed datafeed, unless it's unknown or hasn't been confirmed recently
+    pub fn latest_datafeed(&self) -> Option<DateTime<Utc>> {
+        if !self.config.enabled {
+            return None;
+        }
+        let inner = self.inner.lock();
+        inner
+            .latest
+            .filter(|(_, learned)| learned.elapsed() <= self.max_age())
+            .map(|(latest, _)| latest)
+    }
+
+    /// Records the latest processed datafeed, dropping every response computed from an older one
+    pub fn set_latest_datafeed(&self, updated_at: DateTime<Utc>) {
+        let mut inner = self.inner.lock();
+        match inner.latest {
+            // A lookup that raced a notification mustn't move the cache backwards
+            Some((latest, learned))
+                if latest > updated_at && learned.elapsed() <= self.max_age() => {}
+            Some((latest, _)) if latest == updated_at => {
+                inner.latest = Some((latest, Instant::now()));
+            }
+            _ => {
+                inner.latest = Some((updated_at, Instant::now()));
+                inner.responses.clear();
+            }
+        }
+    }
+
+    /// Drops every response, for changes that affect them other than new datafeeds. Responses
+    /// still being built aren't cached either.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.responses.clear();
+        inner.generation += 1;
+    }
+
+    fn get<T: Clone + 'static>(
+        &self,
+        key: &CacheKey,
+        latest: DateTime<Utc>,
+        requested_at: DateTime<Utc>,
+    ) -> Option<T> {
+        let inner = self.inner.lock();
+        match inner.latest {
+            Some((current, _)) if current == latest => inner
+                .responses
+                .get(key)
+                .filter(|cached| cached.expires_at.is_none_or(|at| requested_at < at))
+                .and_then(|cached| cached.response.downcast_ref::<T>())
+                .cloned(),
+            _ => None,
+        }
+    }
+
+    fn insert<T: PerRequest + Send + Sync + 'static>(
+        &self,
+        key: CacheKey,
+        latest: DateTime<Utc>,
+        generation: u64,
+        response: T,
+    ) {
+        let mut inner = self.inner.lock();
+        let current = inner.latest.is_some_and(|(current, _)| current == latest)
+            && inner.generation == generation;
+        if current && inner.responses.len() < self.config.max_entries {
+            let expires_at = response.expires_at();
+            inner.responses.insert(
+                key,
+                CachedResponse {
+                    response: Arc::new(response),
+                    expires_at,
+                },
+            );
+        }
+    }
+
+    /// Serves the response for `key` if it was computed from the `latest` datafeed and hasn't
+    /// expired, with its [`PerRequest`] fields set for `requested_at`. Otherwise builds it with
+    /// `build` and caches it, unless the cache was cleared meanwhile.
+    pub async fn get_or_insert_with<T, E, F>(
+        &self,
+        key: CacheKey,
+        latest: DateTime<Utc>,
+        requested_at: DateTime<Utc>,
+        build: impl FnOnce() -> F,
+    ) -> Result<Response, E>
+    where
+        T: Serialize + PerRequest + Clone + Send + Sync + 'static,
+        F: Future<Output = Result<T, E>>,
+    {
+        if !self.config.enabled {
+            return Ok(Json(build().await?).into_response());
+        }
+        if let Some(mut response) = self.get::<T>(&key, latest, requested_at) {
+            response.set_requested_at(requested_at);
+            return Ok(Json(response).into_response());
+        }
+
+        let generation = self.inner.lock().generation;
+        let response = build().await?;
+        self.insert(key, latest, generation, response.clone());
+        Ok(Json(response).into_response())
+    }
+}
+
+/// Updates the cache's latest datafeed whenever the processor applies a new one. Runs until the
+/// task is aborted.
+pub async fn listen_for_datafeeds(pool: Pool<Postgres>, cache: ResponseCache) {
+    loop {
+        let mut listener = match PgListener::connect_with(&pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!(name: "cache.listener.connect", error = ?e, "failed to connect datafeed listener");
+                sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        if let Err(e) = listener.listen(DATAFEED_PROCESSED_CHANNEL).await {
+            warn!(name: "cache.listener.connect", error = ?e, "failed to listen for processed datafeeds");
+            sleep(Duration::from_secs(5)).await;
+            continue;
+        }
+        info!(name: "cache.listener.started", "listening for processed datafeeds");
+
+        loop {
+            match listener.recv().await {
+                Ok(notification) => match DateTime::parse_from_rfc3339(notification.payload()) {
+                    Ok(updated_at) => {
+                        trace!(name: "cache.listener.received", payload = notification.payload(), "datafeed processed");
+                        cache.set_latest_datafeed(updated_at.to_utc());
+                    }
+                    Err(e) => {
+                        warn!(name: "cache.listener.received", payload = notification.payload(), error = ?e, "unexpected datafeed notification");
+                    }
+                },
+                Err(e) => {
+                    // The listener reconnects on the next `recv`; anything missed meanwhile is
+                    // caught by the max age
+                    warn!(name: "cache.listener.received", error = ?e, "error receiving Postgres notification");
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use std::convert::Infallible;
+
+    #[derive(Clone, Serialize)]
+    struct Leaderboard {
+        expires_at: Option<DateTime<Utc>>,
+    }
+
+    impl PerRequest for Leaderboard {
+        fn set_requested_at(&mut self, _requested_at: DateTime<Utc>) {}
+
+        fn expires_at(&self) -> Option<DateTime<Utc>> {
+            self.expires_at
+        }
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn key() -> CacheKey {
+        CacheKey::new(
+            "test",
+            at("2025-12-20T00:00:00Z"),
+            at("2025-12-21T00:00:00Z"),
+        )
+    }
+
+    /// Requests the response from `cache`, counting in `built` whether it had to be built
+    async fn request(
+        cache: &ResponseCache,
+        requested_at: DateTime<Utc>,
+        built: &mut u32,
+        expires_at: Option<DateTime<Utc>>,
+        during_build: impl FnOnce(),
+    ) {
+        let latest = at("2025-12-20T12:00:00Z");
+        cache
+            .get_or_insert_with(key(), latest, requested_at, || async {
+                *built += 1;
+                during_build();
+                Ok::<_, Infallible>(Leaderboard { expires_at })
+            })
+            .await
+            .unwrap();
+    }
+
+    fn cache() -> ResponseCache {
+        let cache = ResponseCache::new(CacheConfig::default());
+        cache.set_latest_datafeed(at("2025-12-20T12:00:00Z"));
+        cache
+    }
+
+    #[tokio::test]
+    async fn responses_built_across_a_clear_arent_cached() {
+        let cache = cache();
+        let now = at("2025-12-20T12:00:05Z");
+        let mut built = 0;
+
+        request(&cache, now, &mut built, None, || cache.clear()).await;
+        request(&cache, now, &mut built, None, || {}).await;
+        request(&cache, now, &mut built, None, || {}).await;
+        assert_eq!(built, 2);
+    }
+
+    #[tokio::test]
+    async fn responses_are_rebuilt_once_they_expire() {
+        let cache = cache();
+        let now = at("2025-12-20T12:00:05Z");
+        let expires_at = Some(now + TimeDelta::seconds(5));
+        let mut built = 0;
+
+        request(&cache, now, &mut built, expires_at, || {}).await;
+        request(&cache, now + TimeDelta::seconds(4), &mut built, None, || {}).await;
+        assert_eq!(built, 1);
+        request(&cache, now + TimeDelta::seconds(5), &mut built, None, || {}).await;
+        assert_eq!(built, 2);
+    }
+}
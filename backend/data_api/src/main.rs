@@ -1,14 +1,22 @@
+mod cache;
+mod metrics;
 mod state;
 mod v1;
 
-use crate::state::{Db, HttpClients, Oauth};
+use crate::cache::{ResponseCache, listen_for_datafeeds};
+use crate::metrics::{RequestMetrics, record_request_metrics};
+use crate::state::{Db, HttpClients, Limiters, Oauth};
 use anyhow::anyhow;
-use axum::http::StatusCode;
-use axum::{Router, routing::get};
+use axum::Router;
+use axum::middleware::from_fn_with_state;
 use oauth2::{AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl, basic::BasicClient};
+use shared::health::{self, Checks, Health};
+use shared::telemetry::init_telemetry;
 use shared::vatsim::OauthEndpoints;
-use shared::{init_tracing_and_oltp, initialize_db, load_config};
+use shared::{initialize_db, load_config};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 use tower_http::compression::CompressionLayer;
 use tower_http::cors::{Any, CorsLayer};
 use tower_sessions::{ExpiredDeletion, Expiry, SessionManagerLayer};
@@ -24,8 +32,8 @@ async fn main() -> Result<(), anyhow::Error> {
     //         .export_to("./bindings.ts", &specta::export())?;
     //     return Ok(());
     // }
-    let (tracer_provider, meter_provider) = init_tracing_and_oltp("data_api")?;
     let config = load_config()?;
+    let telemetry = init_telemetry("data_api", &config.telemetry)?;
     info!(name: "config.loaded", config = ?config, "config loaded");
     let pool = initialize_db(&config.postgres, false).await?;
 
@@ -66,6 +74,21 @@ async fn main() -> Result<(), anyhow::Error> {
         .redirect(reqwest::redirect::Policy::none())
         .build()?;
 
+    // The API only serves requests, so only Postgres connectivity decides readiness
+    let health =
+        Health::new("data_api", &config.health, Checks::default()).with_database(pool.clone());
+
+    // Full readiness, including error messages, and metrics only on the internal bind address
+    let shutdown_token = CancellationToken::new();
+    let health_handle = tokio::spawn(health::serve(
+        config.health.bind_address,
+        health::router(health.clone()).merge(telemetry.metrics_router()),
+        shutdown_token.clone().cancelled_owned(),
+    ));
+
+    let cache = ResponseCache::new(config.api.cache.clone());
+    let listener_handle = tokio::spawn(listen_for_datafeeds(pool.clone(), cache.clone()));
+
     let state = state::AppState {
         db: Db { pool },
         oauth: Oauth {
@@ -77,11 +100,20 @@ async fn main() -> Result<(), anyhow::Error> {
             standard: standard_http_client,
             no_redirect: no_redirect_http_client,
         },
+        maintenance: config.maintenance,
+        health: config.health,
+        api: config.api,
+        limiters: Limiters::default(),
+        cache,
     };
 
     let app = Router::new()
-        .route("/health", get(|| async { StatusCode::OK }))
+        .merge(health::public_router(health))
         .nest("/v1", v1::router(state.clone()))
+        .layer(from_fn_with_state(
+            RequestMetrics::default(),
+            record_request_metrics,
+        ))
         .layer(session_layer)
         .layer(CompressionLayer::new())
         .layer(
@@ -96,9 +128,13 @@ async fn main() -> Result<(), anyhow::Error> {
     info!("starting server at {LISTEN_ADDR}");
     let listener = tokio::net::TcpListener::bind(LISTEN_ADDR).await?;
 
-    let res = axum::serve(listener, app)
-        .with_graceful_shutdown(shared::shutdown_listener(None))
-        .await;
+    // Client addresses are needed for per-IP rate limits
+    let res = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shared::shutdown_listener(Some(shutdown_token.clone())))
+    .await;
 
     if let Err(e) = res.as_ref() {
         warn!(name: "axum.shutdown", error = ?e, "error while shutting down axum");
@@ -110,13 +146,17 @@ async fn main() -> Result<(), anyhow::Error> {
         warn!(name: "sessions.shutdown", error = ?e, "failed to end session cleanup task");
     }
 
-    if let Err(e) = tracer_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
-    }
+    listener_handle.abort();
 
-    if let Err(e) = meter_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
+    // Also stops the health server if axum stopped for any other reason
+    shutdown_token.cancel();
+    match health_handle.await {
+        Ok(Err(e)) => warn!(name: "health.shutdown", error = ?e, "health server failed"),
+        Err(e) => warn!(name: "health.shutdown", error = ?e, "failed to end health server task"),
+        Ok(Ok(())) => {}
     }
 
+    telemetry.shutdown();
+
     Ok(res?)
 }
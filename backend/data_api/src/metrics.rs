@@ -0,0 +1,55 @@
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::{KeyValue, global};
+use tokio::time::Instant;
+
+#[derive(Clone)]
+pub struct RequestMetrics {
+    /// Requests served, by `method`, `route` and `status`
+    pub requests: Counter<u64>,
+    /// Time taken to serve a request, by `method`, `route` and `status`
+    pub duration: Histogram<f64>,
+}
+
+impl Default for RequestMetrics {
+    fn default() -> Self {
+        let meter = global::meter("data_api");
+        let requests = meter.u64_counter("http.server.requests").build();
+        let duration = meter
+            .f64_histogram("http.server.request.duration")
+            .with_unit("s")
+            .build();
+
+        Self { requests, duration }
+    }
+}
+
+/// Records every request against its route template (e.g. `/v1/callsigns/top`) rather than the
+/// requested URI, so path and query parameters don't create new time series
+pub async fn record_request_metrics(
+    State(metrics): State<RequestMetrics>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| "unmatched".to_string(), |path| path.as_str().to_string());
+
+    let response = next.run(req).await;
+
+    let attributes = [
+        KeyValue::new("method", method),
+        KeyValue::new("route", route),
+        KeyValue::new("status", i64::from(response.status().as_u16())),
+    ];
+    metrics.requests.add(1, &attributes);
+    metrics
+        .duration
+        .record(started.elapsed().as_secs_f64(), &attributes);
+    response
+}
@@ -1,3 +1,4 @@
+use crate::cache::ResponseCache;
 use axum::extract::FromRef;
 use oauth2::{
     Client, EndpointNotSet, EndpointSet, StandardRevocableToken,
@@ -6,9 +7,14 @@ use oauth2::{
         BasicTokenResponse,
     },
 };
+use shared::health::HealthConfig;
+use shared::rate_limit::RateLimiter;
 use shared::vatsim::OauthEnvironment;
+use shared::{ApiConfig, MaintenanceConfig};
 use sqlx::{Pool, Postgres};
+use std::net::IpAddr;
 use std::sync::Arc;
+use uuid::Uuid;
 
 pub type OauthClient = Client<
     BasicErrorResponse,
@@ -28,6 +34,20 @@ pub struct AppState {
     pub db: Db,
     pub oauth: Oauth,
     pub http_clients: HttpClients,
+    pub maintenance: MaintenanceConfig,
+    pub health: HealthConfig,
+    pub api: ApiConfig,
+    pub limiters: Limiters,
+    pub cache: ResponseCache,
+}
+
+/// Rate limits shared by every request
+#[derive(Clone, Default)]
+pub struct Limiters {
+    /// Keyed by API key id
+    pub api_keys: RateLimiter<Uuid>,
+    /// Keyed by client IP, or its /64 for IPv6
+    pub ips: RateLimiter<IpAddr>,
 }
 
 #[derive(Clone)]
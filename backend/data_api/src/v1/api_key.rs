@@ -0,0 +1,36 @@
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Marks a string as one of our API keys, e.g. for secret scanners
+const KEY_PREFIX: &str = "vns_";
+
+/// Random bytes in each key
+const KEY_BYTES: usize = 32;
+
+/// Characters of the key kept in the clear, so its owner can tell keys apart
+const DISPLAY_PREFIX_LEN: usize = KEY_PREFIX.len() + 8;
+
+/// A new key, which is only ever shown to its owner once
+pub struct GeneratedKey {
+    pub key: String,
+    pub prefix: String,
+    pub hash: String,
+}
+
+pub fn generate() -> GeneratedKey {
+    let mut bytes = [0u8; KEY_BYTES];
+    rand::rng().fill_bytes(&mut bytes);
+    let key = format!("{KEY_PREFIX}{}", hex::encode(bytes));
+
+    GeneratedKey {
+        prefix: key[..DISPLAY_PREFIX_LEN].to_string(),
+        hash: hash(&key),
+        key,
+    }
+}
+
+/// Keys are random enough that a plain SHA-256 is as good as a password hash, and cheap enough to
+/// check on every request
+pub fn hash(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
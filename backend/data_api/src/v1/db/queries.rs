@@ -1,13 +1,8 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use shared::MaintenanceConfig;
 use sqlx::{Pool, Postgres};
-
-/// Callsigns to exclude from Iron Mic stats.
-/// Format: (prefix, suffix) - e.g., ("SJU", "APP") for SJU_APP
-const IRON_MIC_EXCLUDED_CALLSIGNS: &[(&str, &str)] = &[
-    ("SJU", "APP"),
-    // Add more exclusions here as needed:
-    // ("ABC", "CTR"),
-];
+use uuid::Uuid;
 
 #[derive(Debug, thiserror::Error)]
 pub enum QueryError {
@@ -17,34 +12,185 @@ pub enum QueryError {
     IllegalArgs(String),
 }
 
+/// What an Iron Mic exclusion's value is matched against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, specta::Type)]
+#[serde(rename_all = "camelCase")]
+#[sqlx(type_name = "iron_mic_exclusion_kind", rename_all = "lowercase")]
+pub enum ExclusionKind {
+    /// A callsign pattern, where `*` matches any run of characters, e.g. `SJU_*APP`
+    Callsign,
+    /// A position id
+    Position,
+    /// A controller CID
+    Cid,
+}
+
 #[derive(sqlx::FromRow)]
-pub struct CallsignDurationStatsRecord {
-    pub prefix: String,
-    pub suffix: String,
-    pub duration_seconds: i64,
-    pub is_active: bool,
+pub struct ExclusionRecord {
+    pub id: Uuid,
+    pub kind: ExclusionKind,
+    pub value: String,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Active exclusions split by kind, ready to be bound as query parameters
+#[derive(Debug, Default)]
+pub struct IronMicExclusions {
+    /// `LIKE` patterns matched against `PREFIX_SUFFIX` callsigns
+    callsign_patterns: Vec<String>,
+    position_ids: Vec<String>,
+    cids: Vec<i32>,
 }
 
-/// Builds a SQL exclusion clause for callsigns, or empty string if no exclusions.
-fn build_callsign_exclusion_clause() -> String {
-    if IRON_MIC_EXCLUDED_CALLSIGNS.is_empty() {
-        return String::new();
+impl IronMicExclusions {
+    /// Exclusions with values that don't parse, such as a non-numeric CID, are skipped
+    pub fn new(records: &[ExclusionRecord]) -> Self {
+        let mut exclusions = Self::default();
+        for record in records {
+            match record.kind {
+                ExclusionKind::Callsign => exclusions
+                    .callsign_patterns
+                    .push(callsign_like_pattern(&record.value)),
+                ExclusionKind::Position => exclusions.position_ids.push(record.value.clone()),
+                ExclusionKind::Cid => {
+                    if let Ok(cid) = record.value.parse() {
+                        exclusions.cids.push(cid);
+                    }
+                }
+            }
+        }
+        exclusions
     }
+}
+
+/// Turns a callsign pattern into a `LIKE` pattern, escaping everything but `*`
+fn callsign_like_pattern(pattern: &str) -> String {
+    let mut like = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        match c {
+            '*' => like.push('%'),
+            '\\' | '%' | '_' => {
+                like.push('\\');
+                like.push(c);
+            }
+            c => like.push(c),
+        }
+    }
+    like
+}
+
+pub async fn get_exclusion(
+    pool: &Pool<Postgres>,
+    id: Uuid,
+) -> Result<Option<ExclusionRecord>, QueryError> {
+    sqlx::query_as::<_, ExclusionRecord>(
+        r"
+        SELECT id, kind, value, reason, expires_at, created_by, created_at
+        FROM iron_mic_exclusions
+        WHERE id = $1
+        ",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// Exclusions that haven't expired as of `now`
+pub async fn get_active_exclusions(
+    pool: &Pool<Postgres>,
+    now: DateTime<Utc>,
+) -> Result<Vec<ExclusionRecord>, QueryError> {
+    sqlx::query_as::<_, ExclusionRecord>(
+        r"
+        SELECT id, kind, value, reason, expires_at, created_by, created_at
+        FROM iron_mic_exclusions
+        WHERE expires_at IS NULL OR expires_at > $1
+        ORDER BY kind, value
+        ",
+    )
+    .bind(now)
+    .fetch_all(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// Every exclusion, including expired ones, newest first
+pub async fn get_exclusions(pool: &Pool<Postgres>) -> Result<Vec<ExclusionRecord>, QueryError> {
+    sqlx::query_as::<_, ExclusionRecord>(
+        r"
+        SELECT id, kind, value, reason, expires_at, created_by, created_at
+        FROM iron_mic_exclusions
+        ORDER BY created_at DESC
+        ",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// Adds an exclusion, or replaces the reason and expiry of an existing one with the same value
+pub async fn upsert_exclusion(
+    pool: &Pool<Postgres>,
+    kind: ExclusionKind,
+    value: &str,
+    reason: &str,
+    expires_at: Option<DateTime<Utc>>,
+    created_by: i32,
+) -> Result<ExclusionRecord, QueryError> {
+    sqlx::query_as::<_, ExclusionRecord>(
+        r"
+        INSERT INTO iron_mic_exclusions (kind, value, reason, expires_at, created_by)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (kind, value) DO UPDATE
+        SET reason = excluded.reason,
+            expires_at = excluded.expires_at,
+            created_by = excluded.created_by,
+            created_at = now()
+        RETURNING id, kind, value, reason, expires_at, created_by, created_at
+        ",
+    )
+    .bind(kind)
+    .bind(value)
+    .bind(reason)
+    .bind(expires_at)
+    .bind(created_by)
+    .fetch_one(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
 
-    let conditions: Vec<String> = IRON_MIC_EXCLUDED_CALLSIGNS
-        .iter()
-        .map(|(prefix, suffix)| format!("(prefix = '{prefix}' AND suffix = '{suffix}')"))
-        .collect();
+/// Returns whether an exclusion with `id` existed
+pub async fn delete_exclusion(pool: &Pool<Postgres>, id: Uuid) -> Result<bool, QueryError> {
+    let result = sqlx::query("DELETE FROM iron_mic_exclusions WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(QueryError::Sql)?;
+    Ok(result.rows_affected() > 0)
+}
 
-    format!(" AND NOT ({})", conditions.join(" OR "))
+#[derive(sqlx::FromRow)]
+pub struct CallsignDurationStatsRecord {
+    pub prefix: String,
+    pub suffix: String,
+    pub duration_seconds: i64,
+    pub is_active: bool,
 }
 
+/// Total time each callsign was active in `[start, end)`. Whole UTC days that have been rolled up
+/// are read from `callsign_daily_stats`, and only the rest of the interval from raw sessions. The
+/// current day is never rolled up, so sessions that are still active are always seen.
 pub async fn get_iron_mic_stats(
     pool: &Pool<Postgres>,
     start: DateTime<Utc>,
     end: DateTime<Utc>,
     now: DateTime<Utc>,
     limit: i64,
+    exclusions: &IronMicExclusions,
 ) -> Result<Vec<CallsignDurationStatsRecord>, QueryError> {
     if end <= start {
         return Err(QueryError::IllegalArgs(format!(
@@ -52,35 +198,236 @@ pub async fn get_iron_mic_stats(
         )));
     }
 
-    let exclusion_clause = build_callsign_exclusion_clause();
-    let query = format!(
+    sqlx::query_as::<_, CallsignDurationStatsRecord>(
         r"
+        WITH rolled_up AS (
+            SELECT day
+            FROM daily_session_rollups
+            WHERE day >= $1 AND day + interval '24 hours' <= $2
+        ),
+        raw_windows AS (
+            SELECT unnest(
+                tstzmultirange(tstzrange($1, $2))
+                    - coalesce(range_agg(tstzrange(day, day + interval '24 hours')), '{}')
+            ) AS w
+            FROM rolled_up
+        ),
+        totals AS (
+            SELECT prefix, suffix, seconds_active::NUMERIC AS seconds, FALSE AS is_active
+            FROM callsign_daily_stats
+            WHERE day IN (SELECT day FROM rolled_up)
+            UNION ALL
+            SELECT
+                cs.prefix,
+                cs.suffix,
+                EXTRACT(EPOCH FROM (
+                    LEAST(COALESCE(cs.end_time, $3), upper(w.w)) - GREATEST(cs.start_time, lower(w.w))
+                )),
+                cs.end_time IS NULL
+            FROM raw_windows w
+            JOIN callsign_sessions cs ON cs.active_span && w.w
+        )
         SELECT
             prefix,
             suffix,
-            SUM(
-                EXTRACT(EPOCH FROM (
-                    LEAST(COALESCE(end_time, $3), $2) - GREATEST(start_time, $1)
-                ))
-            )::BIGINT AS duration_seconds,
-            BOOL_OR(end_time IS NULL) AS is_active
-        FROM callsign_sessions
-        WHERE start_time < $2
-          AND (end_time IS NULL OR end_time > $1){exclusion_clause}
+            SUM(seconds)::BIGINT AS duration_seconds,
+            BOOL_OR(is_active) AS is_active
+        FROM totals
+        WHERE NOT (prefix || '_' || suffix) LIKE ANY($5)
         GROUP BY prefix, suffix
         ORDER BY duration_seconds DESC
         LIMIT $4
-        "
-    );
+        ",
+    )
+    .bind(start)
+    .bind(end)
+    .bind(now)
+    .bind(limit)
+    .bind(&exclusions.callsign_patterns)
+    .fetch_all(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
 
-    sqlx::query_as::<_, CallsignDurationStatsRecord>(&query)
-        .bind(start)
-        .bind(end)
-        .bind(now)
-        .bind(limit)
-        .fetch_all(pool)
-        .await
-        .map_err(QueryError::Sql)
+#[derive(sqlx::FromRow)]
+pub struct PositionDurationStatsRecord {
+    pub position_id: String,
+    /// `None` for positions that aren't in `facility_positions`
+    pub position_name: Option<String>,
+    pub facility_id: Option<String>,
+    pub facility_name: Option<String>,
+    pub duration_seconds: i64,
+    pub is_active: bool,
+}
+
+/// Like [`get_iron_mic_stats`], but grouped by the position staffed rather than the callsign used
+pub async fn get_position_iron_mic_stats(
+    pool: &Pool<Postgres>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    now: DateTime<Utc>,
+    limit: i64,
+    exclusions: &IronMicExclusions,
+) -> Result<Vec<PositionDurationStatsRecord>, QueryError> {
+    if end <= start {
+        return Err(QueryError::IllegalArgs(format!(
+            "end must be greater than start. end = {end:?}. start = {start:?}"
+        )));
+    }
+
+    sqlx::query_as::<_, PositionDurationStatsRecord>(
+        r"
+        WITH rolled_up AS (
+            SELECT day
+            FROM daily_session_rollups
+            WHERE day >= $1 AND day + interval '24 hours' <= $2
+        ),
+        raw_windows AS (
+            SELECT unnest(
+                tstzmultirange(tstzrange($1, $2))
+                    - coalesce(range_agg(tstzrange(day, day + interval '24 hours')), '{}')
+            ) AS w
+            FROM rolled_up
+        ),
+        totals AS (
+            SELECT position_id, seconds_active::NUMERIC AS seconds, FALSE AS is_active
+            FROM position_daily_stats
+            WHERE day IN (SELECT day FROM rolled_up)
+            UNION ALL
+            SELECT
+                ps.position_id,
+                EXTRACT(EPOCH FROM (
+                    LEAST(COALESCE(ps.end_time, $3), upper(w.w)) - GREATEST(ps.start_time, lower(w.w))
+                )),
+                ps.end_time IS NULL
+            FROM raw_windows w
+            JOIN position_sessions ps ON ps.active_span && w.w
+        ),
+        top AS (
+            SELECT
+                position_id,
+                SUM(seconds)::BIGINT AS duration_seconds,
+                BOOL_OR(is_active) AS is_active
+            FROM totals
+            WHERE NOT position_id = ANY($5)
+            GROUP BY position_id
+            ORDER BY duration_seconds DESC
+            LIMIT $4
+        )
+        SELECT
+            t.position_id,
+            fp.name AS position_name,
+            f.id AS facility_id,
+            f.name AS facility_name,
+            t.duration_seconds,
+            t.is_active
+        FROM top t
+        LEFT JOIN facility_positions fp ON fp.id = t.position_id
+        LEFT JOIN facilities f ON f.id = fp.facility_id
+        ORDER BY t.duration_seconds DESC
+        ",
+    )
+    .bind(start)
+    .bind(end)
+    .bind(now)
+    .bind(limit)
+    .bind(&exclusions.position_ids)
+    .fetch_all(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+#[derive(sqlx::FromRow)]
+pub struct ControllerDurationStatsRecord {
+    pub cid: i32,
+    /// Name from the controller's most recent session in the interval
+    pub name: String,
+    pub duration_seconds: i64,
+    pub is_active: bool,
+}
+
+/// Like [`get_iron_mic_stats`], but grouped by controller CID. Observer sessions aren't counted,
+/// nor are sessions on excluded callsigns or primary positions. Callsigns are matched without
+/// their infix, as on the callsign leaderboard, so `SJU_APP` also excludes `SJU_1_APP`.
+pub async fn get_controller_iron_mic_stats(
+    pool: &Pool<Postgres>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    now: DateTime<Utc>,
+    limit: i64,
+    exclusions: &IronMicExclusions,
+) -> Result<Vec<ControllerDurationStatsRecord>, QueryError> {
+    if end <= start {
+        return Err(QueryError::IllegalArgs(format!(
+            "end must be greater than start. end = {end:?}. start = {start:?}"
+        )));
+    }
+
+    sqlx::query_as::<_, ControllerDurationStatsRecord>(
+        r"
+        WITH rolled_up AS (
+            SELECT day
+            FROM daily_session_rollups
+            WHERE day >= $1 AND day + interval '24 hours' <= $2
+        ),
+        raw_windows AS (
+            SELECT unnest(
+                tstzmultirange(tstzrange($1, $2))
+                    - coalesce(range_agg(tstzrange(day, day + interval '24 hours')), '{}')
+            ) AS w
+            FROM rolled_up
+        ),
+        totals AS (
+            SELECT
+                cid,
+                connected_callsign,
+                primary_position_id,
+                name,
+                last_start_time AS start_time,
+                seconds_active::NUMERIC AS seconds,
+                FALSE AS is_active
+            FROM controller_daily_stats
+            WHERE day IN (SELECT day FROM rolled_up)
+            UNION ALL
+            SELECT
+                cs.cid,
+                cs.connected_callsign,
+                cs.primary_position_id,
+                cs.name,
+                cs.start_time,
+                EXTRACT(EPOCH FROM (
+                    LEAST(COALESCE(cs.end_time, $3), upper(w.w)) - GREATEST(cs.start_time, lower(w.w))
+                )),
+                cs.end_time IS NULL
+            FROM raw_windows w
+            JOIN controller_sessions cs ON cs.active_span && w.w
+            WHERE NOT cs.is_observer
+        )
+        SELECT
+            cid,
+            (ARRAY_AGG(name ORDER BY start_time DESC))[1] AS name,
+            SUM(seconds)::BIGINT AS duration_seconds,
+            BOOL_OR(is_active) AS is_active
+        FROM totals
+        WHERE NOT cid = ANY($5)
+          AND NOT (split_part(connected_callsign, '_', 1) || '_' || split_part(connected_callsign, '_', -1))
+              LIKE ANY($6)
+          AND NOT primary_position_id = ANY($7)
+        GROUP BY cid
+        ORDER BY duration_seconds DESC
+        LIMIT $4
+        ",
+    )
+    .bind(start)
+    .bind(end)
+    .bind(now)
+    .bind(limit)
+    .bind(&exclusions.cids)
+    .bind(&exclusions.callsign_patterns)
+    .bind(&exclusions.position_ids)
+    .fetch_all(pool)
+    .await
+    .map_err(QueryError::Sql)
 }
 
 pub async fn get_latest_datafeed_updated_at(
@@ -97,6 +444,33 @@ pub async fn get_latest_datafeed_updated_at(
     .map_err(QueryError::Sql)
 }
 
+#[derive(sqlx::FromRow)]
+pub struct PipelineStatusRecord {
+    pub last_datafeed_updated_at: Option<DateTime<Utc>>,
+    pub last_processed_at: Option<DateTime<Utc>>,
+    pub queue_depth: i64,
+    pub oldest_queued_at: Option<DateTime<Utc>>,
+}
+
+/// How far the fetcher and processor have gotten: the latest stored datafeed, when a datafeed was
+/// last processed, and what's still waiting in `datafeed_queue`
+pub async fn get_pipeline_status(
+    pool: &Pool<Postgres>,
+) -> Result<PipelineStatusRecord, QueryError> {
+    sqlx::query_as::<_, PipelineStatusRecord>(
+        r"
+        SELECT
+            (SELECT max(updated_at) FROM datafeed_payloads) AS last_datafeed_updated_at,
+            (SELECT max(processed_at) FROM datafeed_messages) AS last_processed_at,
+            (SELECT count(*) FROM datafeed_queue) AS queue_depth,
+            (SELECT min(created_at) FROM datafeed_queue) AS oldest_queued_at
+        ",
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
 #[derive(sqlx::FromRow)]
 pub struct ActivitySnapshot {
     pub observed_at: DateTime<Utc>,
@@ -106,10 +480,12 @@ pub struct ActivitySnapshot {
 }
 
 /// Return activity snapshots between start/end, collapsing consecutive duplicates across any of the three counts.
+/// With an `artcc`, the snapshots are of that root ARTCC's activity only.
 pub async fn get_activity_snapshots(
     pool: &Pool<Postgres>,
     start: DateTime<Utc>,
     end: DateTime<Utc>,
+    artcc: Option<&str>,
 ) -> Result<Vec<ActivitySnapshot>, QueryError> {
     if end <= start {
         return Err(QueryError::IllegalArgs(format!(
@@ -117,8 +493,12 @@ pub async fn get_activity_snapshots(
         )));
     }
 
-    sqlx::query_as::<_, ActivitySnapshot>(
-        r#"
+    let (table, filter) = match artcc {
+        Some(_) => ("artcc_activity_stats", "AND artcc_id = $3"),
+        None => ("session_activity_stats", ""),
+    };
+    let query = format!(
+        r"
         SELECT observed_at, active_controllers, active_callsigns, active_positions
         FROM (
             SELECT
@@ -129,8 +509,8 @@ pub async fn get_activity_snapshots(
                 LAG(active_controllers) OVER (ORDER BY observed_at) AS prev_c,
                 LAG(active_callsigns) OVER (ORDER BY observed_at) AS prev_cs,
                 LAG(active_positions) OVER (ORDER BY observed_at) AS prev_p
-            FROM session_activity_stats
-            WHERE observed_at >= $1 AND observed_at <= $2
+            FROM {table}
+            WHERE observed_at >= $1 AND observed_at <= $2 {filter}
             ORDER BY observed_at
         ) s
         WHERE prev_c IS NULL
@@ -138,11 +518,798 @@ pub async fn get_activity_snapshots(
            OR active_callsigns <> prev_cs
            OR active_positions <> prev_p
         ORDER BY observed_at
-        "#,
+        "
+    );
+
+    let mut query = sqlx::query_as::<_, ActivitySnapshot>(&query)
+        .bind(start)
+        .bind(end);
+    if let Some(artcc) = artcc {
+        query = query.bind(artcc);
+    }
+    query.fetch_all(pool).await.map_err(QueryError::Sql)
+}
+
+/// Origin that activity buckets are aligned to, so bucket boundaries don't depend on the interval
+const BUCKET_ORIGIN: &str = "2000-01-01 00:00:00+00";
+
+/// Maximum number of session samples taken for a breakdown, however long the interval
+const MAX_BREAKDOWN_SAMPLES: i64 = 20_000;
+
+/// A rollup table that bucketed activity is aggregated from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivitySource {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl ActivitySource {
+    /// Picks the coarsest rollup that buckets of `width` can be built from, as long as it hasn't
+    /// been pruned for `start`. Returns `None` if there is none.
+    pub fn for_width(
+        width: TimeDelta,
+        start: DateTime<Utc>,
+        now: DateTime<Utc>,
+        retention: &MaintenanceConfig,
+    ) -> Option<Self> {
+        if width.num_seconds() % TimeDelta::days(1).num_seconds() == 0 {
+            Some(Self::Day)
+        } else if width.num_seconds() % TimeDelta::hours(1).num_seconds() == 0 {
+            Some(Self::Hour)
+        } else if retained(start, now, retention.activity_minute_retention_days) {
+            Some(Self::Minute)
+        } else {
+            None
+        }
+    }
+
+    const fn table(self) -> &'static str {
+        match self {
+            Self::Minute => "session_activity_stats_1m",
+            Self::Hour => "session_activity_stats_1h",
+            Self::Day => "session_activity_stats_1d",
+        }
+    }
+
+    const fn width(self) -> &'static str {
+        match self {
+            Self::Minute => "1 minute",
+            Self::Hour => "1 hour",
+            Self::Day => "24 hours",
+        }
+    }
+}
+
+/// Whether data kept for `days` still covers `start`, with an hour of slack for maintenance runs.
+/// Data without a retention is kept forever.
+pub fn retained(start: DateTime<Utc>, now: DateTime<Utc>, days: Option<u32>) -> bool {
+    days.is_none_or(|days| now - start <= TimeDelta::days(i64::from(days)) - TimeDelta::hours(1))
+}
+
+/// Picks a bucket width that keeps the number of points manageable for the length of the
+/// interval, or `None` for raw snapshots.
+pub fn default_bucket_width(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    now: DateTime<Utc>,
+    retention: &MaintenanceConfig,
+) -> Option<TimeDelta> {
+    let length = end - start;
+    if length <= TimeDelta::hours(6) && retained(start, now, retention.activity_raw_retention_days)
+    {
+        None
+    } else if length <= TimeDelta::days(2)
+        && retained(start, now, retention.activity_minute_retention_days)
+    {
+        Some(TimeDelta::minutes(1))
+    } else if length <= TimeDelta::days(60) {
+        Some(TimeDelta::hours(1))
+    } else {
+        Some(TimeDelta::days(1))
+    }
+}
+
+/// Active counts aggregated over a bucket
+#[derive(sqlx::FromRow)]
+pub struct ActivityBucket {
+    pub bucket: DateTime<Utc>,
+    pub min_controllers: i32,
+    pub max_controllers: i32,
+    pub avg_controllers: f64,
+    pub min_callsigns: i32,
+    pub max_callsigns: i32,
+    pub avg_callsigns: f64,
+    pub min_positions: i32,
+    pub max_positions: i32,
+    pub avg_positions: f64,
+}
+
+/// Returns activity between start/end aggregated into buckets of `width`, read from `source`.
+/// Snapshots that haven't been rolled up into `source` yet are read from the raw table.
+pub async fn get_activity_buckets(
+    pool: &Pool<Postgres>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    width: TimeDelta,
+    source: ActivitySource,
+) -> Result<Vec<ActivityBucket>, QueryError> {
+    if end <= start {
+        return Err(QueryError::IllegalArgs(format!(
+            "end must be greater than start. end = {end:?}. start = {start:?}"
+        )));
+    }
+
+    let (table, source_width) = (source.table(), source.width());
+    let query = format!(
+        r"
+        WITH source AS (
+            SELECT bucket AS observed_at, samples,
+                   min_controllers, max_controllers, avg_controllers * samples AS sum_controllers,
+                   min_callsigns, max_callsigns, avg_callsigns * samples AS sum_callsigns,
+                   min_positions, max_positions, avg_positions * samples AS sum_positions
+            FROM {table}
+            WHERE bucket >= date_bin($3, $1, '{BUCKET_ORIGIN}') AND bucket <= $2
+            UNION ALL
+            SELECT observed_at, 1,
+                   active_controllers, active_controllers, active_controllers,
+                   active_callsigns, active_callsigns, active_callsigns,
+                   active_positions, active_positions, active_positions
+            FROM session_activity_stats
+            WHERE observed_at >= coalesce(
+                    (SELECT max(bucket) FROM {table}) + interval '{source_width}',
+                    '-infinity'
+                )
+              AND observed_at >= date_bin($3, $1, '{BUCKET_ORIGIN}')
+              AND observed_at <= $2
+        )
+        SELECT
+            date_bin($3, observed_at, '{BUCKET_ORIGIN}') AS bucket,
+            min(min_controllers) AS min_controllers,
+            max(max_controllers) AS max_controllers,
+            sum(sum_controllers) / sum(samples) AS avg_controllers,
+            min(min_callsigns) AS min_callsigns,
+            max(max_callsigns) AS max_callsigns,
+            sum(sum_callsigns) / sum(samples) AS avg_callsigns,
+            min(min_positions) AS min_positions,
+            max(max_positions) AS max_positions,
+            sum(sum_positions) / sum(samples) AS avg_positions
+        FROM source
+        GROUP BY 1
+        ORDER BY 1
+        "
+    );
+
+    sqlx::query_as::<_, ActivityBucket>(&query)
+        .bind(start)
+        .bind(end)
+        .bind(width)
+        .fetch_all(pool)
+        .await
+        .map_err(QueryError::Sql)
+}
+
+/// Returns a root ARTCC's activity between start/end aggregated into buckets of `width`, read
+/// from its raw per-datafeed snapshots
+pub async fn get_artcc_activity_buckets(
+    pool: &Pool<Postgres>,
+    artcc: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    width: TimeDelta,
+) -> Result<Vec<ActivityBucket>, QueryError> {
+    if end <= start {
+        return Err(QueryError::IllegalArgs(format!(
+            "end must be greater than start. end = {end:?}. start = {start:?}"
+        )));
+    }
+
+    let query = format!(
+        r"
+        SELECT
+            date_bin($3, observed_at, '{BUCKET_ORIGIN}') AS bucket,
+            min(active_controllers) AS min_controllers,
+            max(active_controllers) AS max_controllers,
+            avg(active_controllers)::double precision AS avg_controllers,
+            min(active_callsigns) AS min_callsigns,
+            max(active_callsigns) AS max_callsigns,
+            avg(active_callsigns)::double precision AS avg_callsigns,
+            min(active_positions) AS min_positions,
+            max(active_positions) AS max_positions,
+            avg(active_positions)::double precision AS avg_positions
+        FROM artcc_activity_stats
+        WHERE artcc_id = $4
+          AND observed_at >= date_bin($3, $1, '{BUCKET_ORIGIN}')
+          AND observed_at <= $2
+        GROUP BY 1
+        ORDER BY 1
+        "
+    );
+
+    sqlx::query_as::<_, ActivityBucket>(&query)
+        .bind(start)
+        .bind(end)
+        .bind(width)
+        .bind(artcc)
+        .fetch_all(pool)
+        .await
+        .map_err(QueryError::Sql)
+}
+
+/// The root ARTCC of a position's facility, or `None` for positions that aren't in
+/// `facility_positions`
+pub async fn get_position_artcc(
+    pool: &Pool<Postgres>,
+    position_id: &str,
+) -> Result<Option<String>, QueryError> {
+    sqlx::query_scalar::<_, String>(
+        r"
+        SELECT f.root_artcc_id
+        FROM facility_positions fp
+        JOIN facilities f ON f.id = fp.facility_id
+        WHERE fp.id = $1
+        ",
     )
-    .bind(start)
-    .bind(end)
+    .bind(position_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// Whether `id` is a known root ARTCC
+pub async fn artcc_exists(pool: &Pool<Postgres>, id: &str) -> Result<bool, QueryError> {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM facilities WHERE id = $1 AND facility_type = 'Artcc')",
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// How activity is grouped for a breakdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Breakdown {
+    /// By the root ARTCC of each position's facility
+    Artcc,
+    /// By the type of each position's facility
+    FacilityType,
+}
+
+/// Active counts of one group aggregated over a bucket
+#[derive(sqlx::FromRow)]
+pub struct ActivityGroupBucket {
+    /// `None` for positions that aren't in `facility_positions`
+    pub key: Option<String>,
+    #[sqlx(flatten)]
+    pub bucket: ActivityBucket,
+}
+
+/// Returns activity between start/end per group, aggregated into buckets of `width`.
+///
+/// Counts are derived from session spans sampled within each bucket, so unlike
+/// [`get_activity_buckets`] they're available for the full history. Controllers and callsigns are
+/// grouped by the controller's primary position. With an `artcc`, only positions under that root
+/// ARTCC are counted.
+pub async fn get_activity_breakdown(
+    pool: &Pool<Postgres>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    width: TimeDelta,
+    breakdown: Breakdown,
+    artcc: Option<&str>,
+) -> Result<Vec<ActivityGroupBucket>, QueryError> {
+    if end <= start {
+        return Err(QueryError::IllegalArgs(format!(
+            "end must be greater than start. end = {end:?}. start = {start:?}"
+        )));
+    }
+
+    // Around a dozen samples per bucket, but no more than a minute apart
+    let step = [
+        width / 12,
+        TimeDelta::minutes(1),
+        (end - start) / i32::try_from(MAX_BREAKDOWN_SAMPLES).unwrap_or(i32::MAX),
+    ]
+    .into_iter()
+    .max()
+    .unwrap_or(width);
+
+    let key = match breakdown {
+        Breakdown::Artcc => "f.root_artcc_id",
+        Breakdown::FacilityType => "f.facility_type::text",
+    };
+    let filter = if artcc.is_some() {
+        "WHERE f.root_artcc_id = $5"
+    } else {
+        ""
+    };
+    let query = format!(
+        r"
+        WITH samples AS (
+            SELECT ts
+            FROM generate_series(date_bin($3, $1, '{BUCKET_ORIGIN}'), least($2, now()), $4) AS ts
+        ),
+        controllers AS (
+            SELECT s.ts, {key} AS key,
+                   count(*) AS controllers,
+                   count(DISTINCT cs.callsign_session_id) AS callsigns
+            FROM samples s
+            JOIN controller_sessions cs ON cs.active_span @> s.ts
+            LEFT JOIN facility_positions fp ON fp.id = cs.primary_position_id
+            LEFT JOIN facilities f ON f.id = fp.facility_id
+            {filter}
+            GROUP BY 1, 2
+        ),
+        positions AS (
+            SELECT s.ts, {key} AS key, count(*) AS positions
+            FROM samples s
+            JOIN position_sessions ps ON ps.active_span @> s.ts
+            LEFT JOIN facility_positions fp ON fp.id = ps.position_id
+            LEFT JOIN facilities f ON f.id = fp.facility_id
+            {filter}
+            GROUP BY 1, 2
+        ),
+        counts AS (
+            SELECT s.ts, k.key,
+                   coalesce(c.controllers, 0)::integer AS controllers,
+                   coalesce(c.callsigns, 0)::integer AS callsigns,
+                   coalesce(p.positions, 0)::integer AS positions
+            FROM samples s
+            CROSS JOIN (SELECT key FROM controllers UNION SELECT key FROM positions) k
+            LEFT JOIN controllers c ON c.ts = s.ts AND c.key IS NOT DISTINCT FROM k.key
+            LEFT JOIN positions p ON p.ts = s.ts AND p.key IS NOT DISTINCT FROM k.key
+        )
+        SELECT
+            key,
+            date_bin($3, ts, '{BUCKET_ORIGIN}') AS bucket,
+            min(controllers) AS min_controllers,
+            max(controllers) AS max_controllers,
+            avg(controllers)::double precision AS avg_controllers,
+            min(callsigns) AS min_callsigns,
+            max(callsigns) AS max_callsigns,
+            avg(callsigns)::double precision AS avg_callsigns,
+            min(positions) AS min_positions,
+            max(positions) AS max_positions,
+            avg(positions)::double precision AS avg_positions
+        FROM counts
+        GROUP BY 1, 2
+        ORDER BY 1, 2
+        "
+    );
+
+    let mut query = sqlx::query_as::<_, ActivityGroupBucket>(&query)
+        .bind(start)
+        .bind(end)
+        .bind(width)
+        .bind(step);
+    if let Some(artcc) = artcc {
+        query = query.bind(artcc);
+    }
+    query.fetch_all(pool).await.map_err(QueryError::Sql)
+}
+
+/// A role that can be granted to a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type, specta::Type)]
+#[serde(rename_all = "camelCase")]
+#[sqlx(type_name = "user_role", rename_all = "snake_case")]
+pub enum Role {
+    /// Can use every admin endpoint, including managing roles
+    Admin,
+    /// Can manage data for one root ARTCC
+    ArtccStaff,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct RoleGrantRecord {
+    pub id: Uuid,
+    pub cid: i32,
+    pub role: Role,
+    /// Set for [`Role::ArtccStaff`] only
+    pub artcc_id: Option<String>,
+    pub granted_by: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Roles granted to `cid`
+pub async fn get_user_roles(
+    pool: &Pool<Postgres>,
+    cid: i32,
+) -> Result<Vec<RoleGrantRecord>, QueryError> {
+    sqlx::query_as::<_, RoleGrantRecord>(
+        r"
+        SELECT id, cid, role, artcc_id, granted_by, created_at
+        FROM user_roles
+        WHERE cid = $1
+        ",
+    )
+    .bind(cid)
     .fetch_all(pool)
     .await
     .map_err(QueryError::Sql)
 }
+
+/// Every role grant, ordered by CID
+pub async fn get_role_grants(pool: &Pool<Postgres>) -> Result<Vec<RoleGrantRecord>, QueryError> {
+    sqlx::query_as::<_, RoleGrantRecord>(
+        r"
+        SELECT id, cid, role, artcc_id, granted_by, created_at
+        FROM user_roles
+        ORDER BY cid, role, artcc_id
+        ",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// Grants `role` to `cid`, or returns `None` if it's already granted
+pub async fn insert_role_grant(
+    pool: &Pool<Postgres>,
+    cid: i32,
+    role: Role,
+    artcc_id: Option<&str>,
+    granted_by: i32,
+) -> Result<Option<RoleGrantRecord>, QueryError> {
+    sqlx::query_as::<_, RoleGrantRecord>(
+        r"
+        INSERT INTO user_roles (cid, role, artcc_id, granted_by)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT ON CONSTRAINT user_roles_grant_key DO NOTHING
+        RETURNING id, cid, role, artcc_id, granted_by, created_at
+        ",
+    )
+    .bind(cid)
+    .bind(role)
+    .bind(artcc_id)
+    .bind(granted_by)
+    .fetch_optional(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// Returns whether a role grant with `id` existed
+pub async fn delete_role_grant(pool: &Pool<Postgres>, id: Uuid) -> Result<bool, QueryError> {
+    let result = sqlx::query("DELETE FROM user_roles WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(QueryError::Sql)?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// What an API key can be used for
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    sqlx::Type,
+    specta::Type,
+)]
+#[serde(rename_all = "camelCase")]
+#[sqlx(type_name = "api_key_scope", rename_all = "lowercase")]
+pub enum ApiKeyScope {
+    /// Routes that need a signed in user to read data
+    Read,
+    /// Admin and ARTCC staff routes, as far as the key's owner has those roles
+    Manage,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct ApiKeyRecord {
+    pub id: Uuid,
+    pub cid: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    pub rate_limit_per_minute: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// A user's API keys, including revoked ones, newest first
+pub async fn get_api_keys(
+    pool: &Pool<Postgres>,
+    cid: i32,
+) -> Result<Vec<ApiKeyRecord>, QueryError> {
+    sqlx::query_as::<_, ApiKeyRecord>(
+        r"
+        SELECT id, cid, name, prefix, scopes, rate_limit_per_minute, created_at, last_used_at,
+               revoked_at
+        FROM api_keys
+        WHERE cid = $1
+        ORDER BY created_at DESC
+        ",
+    )
+    .bind(cid)
+    .fetch_all(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+/// The unrevoked key with `key_hash`
+pub async fn get_api_key_by_hash(
+    pool: &Pool<Postgres>,
+    key_hash: &str,
+) -> Result<Option<ApiKeyRecord>, QueryError> {
+    sqlx::query_as::<_, ApiKeyRecord>(
+        r"
+        SELECT id, cid, name, prefix, scopes, rate_limit_per_minute, created_at, last_used_at,
+               revoked_at
+        FROM api_keys
+        WHERE key_hash = $1 AND revoked_at IS NULL
+        ",
+    )
+    .bind(key_hash)
+    .fetch_optional(pool)
+    .await
+    .map_err(QueryError::Sql)
+}
+
+pub struct NewApiKey<'a> {
+    pub cid: i32,
+    pub name: &'a str,
+    pub prefix: &'a str,
+    pub key_hash: &'a str,
+    pub scopes: &'a [ApiKeyScope],
+    pub rate_limit_per_minute: Option<i32>,
+}
+
+/// Adds a key, unless its owner already has `max_keys` unrevoked keys, in which case `None` is
+/// returned
+pub async fn insert_api_key(
+    pool: &Pool<Postgres>,
+    key: NewApiKey<'_>,
+    max_keys: i64,
+) -> Result<Option<ApiKeyRecord>, QueryError> {
+    let mut tx = pool.begin().await.map_err(QueryError::Sql)?;
+    // Serializes key creation per user, so concurrent requests can't go over the limit
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext('api_keys'), $1)")
+        .bind(key.cid)
+        .execute(&mut *tx)
+        .await
+        .map_err(QueryError::Sql)?;
+    let active: i64 =
+        sqlx::query_scalar("SELECT count(*) FROM api_keys WHERE cid = $1 AND revoked_at IS NULL")
+            .bind(key.cid)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(QueryError::Sql)?;
+    if active >= max_keys {
+        return Ok(None);
+    }
+
+    let record = sqlx::query_as::<_, ApiKeyRecord>(
+        r"
+        INSERT INTO api_keys (cid, name, prefix, key_hash, scopes, rate_limit_per_minute)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, cid, name, prefix, scopes, rate_limit_per_minute, created_at, last_used_at,
+                  revoked_at
+        ",
+    )
+    .bind(key.cid)
+    .bind(key.name)
+    .bind(key.prefix)
+    .bind(key.key_hash)
+    .bind(key.scopes)
+    .bind(key.rate_limit_per_minute)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(QueryError::Sql)?;
+    tx.commit().await.map_err(QueryError::Sql)?;
+    Ok(Some(record))
+}
+
+/// Records that a key was used. Callers only need to do this when `last_used_at` is stale, to
+/// avoid a write on every request.
+pub async fn touch_api_key(pool: &Pool<Postgres>, id: Uuid) -> Result<(), QueryError> {
+    sqlx::query("UPDATE api_keys SET last_used_at = now() WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(QueryError::Sql)?;
+    Ok(())
+}
+
+/// Revokes one of `cid`'s keys. Returns whether an unrevoked key with `id` belonged to them.
+pub async fn revoke_api_key(pool: &Pool<Postgres>, id: Uuid, cid: i32) -> Result<bool, QueryError> {
+    let result = sqlx::query(
+        "UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND cid = $2 AND revoked_at IS NULL",
+    )
+    .bind(id)
+    .bind(cid)
+    .execute(pool)
+    .await
+    .map_err(QueryError::Sql)?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::test_database::TestDatabase;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    async fn active_exclusions(pool: &Pool<Postgres>, now: DateTime<Utc>) -> IronMicExclusions {
+        IronMicExclusions::new(&get_active_exclusions(pool, now).await.unwrap())
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn callsign_exclusions_ignore_infixes_on_both_leaderboards() {
+        let db = TestDatabase::create().await;
+        let (start, end) = (at("2025-12-20T12:00:00Z"), at("2025-12-20T14:00:00Z"));
+        db.insert_session(1_000_001, "SJU_1_APP", start, end).await;
+        db.insert_session(1_000_002, "SJU_CTR", start, end).await;
+        // SJU_APP is excluded by the migration that added exclusions
+        let exclusions = active_exclusions(&db.pool, end).await;
+
+        let callsigns = get_iron_mic_stats(&db.pool, start, end, end, 10, &exclusions)
+            .await
+            .unwrap();
+        let callsigns: Vec<_> = callsigns
+            .iter()
+            .map(|c| format!("{}_{}", c.prefix, c.suffix))
+            .collect();
+        assert_eq!(callsigns, ["SJU_CTR"]);
+        let controllers = get_controller_iron_mic_stats(&db.pool, start, end, end, 10, &exclusions)
+            .await
+            .unwrap();
+        let cids: Vec<_> = controllers.iter().map(|c| c.cid).collect();
+        assert_eq!(cids, [1_000_002]);
+
+        // Wildcards match the same way
+        upsert_exclusion(
+            &db.pool,
+            ExclusionKind::Callsign,
+            "SJU_*CTR",
+            "Test",
+            None,
+            1_000_000,
+        )
+        .await
+        .unwrap();
+        let exclusions = active_exclusions(&db.pool, end).await;
+        let controllers = get_controller_iron_mic_stats(&db.pool, start, end, end, 10, &exclusions)
+            .await
+            .unwrap();
+        assert!(controllers.is_empty());
+    }
+
+    /// Every leaderboard row as `(key, seconds)`, sorted by key, without exclusions
+    async fn leaderboards(
+        pool: &Pool<Postgres>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Vec<(String, i64)> {
+        let exclusions = IronMicExclusions::default();
+        let mut rows = Vec::new();
+        for c in get_iron_mic_stats(pool, start, end, end, 100, &exclusions)
+            .await
+            .unwrap()
+        {
+            rows.push((format!("{}_{}", c.prefix, c.suffix), c.duration_seconds));
+        }
+        for p in get_position_iron_mic_stats(pool, start, end, end, 100, &exclusions)
+            .await
+            .unwrap()
+        {
+            rows.push((p.position_id, p.duration_seconds));
+        }
+        for c in get_controller_iron_mic_stats(pool, start, end, end, 100, &exclusions)
+            .await
+            .unwrap()
+        {
+            rows.push((c.cid.to_string(), c.duration_seconds));
+        }
+        rows.sort();
+        rows
+    }
+
+    /// Marks `day` rolled up with the totals of `(cid, callsign, last_start_time, seconds)`
+    /// sessions, the way the processor would
+    async fn insert_rollup(pool: &Pool<Postgres>, day: &str, totals: &[(i32, &str, &str, i64)]) {
+        let day = at(day);
+        sqlx::query("INSERT INTO daily_session_rollups (day) VALUES ($1)")
+            .bind(day)
+            .execute(pool)
+            .await
+            .unwrap();
+        for &(cid, callsign, last_start_time, seconds) in totals {
+            let (prefix, suffix) = callsign.split_once('_').unwrap();
+            sqlx::query(
+                r"
+                INSERT INTO callsign_daily_stats
+                VALUES ($1, $2, $3, $4, 1, 1)
+                ",
+            )
+            .bind(day)
+            .bind(prefix)
+            .bind(suffix)
+            .bind(seconds)
+            .execute(pool)
+            .await
+            .unwrap();
+            sqlx::query("INSERT INTO position_daily_stats VALUES ($1, $2, $3, 1, 1)")
+                .bind(day)
+                .bind(format!("position-{cid}"))
+                .bind(seconds)
+                .execute(pool)
+                .await
+                .unwrap();
+            sqlx::query(
+                r"
+                INSERT INTO controller_daily_stats
+                VALUES ($1, $2, $3, $4, 'Test Controller', $5, $6, 1, 1)
+                ",
+            )
+            .bind(day)
+            .bind(cid)
+            .bind(callsign)
+            .bind(format!("position-{cid}"))
+            .bind(at(last_start_time))
+            .bind(seconds)
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn leaderboards_read_whole_rolled_up_days_and_raw_partial_days() {
+        let db = TestDatabase::create().await;
+        let sessions = [
+            (1, "SJU_CTR", "2025-12-19T23:00:00Z", "2025-12-20T07:30:00Z"),
+            (2, "SJU_APP", "2025-12-20T10:00:00Z", "2025-12-21T03:00:00Z"),
+            (3, "SJU_TWR", "2025-12-21T22:00:00Z", "2025-12-22T19:00:00Z"),
+        ];
+        for (cid, callsign, start, end) in sessions {
+            db.insert_session(1_000_000 + cid, callsign, at(start), at(end))
+                .await;
+        }
+        let (start, end) = (at("2025-12-20T06:00:00Z"), at("2025-12-22T18:00:00Z"));
+        let raw = leaderboards(&db.pool, start, end).await;
+
+        let day = "2025-12-21T00:00:00Z";
+        insert_rollup(
+            &db.pool,
+            day,
+            &[
+                (1_000_002, "SJU_APP", "2025-12-20T10:00:00Z", 3 * 3600),
+                (1_000_003, "SJU_TWR", "2025-12-21T22:00:00Z", 2 * 3600),
+            ],
+        )
+        .await;
+        assert_eq!(leaderboards(&db.pool, start, end).await, raw);
+
+        // The rolled up day is read from the rollups rather than the sessions
+        sqlx::query("UPDATE callsign_daily_stats SET seconds_active = seconds_active + 60")
+            .execute(&db.pool)
+            .await
+            .unwrap();
+        let callsigns = get_iron_mic_stats(
+            &db.pool,
+            start,
+            end,
+            end,
+            100,
+            &IronMicExclusions::default(),
+        )
+        .await
+        .unwrap();
+        let twr = callsigns.iter().find(|c| c.suffix == "TWR").unwrap();
+        assert_eq!(twr.duration_seconds, 20 * 3600 + 60);
+    }
+}
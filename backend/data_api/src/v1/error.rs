@@ -1,10 +1,11 @@
 use crate::v1::db::queries::QueryError;
 use axum::Json;
-use axum::http::StatusCode;
+use axum::http::{HeaderValue, StatusCode, header};
 use axum::response::{IntoResponse, Response};
 use oauth2::basic::BasicErrorResponse;
 use oauth2::{HttpClientError, RequestTokenError};
 use serde::{Serialize, Serializer};
+use std::time::Duration;
 use thiserror::Error;
 use tracing::warn;
 
@@ -36,6 +37,16 @@ pub enum ApiError {
     ServiceUnavailable(String),
     #[error("authorization required")]
     AuthRequired,
+    #[error("forbidden")]
+    Forbidden,
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("invalid API key")]
+    InvalidApiKey,
+    #[error("rate limited, retry after {0:?}")]
+    RateLimited(Duration),
     #[error("unable to parse {0} as CID")]
     CidParseError(String),
 }
@@ -100,6 +111,25 @@ impl IntoResponse for ApiError {
                 ErrorMessage::from((StatusCode::UNAUTHORIZED, "authentication required"))
                     .into_response()
             }
+            ApiError::Forbidden => {
+                ErrorMessage::from((StatusCode::FORBIDDEN, "not allowed")).into_response()
+            }
+            ApiError::NotFound(e) => ErrorMessage::from((StatusCode::NOT_FOUND, e)).into_response(),
+            ApiError::Conflict(e) => ErrorMessage::from((StatusCode::CONFLICT, e)).into_response(),
+            ApiError::InvalidApiKey => {
+                ErrorMessage::from((StatusCode::UNAUTHORIZED, "invalid API key")).into_response()
+            }
+            ApiError::RateLimited(retry_after) => {
+                let mut response =
+                    ErrorMessage::from((StatusCode::TOO_MANY_REQUESTS, "too many requests"))
+                        .into_response();
+                // Whole seconds, rounded up so clients don't retry too early
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+                response
+            }
             ApiError::CidParseError(e) => {
                 warn!(cid_string = e, "unable to parse string as CID");
                 ErrorMessage::from((StatusCode::INTERNAL_SERVER_ERROR, "unable to parse CID"))
@@ -1,3 +1,4 @@
+use crate::cache::ResponseCache;
 use crate::state::{AppState, Db};
 use crate::v1::db::queries::get_latest_datafeed_updated_at;
 use crate::v1::error::ApiError;
@@ -16,10 +17,21 @@ impl FromRequestParts<AppState> for DatafeedMetadata {
         _parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let db = Db::from_ref(state);
         let now = Utc::now();
 
-        let last_updated = get_latest_datafeed_updated_at(&db.pool).await?;
+        // Known from the processor's notifications most of the time
+        let cache = ResponseCache::from_ref(state);
+        let last_updated = match cache.latest_datafeed() {
+            Some(latest) => Some(latest),
+            None => {
+                let db = Db::from_ref(state);
+                let latest = get_latest_datafeed_updated_at(&db.pool).await?;
+                if let Some(latest) = latest {
+                    cache.set_latest_datafeed(latest);
+                }
+                latest
+            }
+        };
 
         let last_datafeed_updated_at = last_updated
             .ok_or_else(|| ApiError::ServiceUnavailable("no datafeeds found".to_owned()))?;
@@ -1,2 +1,3 @@
 pub mod metadata;
-pub mod params;
\ No newline at end of file
+pub mod params;
+pub mod user;
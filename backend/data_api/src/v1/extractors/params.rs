@@ -1,9 +1,10 @@
+use crate::v1::db::queries::Breakdown;
 use crate::v1::error::ErrorMessage;
 use axum::{
     extract::{FromRequestParts, Query},
     http::{StatusCode, request::Parts},
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SubsecRound, TimeDelta, Utc};
 use humantime;
 use serde::Deserialize;
 use std::marker::PhantomData;
@@ -37,6 +38,8 @@ impl WithMaxDuration for OneMonth {
 /// 1) `start` is in the past
 /// 2) `start` is prior to `end`
 /// 3) the difference between `start` and `end` is not greater than the maximum allowed duration provided by the marker that impls [`WithMaxDuration`].
+///
+/// Both are truncated to whole seconds, so equivalent intervals share cached responses.
 #[derive(Debug, Clone)]
 pub struct MaxDurationInterval<T: WithMaxDuration> {
     pub start: DateTime<Utc>,
@@ -52,9 +55,11 @@ where
     type Rejection = ErrorMessage;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let Query(params) = Query::<RawInterval>::from_request_parts(parts, state)
+        let Query(mut params) = Query::<RawInterval>::from_request_parts(parts, state)
             .await
             .map_err(|e| ErrorMessage::from((StatusCode::BAD_REQUEST, e.to_string())))?;
+        params.start = params.start.trunc_subsecs(0);
+        params.end = params.end.trunc_subsecs(0);
 
         let max_duration = T::MAX_DURATION;
         let now = Utc::now();
@@ -89,3 +94,67 @@ where
         })
     }
 }
+
+/// Largest bucket width accepted for `resolution`
+const MAX_RESOLUTION: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+
+#[derive(Debug, Deserialize)]
+struct RawActivityOptions {
+    pub resolution: Option<String>,
+    pub breakdown: Option<Breakdown>,
+    pub artcc: Option<String>,
+}
+
+/// Optional query parameters of the activity timeseries:
+/// - `resolution`: bucket width as a whole number of minutes, hours or days, e.g. `15m`, `1h`, `1d`
+/// - `breakdown`: `artcc` or `facilityType`
+/// - `artcc`: only count activity under this root ARTCC, e.g. `ZOA`
+#[derive(Debug, Clone)]
+pub struct ActivityOptions {
+    pub resolution: Option<TimeDelta>,
+    pub breakdown: Option<Breakdown>,
+    pub artcc: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for ActivityOptions
+where
+    S: Send + Sync,
+{
+    type Rejection = ErrorMessage;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<RawActivityOptions>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| ErrorMessage::from((StatusCode::BAD_REQUEST, e.to_string())))?;
+
+        let resolution = params
+            .resolution
+            .map(|resolution| parse_resolution(&resolution))
+            .transpose()
+            .map_err(|e| ErrorMessage::from((StatusCode::BAD_REQUEST, e)))?;
+
+        Ok(Self {
+            resolution,
+            breakdown: params.breakdown,
+            artcc: params.artcc.map(|artcc| artcc.trim().to_uppercase()),
+        })
+    }
+}
+
+fn parse_resolution(resolution: &str) -> Result<TimeDelta, String> {
+    let duration = humantime::parse_duration(resolution)
+        .map_err(|e| format!("invalid resolution {resolution}: {e}"))?;
+    if duration.as_secs() % 60 != 0 || duration.subsec_nanos() != 0 || duration.is_zero() {
+        return Err(format!(
+            "resolution must be a whole number of minutes, but was {resolution}"
+        ));
+    }
+    if duration > MAX_RESOLUTION {
+        return Err(format!(
+            "resolution must be {} or less",
+            humantime::format_duration(MAX_RESOLUTION)
+        ));
+    }
+
+    TimeDelta::from_std(duration).map_err(|e| e.to_string())
+}
@@ -0,0 +1,174 @@
+use crate::state::{AppState, Db, Limiters};
+use crate::v1::api_key;
+use crate::v1::db::queries::{
+    ApiKeyRecord, ApiKeyScope, Role, get_api_key_by_hash, get_user_roles, touch_api_key,
+};
+use crate::v1::error::ApiError;
+use crate::v1::session;
+use axum::http::header;
+use axum::{extract::FromRef, extract::FromRequestParts, http::request::Parts};
+use chrono::{TimeDelta, Utc};
+use shared::ApiConfig;
+use shared::rate_limit::Quota;
+use tower_sessions::Session;
+use tracing::warn;
+
+/// How stale a key's `last_used_at` can get before it's updated, so busy keys don't write on every
+/// request
+const LAST_USED_RESOLUTION: TimeDelta = TimeDelta::minutes(1);
+
+/// The signed in user and the roles they hold, authenticated with an `Authorization: Bearer` API
+/// key or else the session. Rejects with [`ApiError::AuthRequired`] if no one is signed in, and
+/// with [`ApiError::InvalidApiKey`] or [`ApiError::RateLimited`] for bad or overused keys.
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+    pub cid: u32,
+    admin: bool,
+    /// Root ARTCCs the user is staff of
+    artccs: Vec<String>,
+    /// Scopes of the API key the request was made with, or `None` for sessions, which have every
+    /// scope
+    key_scopes: Option<Vec<ApiKeyScope>>,
+}
+
+impl CurrentUser {
+    /// Requires a session, or an API key with `scope`
+    pub fn require_scope(&self, scope: ApiKeyScope) -> Result<(), ApiError> {
+        match &self.key_scopes {
+            Some(scopes) if !scopes.contains(&scope) => Err(ApiError::Forbidden),
+            _ => Ok(()),
+        }
+    }
+
+    /// Requires a session rather than an API key, e.g. so keys can't be used to make more keys
+    pub fn require_session(&self) -> Result<(), ApiError> {
+        if self.key_scopes.is_some() {
+            Err(ApiError::Forbidden)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.admin
+    }
+
+    /// Root ARTCCs the user is staff of. Admins can manage every ARTCC without being listed.
+    pub fn artccs(&self) -> &[String] {
+        &self.artccs
+    }
+
+    pub fn require_admin(&self) -> Result<(), ApiError> {
+        if self.admin {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden)
+        }
+    }
+
+    /// Requires an admin or staff of any ARTCC
+    pub fn require_staff(&self) -> Result<(), ApiError> {
+        if self.admin || !self.artccs.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden)
+        }
+    }
+
+    /// Requires an admin or staff of `artcc`
+    pub fn require_artcc_staff(&self, artcc: &str) -> Result<(), ApiError> {
+        if self.admin || self.artccs.iter().any(|a| a == artcc) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden)
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for CurrentUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        // Already loaded by a middleware earlier in the request
+        if let Some(user) = parts.extensions.get::<Self>() {
+            return Ok(user.clone());
+        }
+
+        let db = Db::from_ref(state);
+        let api = ApiConfig::from_ref(state);
+        let (cid, key_scopes) = match bearer_token(parts) {
+            Some(token) => {
+                let key = authenticate_key(&db, &api, &Limiters::from_ref(state), token).await?;
+                let cid = u32::try_from(key.cid)
+                    .map_err(|_| ApiError::CidParseError(key.cid.to_string()))?;
+                (cid, Some(key.scopes))
+            }
+            None => {
+                let session = Session::from_request_parts(parts, state)
+                    .await
+                    .map_err(|_| ApiError::AuthRequired)?;
+                let Some(user) = session::get_user(&session).await? else {
+                    return Err(ApiError::AuthRequired);
+                };
+                (user.cid, None)
+            }
+        };
+
+        let mut current = Self {
+            cid,
+            admin: api.admin_cids.contains(&cid),
+            artccs: Vec::new(),
+            key_scopes,
+        };
+        let cid = i32::try_from(cid).map_err(|_| ApiError::CidParseError(cid.to_string()))?;
+        for grant in get_user_roles(&db.pool, cid).await? {
+            match (grant.role, grant.artcc_id) {
+                (Role::Admin, _) => current.admin = true,
+                (Role::ArtccStaff, Some(artcc)) => current.artccs.push(artcc),
+                (Role::ArtccStaff, None) => {}
+            }
+        }
+
+        parts.extensions.insert(current.clone());
+        Ok(current)
+    }
+}
+
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    let value = parts.headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    value.strip_prefix("Bearer ").map(str::trim)
+}
+
+/// Looks up an unrevoked key and takes a token from its rate limit
+async fn authenticate_key(
+    db: &Db,
+    api: &ApiConfig,
+    limiters: &Limiters,
+    token: &str,
+) -> Result<ApiKeyRecord, ApiError> {
+    let key = get_api_key_by_hash(&db.pool, &api_key::hash(token))
+        .await?
+        .ok_or(ApiError::InvalidApiKey)?;
+
+    let per_minute = key
+        .rate_limit_per_minute
+        .and_then(|limit| u32::try_from(limit).ok())
+        .unwrap_or(api.api_key_rate_limit_per_minute);
+    limiters
+        .api_keys
+        .check(key.id, Quota::per_minute(per_minute))
+        .map_err(ApiError::RateLimited)?;
+
+    let stale = key
+        .last_used_at
+        .is_none_or(|last_used| Utc::now() - last_used > LAST_USED_RESOLUTION);
+    if stale && let Err(e) = touch_api_key(&db.pool, key.id).await {
+        // Not worth failing the request over
+        warn!(name: "api_key.touch.failed", key_id = %key.id, error = ?e, "failed to record API key use");
+    }
+
+    Ok(key)
+}
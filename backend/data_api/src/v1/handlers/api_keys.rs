@@ -0,0 +1,175 @@
+use crate::state::Db;
+use crate::v1::api_key;
+use crate::v1::db::queries;
+use crate::v1::db::queries::{ApiKeyRecord, ApiKeyScope, NewApiKey, QueryError};
+use crate::v1::error::ApiError;
+use crate::v1::extractors::user::CurrentUser;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use shared::ApiConfig;
+use uuid::Uuid;
+
+/// Longest name a key can be given
+const MAX_NAME_LEN: usize = 100;
+
+#[derive(Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    /// The start of the key, to tell keys apart
+    pub prefix: String,
+    pub scopes: Vec<ApiKeyScope>,
+    /// Requests per minute; `None` for the default limit
+    pub rate_limit_per_minute: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl From<ApiKeyRecord> for ApiKeyResponse {
+    fn from(r: ApiKeyRecord) -> Self {
+        Self {
+            id: r.id,
+            name: r.name,
+            prefix: r.prefix,
+            scopes: r.scopes,
+            rate_limit_per_minute: r.rate_limit_per_minute,
+            created_at: r.created_at,
+            last_used_at: r.last_used_at,
+            revoked_at: r.revoked_at,
+        }
+    }
+}
+
+#[derive(Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct CreatedApiKeyResponse {
+    /// The key to send as `Authorization: Bearer <key>`. It isn't stored, so it can't be shown
+    /// again.
+    pub key: String,
+    pub details: ApiKeyResponse,
+}
+
+#[derive(Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateApiKeyRequest {
+    name: String,
+    scopes: Vec<ApiKeyScope>,
+    /// Only admins can go above the default limit
+    rate_limit_per_minute: Option<u32>,
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and the user's
+/// [`ApiKeyResponse`]s, including revoked ones, as JSON
+pub async fn list_api_keys(
+    State(db): State<Db>,
+    user: CurrentUser,
+) -> Result<impl IntoResponse, ApiError> {
+    let cid = i32::try_from(user.cid).map_err(|_| ApiError::CidParseError(user.cid.to_string()))?;
+    let keys = queries::get_api_keys(&db.pool, cid)
+        .await?
+        .into_iter()
+        .map(ApiKeyResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(keys)))
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::CREATED`] and a
+/// [`CreatedApiKeyResponse`] as JSON
+pub async fn create_api_key(
+    State(db): State<Db>,
+    State(api): State<ApiConfig>,
+    user: CurrentUser,
+    Json(request): Json<CreateApiKeyRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let cid = i32::try_from(user.cid).map_err(|_| ApiError::CidParseError(user.cid.to_string()))?;
+
+    let name = request.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(QueryError::IllegalArgs(format!(
+            "name must be between 1 and {MAX_NAME_LEN} characters"
+        ))
+        .into());
+    }
+
+    let mut scopes = request.scopes;
+    scopes.sort();
+    scopes.dedup();
+    if scopes.is_empty() {
+        return Err(QueryError::IllegalArgs("at least one scope is required".to_string()).into());
+    }
+    if scopes.contains(&ApiKeyScope::Manage) {
+        // The key could never be used for anything it manages
+        user.require_staff()?;
+    }
+
+    let rate_limit_per_minute = match request.rate_limit_per_minute {
+        None => None,
+        Some(0) => {
+            return Err(QueryError::IllegalArgs(
+                "rateLimitPerMinute must be at least 1".to_string(),
+            )
+            .into());
+        }
+        Some(limit) if limit > api.api_key_rate_limit_per_minute && !user.is_admin() => {
+            return Err(QueryError::IllegalArgs(format!(
+                "rateLimitPerMinute can be at most {}",
+                api.api_key_rate_limit_per_minute
+            ))
+            .into());
+        }
+        Some(limit) => Some(i32::try_from(limit).unwrap_or(i32::MAX)),
+    };
+
+    let generated = api_key::generate();
+    let key = queries::insert_api_key(
+        &db.pool,
+        NewApiKey {
+            cid,
+            name,
+            prefix: &generated.prefix,
+            key_hash: &generated.hash,
+            scopes: &scopes,
+            rate_limit_per_minute,
+        },
+        i64::from(api.max_api_keys_per_user),
+    )
+    .await?
+    .ok_or_else(|| {
+        ApiError::Conflict(format!(
+            "at most {} API keys can be active at once",
+            api.max_api_keys_per_user
+        ))
+    })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedApiKeyResponse {
+            key: generated.key,
+            details: ApiKeyResponse::from(key),
+        }),
+    ))
+}
+
+/// Revokes one of the user's keys. On success, returns a [`axum::response::Response`] with
+/// [`StatusCode::NO_CONTENT`]
+pub async fn revoke_api_key(
+    State(db): State<Db>,
+    user: CurrentUser,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let cid = i32::try_from(user.cid).map_err(|_| ApiError::CidParseError(user.cid.to_string()))?;
+    if !queries::revoke_api_key(&db.pool, id, cid).await? {
+        return Err(ApiError::NotFound(format!(
+            "no active API key with id {id}"
+        )));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
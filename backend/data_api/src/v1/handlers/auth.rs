@@ -1,5 +1,6 @@
 use crate::state::{HttpClients, Oauth};
 use crate::v1::error::ApiError;
+use crate::v1::extractors::user::CurrentUser;
 use crate::v1::session;
 use crate::v1::session::AuthUser;
 use axum::{
@@ -9,7 +10,7 @@ use axum::{
     response::{IntoResponse, Redirect},
 };
 use oauth2::{AuthorizationCode, CsrfToken, PkceCodeChallenge, Scope, TokenResponse};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use shared::vatsim;
 use tower_sessions::Session;
 
@@ -89,10 +90,29 @@ pub async fn logout(session: Session) -> Result<impl IntoResponse, ApiError> {
     Ok(())
 }
 
-pub async fn me(session: Session) -> Result<impl IntoResponse, ApiError> {
-    let user = session::get_user(&session).await?;
+#[derive(Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct MeResponse {
+    pub cid: u32,
+    pub admin: bool,
+    /// Root ARTCCs the user is staff of
+    pub artccs: Vec<String>,
+}
+
+pub async fn me(user: Result<CurrentUser, ApiError>) -> Result<impl IntoResponse, ApiError> {
     match user {
-        Some(u) => Ok((StatusCode::OK, Json(Some(u))).into_response()),
-        None => Ok((StatusCode::UNAUTHORIZED, Json(None::<AuthUser>)).into_response()),
+        Ok(u) => Ok((
+            StatusCode::OK,
+            Json(Some(MeResponse {
+                cid: u.cid,
+                admin: u.is_admin(),
+                artccs: u.artccs().to_vec(),
+            })),
+        )
+            .into_response()),
+        Err(ApiError::AuthRequired) => {
+            Ok((StatusCode::UNAUTHORIZED, Json(None::<MeResponse>)).into_response())
+        }
+        Err(e) => Err(e),
     }
 }
@@ -0,0 +1,173 @@
+use crate::cache::ResponseCache;
+use crate::state::Db;
+use crate::v1::db::queries;
+use crate::v1::db::queries::{ExclusionKind, ExclusionRecord, QueryError};
+use crate::v1::error::ApiError;
+use crate::v1::extractors::user::CurrentUser;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct ExclusionResponse {
+    pub id: Uuid,
+    pub kind: ExclusionKind,
+    pub value: String,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// `None` for exclusions added by migrations
+    pub created_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ExclusionRecord> for ExclusionResponse {
+    fn from(r: ExclusionRecord) -> Self {
+        Self {
+            id: r.id,
+            kind: r.kind,
+            value: r.value,
+            reason: r.reason,
+            expires_at: r.expires_at,
+            created_by: r.created_by,
+            created_at: r.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateExclusionRequest {
+    kind: ExclusionKind,
+    value: String,
+    reason: String,
+    /// Applies until deleted if unset
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// Normalizes an exclusion's value the way the datafeed reports it, e.g. callsigns in upper case
+fn normalize_value(kind: ExclusionKind, value: &str) -> Result<String, QueryError> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(QueryError::IllegalArgs(
+            "value must not be empty".to_string(),
+        ));
+    }
+
+    match kind {
+        ExclusionKind::Callsign => {
+            let value = value.to_ascii_uppercase();
+            if !value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '*')
+            {
+                return Err(QueryError::IllegalArgs(format!(
+                    "callsign pattern {value} may only contain letters, digits, _ and *"
+                )));
+            }
+            Ok(value)
+        }
+        ExclusionKind::Position => Ok(value.to_string()),
+        ExclusionKind::Cid => match value.parse::<u32>() {
+            Ok(cid) if i32::try_from(cid).is_ok() => Ok(cid.to_string()),
+            _ => Err(QueryError::IllegalArgs(format!(
+                "{value} is not a valid CID"
+            ))),
+        },
+    }
+}
+
+/// Staff can manage exclusions of positions in their ARTCC, anything else needs an admin
+async fn require_can_manage(
+    db: &Db,
+    user: &CurrentUser,
+    kind: ExclusionKind,
+    value: &str,
+) -> Result<(), ApiError> {
+    if user.is_admin() {
+        return Ok(());
+    }
+    match kind {
+        ExclusionKind::Position => match queries::get_position_artcc(&db.pool, value).await? {
+            Some(artcc) => user.require_artcc_staff(&artcc),
+            None => Err(ApiError::Forbidden),
+        },
+        ExclusionKind::Callsign | ExclusionKind::Cid => user.require_admin(),
+    }
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and every
+/// [`ExclusionResponse`], including expired ones, as JSON
+pub async fn list_exclusions(State(db): State<Db>) -> Result<impl IntoResponse, ApiError> {
+    let exclusions = queries::get_exclusions(&db.pool)
+        .await?
+        .into_iter()
+        .map(ExclusionResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(exclusions)))
+}
+
+/// Adds an exclusion, or updates the reason and expiry of an existing one with the same value.
+/// ARTCC staff can only exclude positions in their ARTCC.
+///
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::CREATED`] and the
+/// [`ExclusionResponse`] as JSON
+pub async fn create_exclusion(
+    State(db): State<Db>,
+    State(cache): State<ResponseCache>,
+    user: CurrentUser,
+    Json(request): Json<CreateExclusionRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let created_by =
+        i32::try_from(user.cid).map_err(|_| ApiError::CidParseError(user.cid.to_string()))?;
+
+    let value = normalize_value(request.kind, &request.value)?;
+    require_can_manage(&db, &user, request.kind, &value).await?;
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return Err(QueryError::IllegalArgs("reason must not be empty".to_string()).into());
+    }
+
+    let exclusion = queries::upsert_exclusion(
+        &db.pool,
+        request.kind,
+        &value,
+        reason,
+        request.expires_at,
+        created_by,
+    )
+    .await?;
+    // Cached leaderboards were computed with the old exclusions
+    cache.clear();
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ExclusionResponse::from(exclusion)),
+    ))
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::NO_CONTENT`]
+pub async fn delete_exclusion(
+    State(db): State<Db>,
+    State(cache): State<ResponseCache>,
+    user: CurrentUser,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let not_found = || ApiError::NotFound(format!("no exclusion with id {id}"));
+    let exclusion = queries::get_exclusion(&db.pool, id)
+        .await?
+        .ok_or_else(not_found)?;
+    require_can_manage(&db, &user, exclusion.kind, &exclusion.value).await?;
+
+    if !queries::delete_exclusion(&db.pool, id).await? {
+        return Err(not_found());
+    }
+    cache.clear();
+
+    Ok(StatusCode::NO_CONTENT)
+}
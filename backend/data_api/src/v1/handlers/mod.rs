@@ -1,3 +1,7 @@
 pub mod active_sessions;
+pub mod api_keys;
 pub mod auth;
+pub mod exclusions;
+pub mod roles;
 pub mod stats;
+pub mod system;
@@ -0,0 +1,110 @@
+use crate::state::Db;
+use crate::v1::db::queries;
+use crate::v1::db::queries::{QueryError, Role, RoleGrantRecord};
+use crate::v1::error::ApiError;
+use crate::v1::extractors::user::CurrentUser;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct RoleGrantResponse {
+    pub id: Uuid,
+    pub cid: i32,
+    pub role: Role,
+    /// Set for ARTCC staff only
+    pub artcc_id: Option<String>,
+    pub granted_by: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<RoleGrantRecord> for RoleGrantResponse {
+    fn from(r: RoleGrantRecord) -> Self {
+        Self {
+            id: r.id,
+            cid: r.cid,
+            role: r.role,
+            artcc_id: r.artcc_id,
+            granted_by: r.granted_by,
+            created_at: r.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+pub struct GrantRoleRequest {
+    cid: u32,
+    role: Role,
+    /// Required for ARTCC staff, not allowed for admins
+    artcc_id: Option<String>,
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and every
+/// [`RoleGrantResponse`] as JSON. Admins from the config aren't listed.
+pub async fn list_role_grants(State(db): State<Db>) -> Result<impl IntoResponse, ApiError> {
+    let grants = queries::get_role_grants(&db.pool)
+        .await?
+        .into_iter()
+        .map(RoleGrantResponse::from)
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(grants)))
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::CREATED`] and the
+/// [`RoleGrantResponse`] as JSON
+pub async fn grant_role(
+    State(db): State<Db>,
+    user: CurrentUser,
+    Json(request): Json<GrantRoleRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let granted_by =
+        i32::try_from(user.cid).map_err(|_| ApiError::CidParseError(user.cid.to_string()))?;
+    let cid = i32::try_from(request.cid)
+        .map_err(|_| QueryError::IllegalArgs(format!("{} is not a valid CID", request.cid)))?;
+
+    let artcc_id = match (request.role, request.artcc_id.as_deref()) {
+        (Role::Admin, None) => None,
+        (Role::Admin, Some(_)) => {
+            return Err(QueryError::IllegalArgs(
+                "admin roles can't be scoped to an ARTCC".to_string(),
+            )
+            .into());
+        }
+        (Role::ArtccStaff, Some(artcc)) => {
+            if !queries::artcc_exists(&db.pool, artcc).await? {
+                return Err(QueryError::IllegalArgs(format!("unknown ARTCC {artcc}")).into());
+            }
+            Some(artcc)
+        }
+        (Role::ArtccStaff, None) => {
+            return Err(
+                QueryError::IllegalArgs("ARTCC staff roles need an artccId".to_string()).into(),
+            );
+        }
+    };
+
+    let grant = queries::insert_role_grant(&db.pool, cid, request.role, artcc_id, granted_by)
+        .await?
+        .ok_or_else(|| ApiError::Conflict(format!("{cid} already has that role")))?;
+
+    Ok((StatusCode::CREATED, Json(RoleGrantResponse::from(grant))))
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::NO_CONTENT`]
+pub async fn revoke_role(
+    State(db): State<Db>,
+    Path(id): Path<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    if !queries::delete_role_grant(&db.pool, id).await? {
+        return Err(ApiError::NotFound(format!("no role grant with id {id}")));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,17 +1,26 @@
+use crate::cache::{CacheKey, PerRequest, ResponseCache};
 use crate::state::Db;
 use crate::v1::db::queries;
+use crate::v1::db::queries::{
+    ActivityBucket, ActivitySource, ExclusionKind, IronMicExclusions, QueryError,
+    default_bucket_width, retained,
+};
 use crate::v1::error::ApiError;
 use crate::v1::extractors::metadata::DatafeedMetadata;
-use crate::v1::extractors::params::{MaxDurationInterval, OneMonth, OneYear};
+use crate::v1::extractors::params::{ActivityOptions, MaxDurationInterval, OneMonth, OneYear};
 use axum::Json;
 use axum::extract::State;
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use serde::Serialize;
+use shared::MaintenanceConfig;
 use std::cmp;
 
-#[derive(Serialize, specta::Type)]
+/// Number of entries returned by each Iron Mic leaderboard
+const IRON_MIC_LIMIT: i64 = 150;
+
+#[derive(Clone, Serialize, specta::Type)]
 #[serde(rename_all = "camelCase")]
 struct IronMicResponse {
     pub requested_at: DateTime<Utc>,
@@ -20,9 +29,11 @@ struct IronMicResponse {
     pub end: DateTime<Utc>,
     pub actual_elapsed_duration_seconds: i64,
     pub callsigns: Vec<CallsignDurationStats>,
+    /// Exclusions that were applied
+    pub exclusions: Vec<IronMicExclusion>,
 }
 
-#[derive(Serialize, specta::Type)]
+#[derive(Clone, Serialize, specta::Type)]
 #[serde(rename_all = "camelCase")]
 struct CallsignDurationStats {
     pub prefix: String,
@@ -31,50 +42,342 @@ struct CallsignDurationStats {
     pub is_active: Option<bool>,
 }
 
+#[derive(Clone, Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct PositionIronMicResponse {
+    pub requested_at: DateTime<Utc>,
+    pub last_datafeed_updated_at: DateTime<Utc>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub actual_elapsed_duration_seconds: i64,
+    pub positions: Vec<PositionDurationStats>,
+    /// Exclusions that were applied
+    pub exclusions: Vec<IronMicExclusion>,
+}
+
+#[derive(Clone, Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct PositionDurationStats {
+    pub position_id: String,
+    /// `None` for positions that aren't known from any facility
+    pub position_name: Option<String>,
+    pub facility_id: Option<String>,
+    pub facility_name: Option<String>,
+    pub duration_seconds: i64,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Clone, Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct ControllerIronMicResponse {
+    pub requested_at: DateTime<Utc>,
+    pub last_datafeed_updated_at: DateTime<Utc>,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub actual_elapsed_duration_seconds: i64,
+    pub controllers: Vec<ControllerDurationStats>,
+    /// Exclusions that were applied
+    pub exclusions: Vec<IronMicExclusion>,
+}
+
+#[derive(Clone, Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct ControllerDurationStats {
+    pub cid: i32,
+    pub name: String,
+    pub duration_seconds: i64,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Clone, Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct IronMicExclusion {
+    pub kind: ExclusionKind,
+    pub value: String,
+    pub reason: String,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Exclusions active at the time of the request, to bind to a leaderboard query, along with those
+/// of `kinds` to report in its response
+async fn load_exclusions(
+    db: &Db,
+    meta: &DatafeedMetadata,
+    kinds: &[ExclusionKind],
+) -> Result<(IronMicExclusions, Vec<IronMicExclusion>), ApiError> {
+    let records = queries::get_active_exclusions(&db.pool, meta.requested_at).await?;
+    let exclusions = IronMicExclusions::new(&records);
+    let reported = records
+        .into_iter()
+        .filter(|r| kinds.contains(&r.kind))
+        .map(|r| IronMicExclusion {
+            kind: r.kind,
+            value: r.value,
+            reason: r.reason,
+            expires_at: r.expires_at,
+        })
+        .collect();
+    Ok((exclusions, reported))
+}
+
+/// When the first of the applied exclusions expires, changing the leaderboard
+fn next_expiry(exclusions: &[IronMicExclusion]) -> Option<DateTime<Utc>> {
+    exclusions.iter().filter_map(|e| e.expires_at).min()
+}
+
+/// Seconds of the interval that have elapsed so far, which durations can be compared against
+fn elapsed_seconds(requested_at: DateTime<Utc>, start: DateTime<Utc>, end: DateTime<Utc>) -> i64 {
+    (cmp::min(end, requested_at) - start).num_seconds()
+}
+
+/// Whether a session is still open only means something while the interval hasn't ended yet
+fn active_in_interval(
+    requested_at: DateTime<Utc>,
+    end: DateTime<Utc>,
+    is_active: Option<bool>,
+) -> Option<bool> {
+    if requested_at > end { None } else { is_active }
+}
+
+impl PerRequest for IronMicResponse {
+    fn set_requested_at(&mut self, requested_at: DateTime<Utc>) {
+        self.requested_at = requested_at;
+        self.actual_elapsed_duration_seconds = elapsed_seconds(requested_at, self.start, self.end);
+        for callsign in &mut self.callsigns {
+            callsign.is_active = active_in_interval(requested_at, self.end, callsign.is_active);
+        }
+    }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        next_expiry(&self.exclusions)
+    }
+}
+
+impl PerRequest for PositionIronMicResponse {
+    fn set_requested_at(&mut self, requested_at: DateTime<Utc>) {
+        self.requested_at = requested_at;
+        self.actual_elapsed_duration_seconds = elapsed_seconds(requested_at, self.start, self.end);
+        for position in &mut self.positions {
+            position.is_active = active_in_interval(requested_at, self.end, position.is_active);
+        }
+    }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        next_expiry(&self.exclusions)
+    }
+}
+
+impl PerRequest for ControllerIronMicResponse {
+    fn set_requested_at(&mut self, requested_at: DateTime<Utc>) {
+        self.requested_at = requested_at;
+        self.actual_elapsed_duration_seconds = elapsed_seconds(requested_at, self.start, self.end);
+        for controller in &mut self.controllers {
+            controller.is_active = active_in_interval(requested_at, self.end, controller.is_active);
+        }
+    }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        next_expiry(&self.exclusions)
+    }
+}
+
 /// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and [`IronMicResponse`] as JSON
+///
+/// Cached until the next datafeed is processed or an applied exclusion expires. Only
+/// `requestedAt`, the elapsed duration and whether sessions are active are updated on each
+/// request, so durations of active sessions are as of when the response was cached.
 pub async fn get_iron_mic_stats(
     State(db): State<Db>,
+    State(cache): State<ResponseCache>,
     meta: DatafeedMetadata,
     interval: MaxDurationInterval<OneYear>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let stats = queries::get_iron_mic_stats(
-        &db.pool,
-        interval.start,
-        interval.end,
-        meta.requested_at,
-        150,
-    )
-    .await?;
-
-    let uptime_denominator =
-        (cmp::min(interval.end, meta.requested_at) - interval.start).num_seconds();
-    let durations = stats
-        .into_iter()
-        .map(|s| CallsignDurationStats {
-            prefix: s.prefix,
-            suffix: s.suffix,
-            duration_seconds: s.duration_seconds,
-            is_active: if meta.requested_at > interval.end {
-                None
-            } else {
-                Some(s.is_active)
+    let key = CacheKey::new("callsigns", interval.start, interval.end);
+    cache
+        .get_or_insert_with(
+            key,
+            meta.last_datafeed_updated_at,
+            meta.requested_at,
+            || async {
+                let (exclusions, reported) =
+                    load_exclusions(&db, &meta, &[ExclusionKind::Callsign]).await?;
+                let stats = queries::get_iron_mic_stats(
+                    &db.pool,
+                    interval.start,
+                    interval.end,
+                    meta.requested_at,
+                    IRON_MIC_LIMIT,
+                    &exclusions,
+                )
+                .await?;
+
+                let uptime_denominator =
+                    elapsed_seconds(meta.requested_at, interval.start, interval.end);
+                let durations = stats
+                    .into_iter()
+                    .map(|s| CallsignDurationStats {
+                        prefix: s.prefix,
+                        suffix: s.suffix,
+                        duration_seconds: s.duration_seconds,
+                        is_active: active_in_interval(
+                            meta.requested_at,
+                            interval.end,
+                            Some(s.is_active),
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(IronMicResponse {
+                    requested_at: meta.requested_at,
+                    last_datafeed_updated_at: meta.last_datafeed_updated_at,
+                    start: interval.start,
+                    end: interval.end,
+                    actual_elapsed_duration_seconds: uptime_denominator,
+                    callsigns: durations,
+                    exclusions: reported,
+                })
             },
-        })
-        .collect::<Vec<_>>();
+        )
+        .await
+}
 
-    Ok((
-        StatusCode::OK,
-        Json(IronMicResponse {
-            requested_at: meta.requested_at,
-            last_datafeed_updated_at: meta.last_datafeed_updated_at,
-            start: interval.start,
-            end: interval.end,
-            actual_elapsed_duration_seconds: uptime_denominator,
-            callsigns: durations,
-        }),
-    ))
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and [`PositionIronMicResponse`] as JSON
+///
+/// Cached until the next datafeed is processed or an applied exclusion expires. Only
+/// `requestedAt`, the elapsed duration and whether sessions are active are updated on each
+/// request, so durations of active sessions are as of when the response was cached.
+pub async fn get_position_iron_mic_stats(
+    State(db): State<Db>,
+    State(cache): State<ResponseCache>,
+    meta: DatafeedMetadata,
+    interval: MaxDurationInterval<OneYear>,
+) -> Result<impl IntoResponse, ApiError> {
+    let key = CacheKey::new("positions", interval.start, interval.end);
+    cache
+        .get_or_insert_with(
+            key,
+            meta.last_datafeed_updated_at,
+            meta.requested_at,
+            || async {
+                let (exclusions, reported) =
+                    load_exclusions(&db, &meta, &[ExclusionKind::Position]).await?;
+                let stats = queries::get_position_iron_mic_stats(
+                    &db.pool,
+                    interval.start,
+                    interval.end,
+                    meta.requested_at,
+                    IRON_MIC_LIMIT,
+                    &exclusions,
+                )
+                .await?;
+
+                let positions = stats
+                    .into_iter()
+                    .map(|s| PositionDurationStats {
+                        position_id: s.position_id,
+                        position_name: s.position_name,
+                        facility_id: s.facility_id,
+                        facility_name: s.facility_name,
+                        duration_seconds: s.duration_seconds,
+                        is_active: active_in_interval(
+                            meta.requested_at,
+                            interval.end,
+                            Some(s.is_active),
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(PositionIronMicResponse {
+                    requested_at: meta.requested_at,
+                    last_datafeed_updated_at: meta.last_datafeed_updated_at,
+                    start: interval.start,
+                    end: interval.end,
+                    actual_elapsed_duration_seconds: elapsed_seconds(
+                        meta.requested_at,
+                        interval.start,
+                        interval.end,
+                    ),
+                    positions,
+                    exclusions: reported,
+                })
+            },
+        )
+        .await
 }
 
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and [`ControllerIronMicResponse`] as JSON
+///
+/// Cached until the next datafeed is processed or an applied exclusion expires. Only
+/// `requestedAt`, the elapsed duration and whether sessions are active are updated on each
+/// request, so durations of active sessions are as of when the response was cached.
+pub async fn get_controller_iron_mic_stats(
+    State(db): State<Db>,
+    State(cache): State<ResponseCache>,
+    meta: DatafeedMetadata,
+    interval: MaxDurationInterval<OneYear>,
+) -> Result<impl IntoResponse, ApiError> {
+    let key = CacheKey::new("controllers", interval.start, interval.end);
+    cache
+        .get_or_insert_with(
+            key,
+            meta.last_datafeed_updated_at,
+            meta.requested_at,
+            || async {
+                let (exclusions, reported) = load_exclusions(
+                    &db,
+                    &meta,
+                    &[
+                        ExclusionKind::Callsign,
+                        ExclusionKind::Position,
+                        ExclusionKind::Cid,
+                    ],
+                )
+                .await?;
+                let stats = queries::get_controller_iron_mic_stats(
+                    &db.pool,
+                    interval.start,
+                    interval.end,
+                    meta.requested_at,
+                    IRON_MIC_LIMIT,
+                    &exclusions,
+                )
+                .await?;
+
+                let controllers = stats
+                    .into_iter()
+                    .map(|s| ControllerDurationStats {
+                        cid: s.cid,
+                        name: s.name,
+                        duration_seconds: s.duration_seconds,
+                        is_active: active_in_interval(
+                            meta.requested_at,
+                            interval.end,
+                            Some(s.is_active),
+                        ),
+                    })
+                    .collect::<Vec<_>>();
+
+                Ok(ControllerIronMicResponse {
+                    requested_at: meta.requested_at,
+                    last_datafeed_updated_at: meta.last_datafeed_updated_at,
+                    start: interval.start,
+                    end: interval.end,
+                    actual_elapsed_duration_seconds: elapsed_seconds(
+                        meta.requested_at,
+                        interval.start,
+                        interval.end,
+                    ),
+                    controllers,
+                    exclusions: reported,
+                })
+            },
+        )
+        .await
+}
+
+/// Upper bound on the number of buckets a single timeseries request can return
+const MAX_ACTIVITY_BUCKETS: i64 = 10_000;
+
 #[derive(Serialize, specta::Type)]
 #[serde(rename_all = "camelCase")]
 struct ActivityTimeSeriesResponse {
@@ -82,42 +385,219 @@ struct ActivityTimeSeriesResponse {
     last_datafeed_updated_at: DateTime<Utc>,
     start: DateTime<Utc>,
     end: DateTime<Utc>,
+    /// Root ARTCC the counts are limited to, if one was requested
+    artcc: Option<String>,
+    /// `raw`, or the width of each bucket, e.g. `15m`
+    resolution: String,
     observations: Vec<DateTime<Utc>>,
+    /// Raw counts, or the peak count in each bucket
     active_controllers: Vec<i32>,
     active_callsigns: Vec<i32>,
     active_positions: Vec<i32>,
+    /// Lowest count in each bucket; `None` for raw snapshots
+    min: Option<ActivitySeries<i32>>,
+    /// Average count in each bucket; `None` for raw snapshots
+    avg: Option<ActivitySeries<f64>>,
+    /// Per-group timeseries over the same buckets, if a breakdown was requested
+    breakdown: Option<Vec<ActivityGroupSeries>>,
+}
+
+#[derive(Serialize, specta::Type, Default)]
+#[serde(rename_all = "camelCase")]
+struct ActivitySeries<T> {
+    active_controllers: Vec<T>,
+    active_callsigns: Vec<T>,
+    active_positions: Vec<T>,
+}
+
+#[derive(Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct ActivityGroupSeries {
+    /// ARTCC id or facility type; `None` for positions without a known facility
+    key: Option<String>,
+    observations: Vec<DateTime<Utc>>,
+    max: ActivitySeries<i32>,
+    min: ActivitySeries<i32>,
+    avg: ActivitySeries<f64>,
+}
+
+/// Min, max and avg series of a sequence of buckets
+#[derive(Default)]
+struct BucketSeries {
+    observations: Vec<DateTime<Utc>>,
+    max: ActivitySeries<i32>,
+    min: ActivitySeries<i32>,
+    avg: ActivitySeries<f64>,
+}
+
+impl BucketSeries {
+    fn push(&mut self, b: ActivityBucket) {
+        self.observations.push(b.bucket);
+        self.max.active_controllers.push(b.max_controllers);
+        self.max.active_callsigns.push(b.max_callsigns);
+        self.max.active_positions.push(b.max_positions);
+        self.min.active_controllers.push(b.min_controllers);
+        self.min.active_callsigns.push(b.min_callsigns);
+        self.min.active_positions.push(b.min_positions);
+        self.avg.active_controllers.push(b.avg_controllers);
+        self.avg.active_callsigns.push(b.avg_callsigns);
+        self.avg.active_positions.push(b.avg_positions);
+    }
+}
+
+/// Formats a bucket width in the largest unit it's a whole number of, e.g. `15m` or `2h`
+fn format_resolution(width: TimeDelta) -> String {
+    let minutes = width.num_minutes();
+    if minutes % (60 * 24) == 0 {
+        format!("{}d", minutes / (60 * 24))
+    } else if minutes % 60 == 0 {
+        format!("{}h", minutes / 60)
+    } else {
+        format!("{minutes}m")
+    }
 }
 
 /// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and [`ActivityTimeSeriesResponse`] as JSON
+///
+/// Without a `resolution`, one is chosen from the length of the interval: raw snapshots for short
+/// intervals, and minute, hour or day buckets for longer ones. With an `artcc`, counts are limited
+/// to positions under that root ARTCC.
 pub async fn get_activity_timeseries(
     State(db): State<Db>,
+    State(maintenance): State<MaintenanceConfig>,
     meta: DatafeedMetadata,
     interval: MaxDurationInterval<OneMonth>,
+    options: ActivityOptions,
 ) -> Result<impl IntoResponse, ApiError> {
-    let points = queries::get_activity_snapshots(&db.pool, interval.start, interval.end).await?;
-    let mut observations = Vec::with_capacity(points.len());
-    let mut active_controllers = Vec::with_capacity(points.len());
-    let mut active_callsigns = Vec::with_capacity(points.len());
-    let mut active_positions = Vec::with_capacity(points.len());
-
-    for p in points {
-        observations.push(p.observed_at);
-        active_controllers.push(p.active_controllers);
-        active_callsigns.push(p.active_callsigns);
-        active_positions.push(p.active_positions);
+    let (start, end) = (interval.start, interval.end);
+    let artcc = options.artcc.as_deref();
+    if let Some(artcc) = artcc {
+        if !queries::artcc_exists(&db.pool, artcc).await? {
+            return Err(QueryError::IllegalArgs(format!("unknown ARTCC {artcc}")).into());
+        }
+        if let Some(days) = maintenance.activity_artcc_retention_days
+            && !retained(start, meta.requested_at, Some(days))
+        {
+            return Err(QueryError::IllegalArgs(format!(
+                "per-ARTCC activity is only available for the last {days} days"
+            ))
+            .into());
+        }
     }
 
+    let width = options
+        .resolution
+        .or_else(|| default_bucket_width(start, end, meta.requested_at, &maintenance))
+        // Breakdowns are always bucketed
+        .or_else(|| options.breakdown.map(|_| TimeDelta::minutes(1)));
+
+    let Some(width) = width else {
+        let points = queries::get_activity_snapshots(&db.pool, start, end, artcc).await?;
+        let mut series = ActivitySeries::default();
+        let mut observations = Vec::with_capacity(points.len());
+        for p in points {
+            observations.push(p.observed_at);
+            series.active_controllers.push(p.active_controllers);
+            series.active_callsigns.push(p.active_callsigns);
+            series.active_positions.push(p.active_positions);
+        }
+
+        return Ok((
+            StatusCode::OK,
+            Json(ActivityTimeSeriesResponse {
+                requested_at: meta.requested_at,
+                last_datafeed_updated_at: meta.last_datafeed_updated_at,
+                start,
+                end,
+                artcc: options.artcc,
+                resolution: "raw".to_string(),
+                observations,
+                active_controllers: series.active_controllers,
+                active_callsigns: series.active_callsigns,
+                active_positions: series.active_positions,
+                min: None,
+                avg: None,
+                breakdown: None,
+            }),
+        ));
+    };
+
+    if (end - start).num_seconds() / width.num_seconds() > MAX_ACTIVITY_BUCKETS {
+        return Err(QueryError::IllegalArgs(format!(
+            "resolution {} would return more than {MAX_ACTIVITY_BUCKETS} buckets",
+            format_resolution(width)
+        ))
+        .into());
+    }
+    let buckets = if let Some(artcc) = artcc {
+        queries::get_artcc_activity_buckets(&db.pool, artcc, start, end, width).await?
+    } else {
+        let source = ActivitySource::for_width(width, start, meta.requested_at, &maintenance)
+            .ok_or_else(|| {
+                QueryError::IllegalArgs(format!(
+                    "resolutions finer than an hour are only available for the last {} days",
+                    // Only unavailable if the minute rollups have a retention
+                    maintenance
+                        .activity_minute_retention_days
+                        .unwrap_or_default()
+                ))
+            })?;
+        queries::get_activity_buckets(&db.pool, start, end, width, source).await?
+    };
+    let mut totals = BucketSeries::default();
+    for bucket in buckets {
+        totals.push(bucket);
+    }
+
+    let breakdown = match options.breakdown {
+        Some(breakdown) => {
+            let mut groups: Vec<(Option<String>, BucketSeries)> = Vec::new();
+            for group in
+                queries::get_activity_breakdown(&db.pool, start, end, width, breakdown, artcc)
+                    .await?
+            {
+                // Rows are ordered by key, so each group's buckets are contiguous
+                match groups.last_mut() {
+                    Some((key, series)) if *key == group.key => series.push(group.bucket),
+                    _ => {
+                        let mut series = BucketSeries::default();
+                        series.push(group.bucket);
+                        groups.push((group.key, series));
+                    }
+                }
+            }
+            Some(
+                groups
+                    .into_iter()
+                    .map(|(key, series)| ActivityGroupSeries {
+                        key,
+                        observations: series.observations,
+                        max: series.max,
+                        min: series.min,
+                        avg: series.avg,
+                    })
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+
     Ok((
         StatusCode::OK,
         Json(ActivityTimeSeriesResponse {
             requested_at: meta.requested_at,
             last_datafeed_updated_at: meta.last_datafeed_updated_at,
-            start: interval.start,
-            end: interval.end,
-            observations,
-            active_controllers,
-            active_callsigns,
-            active_positions,
+            start,
+            end,
+            artcc: options.artcc,
+            resolution: format_resolution(width),
+            observations: totals.observations,
+            active_controllers: totals.max.active_controllers,
+            active_callsigns: totals.max.active_callsigns,
+            active_positions: totals.max.active_positions,
+            min: Some(totals.min),
+            avg: Some(totals.avg),
+            breakdown,
         }),
     ))
 }
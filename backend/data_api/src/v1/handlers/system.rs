@@ -0,0 +1,61 @@
+use crate::state::Db;
+use crate::v1::db::queries;
+use crate::v1::error::ApiError;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+use shared::health::HealthConfig;
+
+#[derive(Serialize, specta::Type)]
+#[serde(rename_all = "camelCase")]
+struct SystemStatusResponse {
+    pub requested_at: DateTime<Utc>,
+    pub last_datafeed_updated_at: Option<DateTime<Utc>>,
+    pub last_processed_at: Option<DateTime<Utc>>,
+    /// Seconds between `last_datafeed_updated_at` and `requested_at`
+    pub datafeed_lag_seconds: Option<i64>,
+    pub queue_depth: i64,
+    pub oldest_queued_at: Option<DateTime<Utc>>,
+    /// Whether the stats shown are behind the live datafeed, so a "data delayed" notice should be
+    /// shown
+    pub delayed: bool,
+}
+
+/// On success, returns a [`axum::response::Response`] with [`StatusCode::OK`] and
+/// [`SystemStatusResponse`] as JSON. Data is delayed once the latest datafeed or the oldest queued
+/// datafeed is older than the services' readiness thresholds.
+pub async fn get_system_status(
+    State(db): State<Db>,
+    State(health): State<HealthConfig>,
+) -> Result<impl IntoResponse, ApiError> {
+    let requested_at = Utc::now();
+    let status = queries::get_pipeline_status(&db.pool).await?;
+
+    let older_than = |at: DateTime<Utc>, seconds: u64| {
+        requested_at - at > TimeDelta::seconds(i64::try_from(seconds).unwrap_or(i64::MAX))
+    };
+    let delayed = status
+        .last_datafeed_updated_at
+        .is_none_or(|at| older_than(at, health.max_lag_seconds))
+        || status
+            .oldest_queued_at
+            .is_some_and(|at| older_than(at, health.max_queue_age_seconds));
+
+    Ok((
+        StatusCode::OK,
+        Json(SystemStatusResponse {
+            requested_at,
+            last_datafeed_updated_at: status.last_datafeed_updated_at,
+            last_processed_at: status.last_processed_at,
+            datafeed_lag_seconds: status
+                .last_datafeed_updated_at
+                .map(|at| (requested_at - at).num_seconds()),
+            queue_depth: status.queue_depth,
+            oldest_queued_at: status.oldest_queued_at,
+            delayed,
+        }),
+    ))
+}
@@ -1,18 +1,52 @@
 use axum::extract::Request;
 use axum::{middleware::Next, response::IntoResponse};
-use tower_sessions::Session;
 
+use crate::v1::db::queries::ApiKeyScope;
 use crate::v1::error::ApiError;
-use crate::v1::session;
+use crate::v1::extractors::user::CurrentUser;
 
+/// Requires a session or an API key with the `read` scope
 pub async fn require_auth(
-    session: Session,
+    user: CurrentUser,
     req: Request,
     next: Next,
 ) -> Result<impl IntoResponse, ApiError> {
-    let Some(_) = session::get_user(&session).await? else {
-        return Err(ApiError::AuthRequired);
-    };
+    user.require_scope(ApiKeyScope::Read)?;
+
+    Ok(next.run(req).await)
+}
+
+/// Requires a session rather than an API key
+pub async fn require_session(
+    user: CurrentUser,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    user.require_session()?;
+
+    Ok(next.run(req).await)
+}
+
+/// Requires an admin, with a session or an API key with the `manage` scope
+pub async fn require_admin(
+    user: CurrentUser,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    user.require_scope(ApiKeyScope::Manage)?;
+    user.require_admin()?;
+
+    Ok(next.run(req).await)
+}
+
+/// Like [`require_admin`], but ARTCC staff are also let through
+pub async fn require_staff(
+    user: CurrentUser,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    user.require_scope(ApiKeyScope::Manage)?;
+    user.require_staff()?;
 
     Ok(next.run(req).await)
 }
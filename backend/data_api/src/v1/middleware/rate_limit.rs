@@ -0,0 +1,54 @@
+use crate::state::Limiters;
+use crate::v1::error::ApiError;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::HeaderMap;
+use axum::{middleware::Next, response::IntoResponse};
+use shared::ApiConfig;
+use shared::rate_limit::Quota;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+/// Limits requests per client IP. Clients are identified by their IPv4 address or IPv6 /64, since
+/// a single IPv6 client usually has a whole /64 to rotate through.
+pub async fn rate_limit_by_ip(
+    State(api): State<ApiConfig>,
+    State(limiters): State<Limiters>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Result<impl IntoResponse, ApiError> {
+    let config = &api.rate_limit;
+    if config.enabled {
+        let ip = config
+            .trust_forwarded_for
+            .then(|| forwarded_for(req.headers()))
+            .flatten()
+            .unwrap_or(addr.ip());
+        let quota = Quota {
+            per_minute: config.requests_per_minute,
+            burst: config.burst,
+        };
+        limiters
+            .ips
+            .check(client_key(ip), quota)
+            .map_err(ApiError::RateLimited)?;
+    }
+
+    Ok(next.run(req).await)
+}
+
+/// The address the nearest proxy saw, i.e. the last `X-Forwarded-For` entry. Earlier entries are
+/// set by the client and can't be trusted.
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    let value = headers.get_all("x-forwarded-for").iter().next_back()?;
+    value.to_str().ok()?.rsplit(',').next()?.trim().parse().ok()
+}
+
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from_bits(v6.to_bits() & !u128::from(u64::MAX))),
+        },
+    }
+}
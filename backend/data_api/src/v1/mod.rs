@@ -1,3 +1,5 @@
+mod api_key;
+#[allow(dead_code)]
 mod api_models;
 mod db;
 mod error;
@@ -1,10 +1,18 @@
 use crate::state::AppState;
+use crate::v1::handlers::api_keys::{create_api_key, list_api_keys, revoke_api_key};
 use crate::v1::handlers::auth::{callback, login, logout, me};
-use crate::v1::handlers::stats::{get_activity_timeseries, get_iron_mic_stats};
-use crate::v1::middleware::auth::require_auth;
+use crate::v1::handlers::exclusions::{create_exclusion, delete_exclusion, list_exclusions};
+use crate::v1::handlers::roles::{grant_role, list_role_grants, revoke_role};
+use crate::v1::handlers::stats::{
+    get_activity_timeseries, get_controller_iron_mic_stats, get_iron_mic_stats,
+    get_position_iron_mic_stats,
+};
+use crate::v1::handlers::system::get_system_status;
+use crate::v1::middleware::auth::{require_admin, require_auth, require_session, require_staff};
+use crate::v1::middleware::rate_limit::rate_limit_by_ip;
 use axum::Router;
 use axum::middleware::from_fn_with_state;
-use axum::routing::get;
+use axum::routing::{delete, get};
 
 pub fn router(state: AppState) -> Router<AppState> {
     Router::<AppState>::new()
@@ -12,8 +20,21 @@ pub fn router(state: AppState) -> Router<AppState> {
         .route("/auth/callback", get(callback))
         .route("/auth/logout", get(logout))
         .route("/auth/me", get(me))
-        .route("/callsigns/top", get(get_iron_mic_stats))
+        .merge(public_routes(&state))
         .merge(protected_routes(&state))
+        .merge(session_routes(&state))
+        .merge(staff_routes(&state))
+        .merge(admin_routes(&state))
+}
+
+/// Routes anyone can use, rate limited per IP
+pub fn public_routes(state: &AppState) -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/callsigns/top", get(get_iron_mic_stats))
+        .route("/positions/top", get(get_position_iron_mic_stats))
+        .route("/controllers/top", get(get_controller_iron_mic_stats))
+        .route("/system/status", get(get_system_status))
+        .route_layer(from_fn_with_state(state.clone(), rate_limit_by_ip))
 }
 
 pub fn protected_routes(state: &AppState) -> Router<AppState> {
@@ -21,3 +42,29 @@ pub fn protected_routes(state: &AppState) -> Router<AppState> {
         .route("/activity/timeseries", get(get_activity_timeseries))
         .route_layer(from_fn_with_state(state.clone(), require_auth))
 }
+
+/// Routes that can't be used with an API key
+pub fn session_routes(state: &AppState) -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/api-keys", get(list_api_keys).post(create_api_key))
+        .route("/api-keys/{id}", delete(revoke_api_key))
+        .route_layer(from_fn_with_state(state.clone(), require_session))
+}
+
+/// Routes for admins and ARTCC staff. Handlers check the ARTCC scope of what's being changed.
+pub fn staff_routes(state: &AppState) -> Router<AppState> {
+    Router::<AppState>::new()
+        .route(
+            "/admin/iron-mic/exclusions",
+            get(list_exclusions).post(create_exclusion),
+        )
+        .route("/admin/iron-mic/exclusions/{id}", delete(delete_exclusion))
+        .route_layer(from_fn_with_state(state.clone(), require_staff))
+}
+
+pub fn admin_routes(state: &AppState) -> Router<AppState> {
+    Router::<AppState>::new()
+        .route("/admin/roles", get(list_role_grants).post(grant_role))
+        .route("/admin/roles/{id}", delete(revoke_role))
+        .route_layer(from_fn_with_state(state.clone(), require_admin))
+}
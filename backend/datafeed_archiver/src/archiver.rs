@@ -0,0 +1,351 @@
+use crate::error::ArchiverError;
+use crate::parquet::{ControllersWriter, count_rows};
+use crate::queries::{
+    ArchiveManifest, delete_payloads, fetch_days_before, fetch_dependent_deltas,
+    fetch_latest_updated_at, fetch_payloads_between, lock_payload_ids_between, mark_day_pruned,
+    rewrite_as_snapshot, upsert_archived_day,
+};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use shared::ArchiveConfig;
+use shared::archive::{
+    ArchiveStore, ArchiveWriter, ArchivedDay, ArchivedPayload, fetch_archived_day, iter_payloads,
+};
+use shared::compression::Codec;
+use shared::payloads::PayloadReader;
+use sqlx::{Pool, Postgres};
+use std::collections::HashSet;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// Payloads read from Postgres per query while exporting a day
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+/// Returns the half-open range of instants in a UTC day
+fn day_bounds(day: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = day.and_time(chrono::NaiveTime::MIN).and_utc();
+    (start, start + chrono::Duration::days(1))
+}
+
+/// Collects the payloads of a day into the archive formats
+struct DayExport {
+    writer: ArchiveWriter,
+    controllers: Option<ControllersWriter>,
+    ids: Vec<Uuid>,
+    first_updated_at: Option<DateTime<Utc>>,
+    last_updated_at: Option<DateTime<Utc>>,
+}
+
+impl DayExport {
+    fn new(parquet: bool) -> Result<Self, ArchiverError> {
+        Ok(Self {
+            writer: ArchiveWriter::new()?,
+            controllers: parquet.then(ControllersWriter::new).transpose()?,
+            ids: Vec::new(),
+            first_updated_at: None,
+            last_updated_at: None,
+        })
+    }
+
+    fn push(&mut self, payload: ArchivedPayload) -> Result<(), ArchiverError> {
+        self.writer.write(&payload)?;
+        self.ids.push(payload.id);
+        self.first_updated_at.get_or_insert(payload.updated_at);
+        self.last_updated_at = Some(payload.updated_at);
+        if let Some(controllers) = &mut self.controllers {
+            controllers.write(payload.payload)?;
+        }
+        Ok(())
+    }
+}
+
+/// Exports every payload stored for `day` to the archive and verifies that it reads back intact.
+///
+/// If the day was already archived and pruned, e.g. because a late datafeed was stored for it
+/// afterwards, the existing archive is merged with the payloads now in Postgres.
+#[instrument(skip(pool, store))]
+pub async fn archive_day(
+    pool: &Pool<Postgres>,
+    store: &ArchiveStore,
+    day: NaiveDate,
+    parquet: bool,
+) -> Result<Option<ArchiveManifest>, ArchiverError> {
+    let (start, end) = day_bounds(day);
+    let existing_bytes = match fetch_archived_day(pool, day).await? {
+        Some(existing) if existing.pruned_at.is_some() => {
+            Some(store.get(&existing.object_key).await?)
+        }
+        _ => None,
+    };
+    let mut archived = existing_bytes
+        .as_deref()
+        .map(iter_payloads)
+        .transpose()?
+        .into_iter()
+        .flatten();
+
+    let mut export = DayExport::new(parquet)?;
+    let mut reader = PayloadReader::default();
+    let mut conn = pool.acquire().await?;
+    let mut pending = archived.next().transpose()?;
+    let mut after = None;
+    loop {
+        let rows = fetch_payloads_between(&mut *conn, start, end, after, EXPORT_BATCH_SIZE).await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        after = Some(last.stored.updated_at);
+
+        for row in rows {
+            // Previously archived payloads are merged in order. Postgres is the newer copy of any
+            // datafeed stored in both.
+            while let Some(payload) = pending.take_if(|p| p.updated_at <= row.stored.updated_at) {
+                if payload.updated_at < row.stored.updated_at {
+                    export.push(payload)?;
+                }
+                pending = archived.next().transpose()?;
+            }
+
+            let payload = reader.read(&mut *conn, &row.stored).await?;
+            export.push(ArchivedPayload {
+                id: row.stored.id,
+                updated_at: row.stored.updated_at,
+                created_at: row.created_at,
+                original_size_bytes: row.original_size_bytes,
+                payload,
+            })?;
+        }
+    }
+    while let Some(payload) = pending {
+        export.push(payload)?;
+        pending = archived.next().transpose()?;
+    }
+    drop(conn);
+
+    let (Some(first_updated_at), Some(last_updated_at)) =
+        (export.first_updated_at, export.last_updated_at)
+    else {
+        return Ok(None);
+    };
+
+    let object_key = ArchiveStore::datafeeds_key(day);
+    let bytes = export.writer.finish()?;
+    let size_bytes = i64::try_from(bytes.len())?;
+    store.put(&object_key, bytes.clone()).await?;
+    verify_datafeeds(store, day, &object_key, &bytes, &export.ids).await?;
+
+    let parquet_key = match export.controllers {
+        Some(controllers) => {
+            let key = ArchiveStore::controllers_key(day);
+            let (bytes, rows) = controllers.finish()?;
+            store.put(&key, bytes).await?;
+            let read_rows = count_rows(store.get(&key).await?)?;
+            if usize::try_from(read_rows).ok() != Some(rows) {
+                return Err(ArchiverError::Verification {
+                    day,
+                    reason: format!("wrote {rows} controller rows but read back {read_rows}"),
+                });
+            }
+            Some(key)
+        }
+        None => None,
+    };
+
+    let manifest = ArchiveManifest {
+        day,
+        object_key,
+        parquet_key,
+        payload_count: i32::try_from(export.ids.len())?,
+        first_updated_at,
+        last_updated_at,
+        size_bytes,
+    };
+    upsert_archived_day(pool, &manifest).await?;
+
+    info!(
+        name: "archive.day.exported",
+        %day,
+        payloads = manifest.payload_count,
+        size_bytes = manifest.size_bytes,
+        "exported datafeeds to archive"
+    );
+    Ok(Some(manifest))
+}
+
+/// Reads an archive back from the store and checks it's byte-for-byte what was written and
+/// decodes to the expected payloads
+async fn verify_datafeeds(
+    store: &ArchiveStore,
+    day: NaiveDate,
+    key: &str,
+    written: &[u8],
+    ids: &[Uuid],
+) -> Result<(), ArchiverError> {
+    let failed = |reason: String| ArchiverError::Verification { day, reason };
+
+    let read = store.get(key).await?;
+    if read != written {
+        return Err(failed(format!(
+            "wrote {} bytes but read back {} different bytes",
+            written.len(),
+            read.len()
+        )));
+    }
+
+    let mut count = 0;
+    for (payload, id) in iter_payloads(&read)?.zip(ids) {
+        if payload?.id != *id {
+            return Err(failed(format!("payload {count} is not {id}")));
+        }
+        count += 1;
+    }
+    if count != ids.len() {
+        return Err(failed(format!(
+            "wrote {} payloads but read back {count}",
+            ids.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks that the archive of `day` can be read and matches its manifest
+#[instrument(skip(pool, store))]
+pub async fn verify_day(
+    pool: &Pool<Postgres>,
+    store: &ArchiveStore,
+    day: NaiveDate,
+) -> Result<ArchivedDay, ArchiverError> {
+    let manifest = fetch_archived_day(pool, day)
+        .await?
+        .ok_or(ArchiverError::NotArchived(day))?;
+    let failed = |reason: String| ArchiverError::Verification { day, reason };
+
+    let bytes = store.get(&manifest.object_key).await?;
+    if i64::try_from(bytes.len())? != manifest.size_bytes {
+        return Err(failed(format!(
+            "archive is {} bytes but the manifest records {}",
+            bytes.len(),
+            manifest.size_bytes
+        )));
+    }
+
+    let mut count = 0;
+    let mut bounds = None;
+    for payload in iter_payloads(&bytes)? {
+        let payload = payload?;
+        let (first, _) = bounds.unwrap_or((payload.updated_at, payload.updated_at));
+        bounds = Some((first, payload.updated_at));
+        count += 1;
+    }
+    if count != manifest.payload_count {
+        return Err(failed(format!(
+            "archive has {count} payloads but the manifest records {}",
+            manifest.payload_count
+        )));
+    }
+    if bounds != Some((manifest.first_updated_at, manifest.last_updated_at)) {
+        return Err(failed(
+            "archived payloads don't span the range recorded in the manifest".to_string(),
+        ));
+    }
+
+    if let Some(key) = &manifest.parquet_key {
+        count_rows(store.get(key).await?)?;
+    }
+
+    Ok(manifest)
+}
+
+/// Deletes the payloads of an archived day from Postgres. Nothing is deleted unless every payload
+/// currently stored for the day is in the archive, so payloads stored after the export are kept
+/// until the next run archives them.
+///
+/// Deltas stored outside the day that are based on one of its payloads are rewritten as snapshots
+/// first, so they can still be read once their base is gone.
+#[instrument(skip(pool, store, codec))]
+pub async fn prune_day(
+    pool: &Pool<Postgres>,
+    store: &ArchiveStore,
+    codec: &Codec,
+    day: NaiveDate,
+) -> Result<Option<u64>, ArchiverError> {
+    let manifest = fetch_archived_day(pool, day)
+        .await?
+        .ok_or(ArchiverError::NotArchived(day))?;
+    let (start, end) = day_bounds(day);
+
+    let mut tx = pool.begin().await?;
+    let ids = lock_payload_ids_between(&mut *tx, start, end).await?;
+
+    // The processor encodes the next delta against the latest payload, so it's never pruned
+    if let Some(latest) = fetch_latest_updated_at(&mut *tx).await?
+        && latest < end
+    {
+        warn!(name: "archive.day.prune_skipped", %day, "day holds the most recent stored datafeed, not pruning it");
+        return Ok(None);
+    }
+
+    let bytes = store.get(&manifest.object_key).await?;
+    let mut archived = HashSet::with_capacity(ids.len());
+    for payload in iter_payloads(&bytes)? {
+        archived.insert(payload?.id);
+    }
+    let unarchived = ids.iter().filter(|id| !archived.contains(id)).count();
+    if unarchived > 0 {
+        warn!(name: "archive.day.prune_skipped", %day, unarchived, "day has payloads that aren't archived yet, not pruning it");
+        return Ok(None);
+    }
+
+    let dependents = fetch_dependent_deltas(&mut *tx, &ids).await?;
+    let mut reader = PayloadReader::default();
+    for dependent in &dependents {
+        let payload = reader.read(&mut *tx, dependent).await?;
+        let compressed = codec.compress(&serde_json::to_vec(&payload)?)?;
+        rewrite_as_snapshot(
+            &mut *tx,
+            dependent.id,
+            compressed.bytes,
+            compressed.algo,
+            compressed.dictionary_id,
+        )
+        .await?;
+    }
+
+    let pruned = delete_payloads(&mut *tx, &ids).await?;
+    mark_day_pruned(&mut *tx, day).await?;
+    tx.commit().await?;
+
+    info!(
+        name: "archive.day.pruned",
+        %day,
+        pruned,
+        rebased = dependents.len(),
+        "pruned archived datafeeds from postgres"
+    );
+    Ok(Some(pruned))
+}
+
+/// Archives, and unless `skip_prune` is set prunes, every day older than the retention window
+pub async fn run(
+    pool: &Pool<Postgres>,
+    store: &ArchiveStore,
+    codec: &Codec,
+    config: &ArchiveConfig,
+    skip_prune: bool,
+) -> Result<(), ArchiverError> {
+    let cutoff = Utc::now().date_naive() - Days::new(u64::from(config.retention_days));
+    let days = fetch_days_before(pool, cutoff).await?;
+    info!(name: "archive.run.started", %cutoff, days = days.len(), "archiving datafeeds older than the retention window");
+
+    for day in days {
+        if archive_day(pool, store, day, config.parquet)
+            .await?
+            .is_some()
+            && !skip_prune
+        {
+            prune_day(pool, store, codec, day).await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Exports stored datafeeds older than the retention window to the archive and prunes them from Postgres"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Archives every day of stored datafeeds older than `archive.retention_days`, then prunes
+    /// the archived rows. Intended to be run on a schedule, e.g. daily.
+    Run {
+        /// Only export and verify archives, keeping the rows in Postgres
+        #[arg(long)]
+        skip_prune: bool,
+    },
+    /// Archives a single UTC day, regardless of the retention window, without pruning it
+    Archive {
+        /// Day to archive, as YYYY-MM-DD
+        day: NaiveDate,
+    },
+    /// Checks that the archive for a day can be read back and matches its manifest
+    Verify {
+        /// Day to verify, as YYYY-MM-DD
+        day: NaiveDate,
+    },
+}
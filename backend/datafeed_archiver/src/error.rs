@@ -0,0 +1,34 @@
+use chrono::NaiveDate;
+use shared::archive::ArchiveError;
+use shared::compression::CompressionError;
+use shared::error::InitializationError;
+use shared::payloads::PayloadError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ArchiverError {
+    #[error("failed to initialize datafeed archiver: {0}")]
+    Initialization(#[from] InitializationError),
+    #[error("no archive location configured; set archive.location")]
+    NotConfigured,
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+    #[error(transparent)]
+    Payload(#[from] PayloadError),
+    #[error(transparent)]
+    Compression(#[from] CompressionError),
+    #[error("database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("failed to write parquet: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error("failed to build parquet batch: {0}")]
+    Arrow(#[from] arrow_schema::ArrowError),
+    #[error("archive for {day} failed verification: {reason}")]
+    Verification { day: NaiveDate, reason: String },
+    #[error("{0} has not been archived")]
+    NotArchived(NaiveDate),
+    #[error("failed to serialize payload: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("archive too large for its manifest: {0}")]
+    Size(#[from] std::num::TryFromIntError),
+}
@@ -0,0 +1,54 @@
+#[warn(clippy::pedantic)]
+mod archiver;
+mod cli;
+mod error;
+mod parquet;
+mod queries;
+
+use crate::archiver::{archive_day, run, verify_day};
+use crate::cli::{Cli, Command};
+use crate::error::ArchiverError;
+use clap::Parser;
+use shared::archive::ArchiveStore;
+use shared::compression::Codec;
+use shared::error::InitializationError;
+use shared::telemetry::init_telemetry;
+use shared::{initialize_db, load_config};
+use tracing::info;
+
+#[tokio::main]
+async fn main() -> Result<(), ArchiverError> {
+    let cli = Cli::parse();
+    let config = load_config().map_err(InitializationError::from)?;
+    let telemetry = init_telemetry("datafeed_archiver", &config.telemetry)?;
+    info!(name: "config.loaded", config = ?config, "config loaded");
+    let archive_config = config
+        .archive
+        .as_ref()
+        .ok_or(ArchiverError::NotConfigured)?;
+    let store = ArchiveStore::from_config(archive_config)?;
+    let db_pool = initialize_db(&config.postgres, true).await?;
+
+    let res = match cli.command {
+        Command::Run { skip_prune } => {
+            let codec = Codec::load(&db_pool, &config.compression).await?;
+            run(&db_pool, &store, &codec, archive_config, skip_prune).await
+        }
+        Command::Archive { day } => archive_day(&db_pool, &store, day, archive_config.parquet)
+            .await
+            .map(|_| ()),
+        Command::Verify { day } => verify_day(&db_pool, &store, day).await.map(|manifest| {
+            info!(
+                name: "archive.day.verified",
+                %day,
+                payloads = manifest.payload_count,
+                pruned = manifest.pruned_at.is_some(),
+                "archive verified"
+            );
+        }),
+    };
+
+    telemetry.shutdown();
+
+    res
+}
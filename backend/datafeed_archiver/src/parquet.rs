@@ -0,0 +1,174 @@
+use crate::error::ArchiverError;
+use arrow_array::builder::{
+    BooleanBuilder, Int32Builder, StringBuilder, TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use bytes::Bytes;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use serde_json::Value;
+use shared::vnas::datafeed::DatafeedRoot;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Rows buffered before they're flushed to the Parquet writer as a record batch
+const BATCH_ROWS: usize = 65_536;
+
+fn schema() -> SchemaRef {
+    let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+    Arc::new(Schema::new(vec![
+        Field::new("updated_at", timestamp.clone(), false),
+        Field::new("cid", DataType::Utf8, false),
+        Field::new("callsign", DataType::Utf8, false),
+        Field::new("artcc_id", DataType::Utf8, false),
+        Field::new("primary_facility_id", DataType::Utf8, false),
+        Field::new("primary_position_id", DataType::Utf8, false),
+        Field::new("role", DataType::Utf8, false),
+        Field::new("is_active", DataType::Boolean, false),
+        Field::new("is_observer", DataType::Boolean, false),
+        Field::new("login_time", timestamp, false),
+        Field::new("user_rating", DataType::Utf8, false),
+        Field::new("requested_rating", DataType::Utf8, false),
+        Field::new("facility_type", DataType::Utf8, false),
+        Field::new("primary_frequency", DataType::Int32, false),
+    ]))
+}
+
+/// Writes one row per controller per datafeed, for analytics over archived days
+pub struct ControllersWriter {
+    writer: ArrowWriter<Vec<u8>>,
+    schema: SchemaRef,
+    updated_at: TimestampMicrosecondBuilder,
+    cid: StringBuilder,
+    callsign: StringBuilder,
+    artcc_id: StringBuilder,
+    primary_facility_id: StringBuilder,
+    primary_position_id: StringBuilder,
+    role: StringBuilder,
+    is_active: BooleanBuilder,
+    is_observer: BooleanBuilder,
+    login_time: TimestampMicrosecondBuilder,
+    user_rating: StringBuilder,
+    requested_rating: StringBuilder,
+    facility_type: StringBuilder,
+    primary_frequency: Int32Builder,
+    buffered: usize,
+    rows: usize,
+}
+
+impl ControllersWriter {
+    pub fn new() -> Result<Self, ArchiverError> {
+        let schema = schema();
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::try_new(9)?))
+            .build();
+        let timestamp = || TimestampMicrosecondBuilder::new().with_timezone("UTC");
+
+        Ok(Self {
+            writer: ArrowWriter::try_new(Vec::new(), Arc::clone(&schema), Some(props))?,
+            schema,
+            updated_at: timestamp(),
+            cid: StringBuilder::new(),
+            callsign: StringBuilder::new(),
+            artcc_id: StringBuilder::new(),
+            primary_facility_id: StringBuilder::new(),
+            primary_position_id: StringBuilder::new(),
+            role: StringBuilder::new(),
+            is_active: BooleanBuilder::new(),
+            is_observer: BooleanBuilder::new(),
+            login_time: timestamp(),
+            user_rating: StringBuilder::new(),
+            requested_rating: StringBuilder::new(),
+            facility_type: StringBuilder::new(),
+            primary_frequency: Int32Builder::new(),
+            buffered: 0,
+            rows: 0,
+        })
+    }
+
+    /// Adds the controllers of a raw datafeed. Datafeeds that no longer match the DTOs are
+    /// skipped, since the NDJSON archive keeps them regardless.
+    pub fn write(&mut self, payload: Value) -> Result<(), ArchiverError> {
+        let datafeed = match serde_json::from_value::<DatafeedRoot>(payload) {
+            Ok(datafeed) => datafeed,
+            Err(e) => {
+                warn!(name: "archive.parquet.datafeed_skipped", error = %e, "datafeed could not be parsed, leaving it out of the controllers parquet");
+                return Ok(());
+            }
+        };
+
+        let updated_at = datafeed.updated_at.timestamp_micros();
+        for controller in datafeed.controllers {
+            let vatsim = controller.vatsim_data;
+            self.updated_at.append_value(updated_at);
+            self.cid.append_value(vatsim.cid);
+            self.callsign.append_value(vatsim.callsign);
+            self.artcc_id.append_value(controller.artcc_id);
+            self.primary_facility_id
+                .append_value(controller.primary_facility_id);
+            self.primary_position_id
+                .append_value(controller.primary_position_id);
+            self.role.append_value(controller.role.as_str());
+            self.is_active.append_value(controller.is_active);
+            self.is_observer.append_value(controller.is_observer);
+            self.login_time
+                .append_value(controller.login_time.timestamp_micros());
+            self.user_rating.append_value(vatsim.user_rating.as_str());
+            self.requested_rating
+                .append_value(vatsim.requested_rating.as_str());
+            self.facility_type
+                .append_value(vatsim.facility_type.as_str());
+            self.primary_frequency
+                .append_value(vatsim.primary_frequency);
+            self.buffered += 1;
+        }
+
+        if self.buffered >= BATCH_ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), ArchiverError> {
+        if self.buffered == 0 {
+            return Ok(());
+        }
+
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.updated_at.finish()),
+            Arc::new(self.cid.finish()),
+            Arc::new(self.callsign.finish()),
+            Arc::new(self.artcc_id.finish()),
+            Arc::new(self.primary_facility_id.finish()),
+            Arc::new(self.primary_position_id.finish()),
+            Arc::new(self.role.finish()),
+            Arc::new(self.is_active.finish()),
+            Arc::new(self.is_observer.finish()),
+            Arc::new(self.login_time.finish()),
+            Arc::new(self.user_rating.finish()),
+            Arc::new(self.requested_rating.finish()),
+            Arc::new(self.facility_type.finish()),
+            Arc::new(self.primary_frequency.finish()),
+        ];
+        self.writer
+            .write(&RecordBatch::try_new(Arc::clone(&self.schema), columns)?)?;
+        self.rows += self.buffered;
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Returns the Parquet file and the number of rows in it
+    pub fn finish(mut self) -> Result<(Vec<u8>, usize), ArchiverError> {
+        self.flush()?;
+        Ok((self.writer.into_inner()?, self.rows))
+    }
+}
+
+/// Returns the number of rows in a Parquet file
+pub fn count_rows(bytes: Vec<u8>) -> Result<i64, ArchiverError> {
+    let reader = SerializedFileReader::new(Bytes::from(bytes))?;
+    Ok(reader.metadata().file_metadata().num_rows())
+}
@@ -0,0 +1,236 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use shared::payloads::StoredPayload;
+use sqlx::{Executor, Postgres};
+use tracing::instrument;
+use uuid::Uuid;
+
+/// A stored payload along with the row metadata kept in the archive
+#[derive(Debug, sqlx::FromRow)]
+pub struct DayPayload {
+    #[sqlx(flatten)]
+    pub stored: StoredPayload,
+    pub created_at: DateTime<Utc>,
+    pub original_size_bytes: i32,
+}
+
+/// Summary of an archive export, stored as a `datafeed_archives` row
+#[derive(Debug)]
+pub struct ArchiveManifest {
+    pub day: NaiveDate,
+    pub object_key: String,
+    pub parquet_key: Option<String>,
+    pub payload_count: i32,
+    pub first_updated_at: DateTime<Utc>,
+    pub last_updated_at: DateTime<Utc>,
+    pub size_bytes: i64,
+}
+
+/// Returns the UTC days before `cutoff` that still have payloads in Postgres, oldest first
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_days_before<'e, E>(
+    executor: E,
+    cutoff: NaiveDate,
+) -> Result<Vec<NaiveDate>, sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar::<_, NaiveDate>(
+        r"
+        SELECT DISTINCT (updated_at AT TIME ZONE 'UTC')::date AS day
+        FROM datafeed_payloads
+        WHERE updated_at < ($1::date)::timestamp AT TIME ZONE 'UTC'
+        ORDER BY day
+        ",
+    )
+    .bind(cutoff)
+    .fetch_all(executor)
+    .await
+}
+
+/// Returns up to `limit` payloads updated in `[start, end)` after `after`, in `updated_at` order
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_payloads_between<'e, E>(
+    executor: E,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    after: Option<DateTime<Utc>>,
+    limit: i64,
+) -> Result<Vec<DayPayload>, sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, DayPayload>(
+        r"
+        SELECT id, updated_at, payload_kind, base_payload_id, payload_compressed,
+               compression_algo, dictionary_id, created_at, original_size_bytes
+        FROM datafeed_payloads
+        WHERE updated_at >= $1
+          AND updated_at < $2
+          AND ($3::timestamptz IS NULL OR updated_at > $3)
+        ORDER BY updated_at
+        LIMIT $4
+        ",
+    )
+    .bind(start)
+    .bind(end)
+    .bind(after)
+    .bind(limit)
+    .fetch_all(executor)
+    .await
+}
+
+/// Locks and returns the ids of every payload updated in `[start, end)`
+#[instrument(level = "debug", skip(executor))]
+pub async fn lock_payload_ids_between<'e, E>(
+    executor: E,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<Uuid>, sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar::<_, Uuid>(
+        r"
+        SELECT id
+        FROM datafeed_payloads
+        WHERE updated_at >= $1 AND updated_at < $2
+        ORDER BY updated_at
+        FOR UPDATE
+        ",
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(executor)
+    .await
+}
+
+/// Returns the deltas that aren't in `ids` but are based on one of them, in `updated_at` order
+#[instrument(level = "debug", skip(executor, ids))]
+pub async fn fetch_dependent_deltas<'e, E>(
+    executor: E,
+    ids: &[Uuid],
+) -> Result<Vec<StoredPayload>, sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, StoredPayload>(
+        r"
+        SELECT id, updated_at, payload_kind, base_payload_id, payload_compressed,
+               compression_algo, dictionary_id
+        FROM datafeed_payloads
+        WHERE base_payload_id = ANY($1)
+          AND NOT (id = ANY($1))
+        ORDER BY updated_at
+        FOR UPDATE
+        ",
+    )
+    .bind(ids)
+    .fetch_all(executor)
+    .await
+}
+
+/// Rewrites a payload as a snapshot, so it no longer depends on any other payload
+#[instrument(level = "debug", skip(executor, payload_compressed))]
+pub async fn rewrite_as_snapshot<'e, E>(
+    executor: E,
+    id: Uuid,
+    payload_compressed: Vec<u8>,
+    algo: &str,
+    dictionary_id: Option<Uuid>,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        r"
+        UPDATE datafeed_payloads
+        SET payload_kind = 'snapshot',
+            base_payload_id = NULL,
+            payload_compressed = $2,
+            compression_algo = $3,
+            dictionary_id = $4
+        WHERE id = $1
+        ",
+    )
+    .bind(id)
+    .bind(payload_compressed)
+    .bind(algo)
+    .bind(dictionary_id)
+    .execute(executor)
+    .await
+    .map(|_| ())
+}
+
+#[instrument(level = "debug", skip(executor, ids))]
+pub async fn delete_payloads<'e, E>(executor: E, ids: &[Uuid]) -> Result<u64, sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query("DELETE FROM datafeed_payloads WHERE id = ANY($1)")
+        .bind(ids)
+        .execute(executor)
+        .await
+        .map(|r| r.rows_affected())
+}
+
+/// Records an export, keeping `pruned_at` if the day had already been pruned
+#[instrument(level = "debug", skip(executor))]
+pub async fn upsert_archived_day<'e, E>(
+    executor: E,
+    manifest: &ArchiveManifest,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        r"
+        INSERT INTO datafeed_archives (
+            day, object_key, parquet_key, payload_count, first_updated_at, last_updated_at,
+            size_bytes, archived_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+        ON CONFLICT (day) DO UPDATE
+        SET object_key = EXCLUDED.object_key,
+            parquet_key = EXCLUDED.parquet_key,
+            payload_count = EXCLUDED.payload_count,
+            first_updated_at = EXCLUDED.first_updated_at,
+            last_updated_at = EXCLUDED.last_updated_at,
+            size_bytes = EXCLUDED.size_bytes,
+            archived_at = EXCLUDED.archived_at
+        ",
+    )
+    .bind(manifest.day)
+    .bind(&manifest.object_key)
+    .bind(&manifest.parquet_key)
+    .bind(manifest.payload_count)
+    .bind(manifest.first_updated_at)
+    .bind(manifest.last_updated_at)
+    .bind(manifest.size_bytes)
+    .execute(executor)
+    .await
+    .map(|_| ())
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn mark_day_pruned<'e, E>(executor: E, day: NaiveDate) -> Result<(), sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query("UPDATE datafeed_archives SET pruned_at = now() WHERE day = $1")
+        .bind(day)
+        .execute(executor)
+        .await
+        .map(|_| ())
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_latest_updated_at<'e, E>(
+    executor: E,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar::<_, Option<DateTime<Utc>>>("SELECT max(updated_at) FROM datafeed_payloads")
+        .fetch_one(executor)
+        .await
+}
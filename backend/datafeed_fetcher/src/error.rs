@@ -1,23 +1,23 @@
 use thiserror::Error;
 
-#[derive(Error, Debug)]
-pub enum FetchError {
-    #[error(transparent)]
-    Reqwest(#[from] reqwest::Error),
-    #[error(transparent)]
-    Deserialize(#[from] serde_json::Error),
-    #[error(transparent)]
-    TimestampDeserialize(#[from] chrono::format::ParseError),
-    #[error("unable to find or parse updatedAt field in JSON")]
-    MissingUpdatedAt,
-}
-
 #[derive(Debug, Error)]
 pub enum EnqueueError {
     #[error("database error: {0}")]
     Db(#[from] sqlx::Error),
-    #[error("datafeed fetch error: {0}")]
-    Fetch(#[from] FetchError),
+    #[error("{0} spilled datafeeds have to be enqueued first")]
+    SpillBacklog(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum SpillError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("failed to decompress spilled datafeed: {0}")]
+    Decompress(std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("spill queue is empty")]
+    Empty,
 }
 
 #[derive(Debug, Error)]
@@ -26,6 +26,8 @@ pub enum MainError {
     Init(#[from] shared::error::InitializationError),
     #[error(transparent)]
     Enqueue(#[from] EnqueueError),
+    #[error("failed to open spill queue: {0}")]
+    Spill(#[from] SpillError),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -1,23 +1,21 @@
 #![warn(clippy::pedantic)]
 mod error;
+mod metrics;
+mod spill;
 
-use crate::error::{EnqueueError, FetchError, MainError};
-use axum::Router;
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::routing::get;
+use crate::error::{EnqueueError, MainError};
+use crate::metrics::{FetchMetrics, SpillMetrics};
+use crate::spill::SpillQueue;
 use chrono::{DateTime, TimeDelta, Utc};
-use parking_lot::RwLock;
-use serde_json::Value;
-use shared::vnas::datafeed::{VnasEnvironment, datafeed_url};
-use shared::{init_tracing_and_oltp, shutdown_listener};
-use shared::{initialize_db, load_config};
+use serde_json::{Value, json};
+use shared::error::InitializationError;
+use shared::health::{self, Checks, Health};
+use shared::leader::{LeaderElection, LeaderLock};
+use shared::shutdown_listener;
+use shared::telemetry::init_telemetry;
+use shared::vnas::fetch::{DatafeedFetcher, FetchOutcome, PollSchedule};
+use shared::{FetcherConfig, initialize_db, load_config};
 use sqlx::{Pool, Postgres};
-use std::collections::VecDeque;
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::net::TcpListener;
 use tokio::time::sleep;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, instrument, warn};
@@ -26,44 +24,73 @@ use uuid::Uuid;
 #[derive(Clone)]
 struct FetcherState {
     db_pool: Pool<Postgres>,
-    last_attempted_update: Arc<RwLock<Option<DateTime<Utc>>>>,
-    last_successful_update: Arc<RwLock<Option<DateTime<Utc>>>>,
-    last_error: Arc<RwLock<Option<EnqueueError>>>,
-    in_memory_queue: Arc<RwLock<VecDeque<(Value, DateTime<Utc>)>>>,
+    health: Health,
+    metrics: FetchMetrics,
+}
+
+/// Polling state of the fetcher loop, reported under the health check's `details`
+struct Polling {
+    schedule: PollSchedule,
+    next_poll_at: Option<DateTime<Utc>>,
+    last_attempted_at: Option<DateTime<Utc>>,
+    /// `updatedAt` of the last datafeed enqueued, including into the spill queue
+    last_enqueued_updated_at: Option<DateTime<Utc>>,
+    /// Polls answered with `304 Not Modified`
+    not_modified: u64,
+    /// Datafeeds downloaded but not enqueued because their `updatedAt` hadn't changed
+    unchanged: u64,
 }
 
 #[tokio::main]
+#[allow(clippy::too_many_lines)]
 async fn main() -> Result<(), MainError> {
-    let (tracer_provider, meter_provider) = init_tracing_and_oltp("artcc_updater")?;
-
-    // Set up config
-    let config = load_config().unwrap_or_else(|e| {
-        error!(error = ?e, "configuration could not be initialized");
-        panic!("configuration could not be initialized");
-    });
+    // Set up config, which decides how telemetry is exported
+    let config = load_config().map_err(InitializationError::from)?;
+    let telemetry = init_telemetry("datafeed_fetcher", &config.telemetry)?;
     info!(name: "config.loaded", config = ?config, "config loaded");
 
     let db_pool = initialize_db(&config.postgres, true).await?;
 
-    let interval_seconds = config.fetcher.map_or(15, |c| c.interval_seconds);
-
+    let fetcher_config = config.fetcher.unwrap_or_default();
+    let spill_queue = SpillQueue::open(&fetcher_config.spill).await?;
+
+    let health = Health::new(
+        "datafeed_fetcher",
+        &config.health,
+        Checks {
+            success: true,
+            lag: false,
+            queue_age: false,
+        },
+    )
+    .with_database(db_pool.clone());
     let state = FetcherState {
         db_pool,
-        last_attempted_update: Arc::new(RwLock::new(None)),
-        last_successful_update: Arc::new(RwLock::new(None)),
-        last_error: Arc::new(RwLock::new(None)),
-        in_memory_queue: Arc::new(RwLock::new(VecDeque::new())),
+        health,
+        metrics: FetchMetrics::default(),
     };
 
     // Cancellation token shared across tasks; listener cancels on SIGINT/SIGTERM.
     let shutdown_token = CancellationToken::new();
     let mut signal_handle = tokio::spawn(shutdown_listener(Some(shutdown_token.clone())));
 
-    let mut axum_handle = tokio::spawn(run_health_server(state.clone(), shutdown_token.clone()));
+    let mut axum_handle = tokio::spawn(health::serve(
+        config.health.bind_address,
+        health::router(state.health.clone()).merge(telemetry.metrics_router()),
+        shutdown_token.clone().cancelled_owned(),
+    ));
 
-    let mut fetcher_handle = tokio::spawn(fetcher_loop(
+    let election = LeaderElection::new(
+        state.db_pool.clone(),
+        LeaderLock::Fetcher,
+        &config.leader_election,
+        state.health.clone(),
+    );
+    let mut fetcher_handle = tokio::spawn(lead_fetching(
         state,
-        interval_seconds,
+        spill_queue,
+        fetcher_config,
+        election,
         shutdown_token.clone(),
     ));
 
@@ -147,13 +174,7 @@ async fn main() -> Result<(), MainError> {
         }
     }
 
-    if let Err(e) = tracer_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
-    }
-
-    if let Err(e) = meter_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
-    }
+    telemetry.shutdown();
 
     if let Some(err) = first_err {
         Err(err)
@@ -162,13 +183,45 @@ async fn main() -> Result<(), MainError> {
     }
 }
 
-async fn fetcher_loop(
+/// Runs [`fetcher_loop`] for as long as this replica is leader, going back to standby whenever
+/// leadership is lost, so only one fetcher replica polls the datafeed at a time
+async fn lead_fetching(
     state: FetcherState,
-    interval_seconds: u64,
+    mut spill_queue: SpillQueue,
+    fetcher_config: FetcherConfig,
+    election: LeaderElection,
+    shutdown: CancellationToken,
+) -> Result<(), EnqueueError> {
+    while let Some(leadership) = election.acquire(&shutdown).await {
+        let res = fetcher_loop(
+            &state,
+            &mut spill_queue,
+            PollSchedule::new(&fetcher_config),
+            leadership.token(),
+        )
+        .await;
+        leadership.release().await;
+        res?;
+        if shutdown.is_cancelled() {
+            break;
+        }
+        warn!(name: "fetcher.leadership.lost", "lost leadership, returning to standby");
+    }
+
+    Ok(())
+}
+
+async fn fetcher_loop(
+    state: &FetcherState,
+    spill_queue: &mut SpillQueue,
+    schedule: PollSchedule,
     shutdown: CancellationToken,
 ) -> Result<(), EnqueueError> {
     // Default reqwest client
-    let http_client = reqwest::Client::new();
+    let mut fetcher = DatafeedFetcher::new(reqwest::Client::new());
+    let metrics = SpillMetrics::default();
+    let mut polling = Polling::new(schedule);
+    publish_spill_stats(state, spill_queue, &metrics);
 
     info!(name: "fetcher.loop.initialized", "initialized Datafeed Fetcher");
     let mut initial_loop = true;
@@ -176,46 +229,91 @@ async fn fetcher_loop(
         if initial_loop {
             initial_loop = false;
         } else {
+            let now = Utc::now();
+            let delay = polling.schedule.next_delay(now);
+            polling.next_poll_at = Some(now + TimeDelta::from_std(delay).unwrap_or(TimeDelta::MAX));
+            polling.publish(&state.health);
             tokio::select! {
-                _ = sleep(Duration::from_secs(interval_seconds)) => {},
-                _ = shutdown.cancelled() => {
+                () = sleep(delay) => {},
+                () = shutdown.cancelled() => {
                     info!(name: "fetcher_loop.shutdown.requested", "shutdown requested, exiting fetcher loop");
                     break;
                 }
             }
         }
 
-        // Try to process in-memory queue first
-        process_in_memory_queue(&state).await;
+        // Try to drain the spill queue first
+        drain_spill_queue(state, spill_queue, &metrics).await;
 
         let now = Utc::now();
-        *state.last_attempted_update.write() = Some(now);
-        let (payload, datafeed_updated_at) = match fetch_datafeed(&http_client).await {
-            Ok((p, t)) => (p, t),
+        polling.last_attempted_at = Some(now);
+        let (payload, datafeed_updated_at) = match state.metrics.time_fetch(fetcher.fetch()).await {
+            Ok(FetchOutcome::Modified {
+                payload,
+                updated_at,
+            }) => (payload, updated_at),
+            Ok(FetchOutcome::NotModified) => {
+                debug!(name: "fetcher_loop.datafeed.not_modified", "datafeed not modified since last fetch");
+                polling.schedule.record_unchanged();
+                polling.not_modified += 1;
+                state.health.record_success(now);
+                continue;
+            }
             Err(e) => {
-                warn!(name:"fetcher_loop.datafeed.received", error = ?e, "failed to fetch and deserialize datafeed");
-                *state.last_error.write() = Some(e.into());
+                polling.schedule.record_failure();
+                warn!(
+                    name:"fetcher_loop.datafeed.received",
+                    error = ?e,
+                    consecutive_failures = polling.schedule.consecutive_failures(),
+                    "failed to fetch and deserialize datafeed"
+                );
+                state.health.record_error(e);
                 continue;
             }
         };
+        polling.schedule.record_success(datafeed_updated_at);
+        state.health.set_datafeed_updated_at(datafeed_updated_at);
+
+        if polling.last_enqueued_updated_at == Some(datafeed_updated_at) {
+            debug!(name: "fetcher_loop.datafeed.unchanged", updated_at = ?datafeed_updated_at, "skipping enqueue; datafeed updatedAt unchanged");
+            polling.unchanged += 1;
+            state.health.record_success(now);
+            continue;
+        }
         info!(updated_at = ?datafeed_updated_at, "fetched datafeed");
 
-        if let Err(e) = enqueue_datafeed(&state.db_pool, payload.clone(), datafeed_updated_at).await
-        {
-            warn!(name:"fetcher_loop.datafeed.enqueued", error = ?e, "could not enqueue datafeed into Postgres");
-            *state.last_error.write() = Some(e);
-            state
-                .in_memory_queue
-                .write()
-                .push_back((payload, datafeed_updated_at));
-            info!(
-                name = "fetcher_loop.in_memory_queue.item_added",
-                "added item to in-memory queue"
-            );
+        // Spilled datafeeds are enqueued first, so anything fetched while they're still spilled
+        // joins them to keep the queue in order
+        let enqueued = if spill_queue.is_empty() {
+            state.enqueue(payload.clone(), datafeed_updated_at).await
         } else {
-            *state.last_successful_update.write() = Some(now);
-            debug!(name:"fetcher_loop.datafeed.enqueued", "enqueued datafeed into Postgres queue");
+            Err(EnqueueError::SpillBacklog(spill_queue.len()))
+        };
+        match enqueued {
+            Ok(()) => {
+                state.health.record_success(now);
+                debug!(name:"fetcher_loop.datafeed.enqueued", "enqueued datafeed into Postgres queue");
+            }
+            Err(e) => {
+                warn!(name:"fetcher_loop.datafeed.enqueued", error = ?e, "could not enqueue datafeed into Postgres, spilling to disk");
+                state.health.record_error(e);
+                match spill_queue.push(&payload, datafeed_updated_at).await {
+                    Ok(dropped) => {
+                        metrics.dropped.add(dropped, &[]);
+                        info!(
+                            name: "fetcher_loop.spill.item_added",
+                            depth = spill_queue.len(),
+                            "added datafeed to spill queue"
+                        );
+                    }
+                    Err(e) => {
+                        error!(name: "fetcher_loop.spill.item_added", error = ?e, updated_at = ?datafeed_updated_at, "failed to spill datafeed, it will be lost");
+                    }
+                }
+                publish_spill_stats(state, spill_queue, &metrics);
+            }
         }
+        polling.last_enqueued_updated_at = Some(datafeed_updated_at);
 
         // If shutdown was requested during processing, break after finishing the iteration.
         if shutdown.is_cancelled() {
@@ -227,148 +325,123 @@ async fn fetcher_loop(
     Ok(())
 }
 
-async fn process_in_memory_queue(state: &FetcherState) {
-    let queue_len = state.in_memory_queue.read().len();
-    if queue_len == 0 {
-        return;
+impl Polling {
+    fn new(schedule: PollSchedule) -> Self {
+        Self {
+            schedule,
+            next_poll_at: None,
+            last_attempted_at: None,
+            last_enqueued_updated_at: None,
+            not_modified: 0,
+            unchanged: 0,
+        }
     }
 
-    clear_in_memory_queue(state).await;
+    fn publish(&self, health: &Health) {
+        let schedule = &self.schedule;
+        health.set_detail("consecutive_failures", schedule.consecutive_failures());
+        health.set_detail("next_poll_at", json!(self.next_poll_at));
+        health.set_detail("last_attempted_at", json!(self.last_attempted_at));
+        health.set_detail(
+            "update_cadence_seconds",
+            json!(schedule.cadence().map(TimeDelta::as_seconds_f64)),
+        );
+        health.set_detail(
+            "last_enqueued_updated_at",
+            json!(self.last_enqueued_updated_at),
+        );
+        health.set_detail("not_modified", self.not_modified);
+        health.set_detail("unchanged", self.unchanged);
+    }
 }
 
-#[instrument(skip(state))]
-async fn clear_in_memory_queue(state: &FetcherState) {
-    let queue_len = state.in_memory_queue.read().len();
+#[instrument(skip_all)]
+async fn drain_spill_queue(
+    state: &FetcherState,
+    spill_queue: &mut SpillQueue,
+    metrics: &SpillMetrics,
+) {
+    if spill_queue.is_empty() {
+        return;
+    }
     info!(
-        name = "fetcher_loop.in_memory_queue.processing.started",
-        count = queue_len,
-        "processing in-memory queue"
+        name: "fetcher_loop.spill.processing.started",
+        count = spill_queue.len(),
+        "draining spill queue"
     );
 
     loop {
-        let item = state.in_memory_queue.write().pop_front();
-
-        if let Some((payload, datafeed_updated_at)) = item {
-            match enqueue_datafeed(&state.db_pool, payload.clone(), datafeed_updated_at).await {
-                Ok(()) => {
-                    info!(
-                        name = "fetcher_loop.in_memory_queue.processing.item",
-                        "processed item from in-memory queue"
-                    );
-                }
-                Err(e) => {
-                    warn!(
-                        name = "fetcher_loop.in_memory_queue.item",
-                        error = ?e,
-                        "failed to process item from in-memory queue, will retry later"
-                    );
-                    state
-                        .in_memory_queue
-                        .write()
-                        .push_front((payload, datafeed_updated_at));
-                    break;
-                }
+        let (payload, datafeed_updated_at) = match spill_queue.front().await {
+            Ok(Some(item)) => item,
+            Ok(None) => break,
+            Err(e) => {
+                warn!(name: "fetcher_loop.spill.item", error = ?e, "failed to read spilled datafeed, will retry later");
+                break;
             }
-        } else {
+        };
+        if let Err(e) = state.enqueue(payload, datafeed_updated_at).await {
+            warn!(
+                name: "fetcher_loop.spill.item",
+                error = ?e,
+                "failed to enqueue spilled datafeed, will retry later"
+            );
+            state.health.record_error(e);
+            break;
+        }
+        if let Err(e) = spill_queue.pop_front().await {
+            // Enqueued already, so at worst it's enqueued again and discarded as a duplicate
+            warn!(name: "fetcher_loop.spill.item", error = ?e, "failed to remove spilled datafeed after enqueueing it");
             break;
         }
+        debug!(
+            name: "fetcher_loop.spill.processing.item",
+            updated_at = ?datafeed_updated_at,
+            "enqueued spilled datafeed"
+        );
     }
-    let queue_len_after = state.in_memory_queue.read().len();
-    if queue_len_after > 0 {
+    publish_spill_stats(state, spill_queue, metrics);
+
+    if spill_queue.is_empty() {
         info!(
-            name = "fetcher_loop.in_memory_queue.processing.paused",
-            count = queue_len_after,
-            "paused processing in-memory queue"
+            name: "fetcher_loop.spill.processing.ended",
+            "finished draining spill queue"
         );
     } else {
         info!(
-            name = "fetcher_loop.in_memory_queue.processing.ended",
-            "finished processing in-memory queue"
+            name: "fetcher_loop.spill.processing.paused",
+            count = spill_queue.len(),
+            "paused draining spill queue"
         );
     }
 }
 
-async fn run_health_server(
-    state: FetcherState,
-    shutdown: CancellationToken,
-) -> Result<(), std::io::Error> {
-    info!(name: "axum.initialized", "starting axum health server");
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .with_state(state);
-    let listener = TcpListener::bind("127.0.0.1:3000").await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown.cancelled_owned())
-        .await?;
-    Ok(())
-}
-
-async fn health_check(State(state): State<FetcherState>) -> impl IntoResponse {
-    let last_attempted_update = *state.last_attempted_update.read();
-    let last_successful_update = *state.last_successful_update.read();
-    let last_error = if let Some(e) = state.last_error.read().as_ref() {
-        format!("{e:?}")
-    } else {
-        "unknown".to_string()
-    };
-    let in_memory_queue_len = state.in_memory_queue.read().len();
-
-    if last_attempted_update.is_none() || last_successful_update.is_none() {
-        return if let Some(last_attempted_update) = last_attempted_update {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!(
-                    "Datafeed has not been successfully updated. Last attempted update: {last_attempted_update}. Last error: {last_error}. In-memory queue length: {in_memory_queue_len}"
-                ),
-            )
-        } else {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!(
-                    "No attempted or successful datafeed updates. In-memory queue length: {in_memory_queue_len}"
-                ),
-            )
-        };
-    }
-
-    // We can safely unwrap here because we checked is_none above
-    let last_attempted_update = last_attempted_update.unwrap();
-    let last_successful_update = last_successful_update.unwrap();
-    if (Utc::now() - last_successful_update) > TimeDelta::seconds(60) {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!(
-                "Datafeed not fetched in the last 60 seconds. Last successful update: {last_successful_update}. Last attempted updated: {last_attempted_update}. Last error: {last_error}. In-memory queue length: {in_memory_queue_len}"
-            ),
-        )
-    } else {
-        (
-            StatusCode::OK,
-            format!(
-                "Datafeed last successfully fetched: {last_successful_update}. In-memory queue length: {in_memory_queue_len}"
-            ),
-        )
-    }
+fn publish_spill_stats(state: &FetcherState, spill_queue: &SpillQueue, metrics: &SpillMetrics) {
+    let spill = spill_queue.stats();
+    metrics.depth.record(spill.depth as u64, &[]);
+    metrics.bytes.record(spill.bytes, &[]);
+    metrics.oldest_age.record(
+        spill
+            .oldest_age(Utc::now())
+            .map_or(0.0, TimeDelta::as_seconds_f64),
+        &[],
+    );
+    state.health.set_queue_depth(spill.depth as u64);
+    state.health.set_detail(
+        "spill",
+        json!({
+            "depth": spill.depth,
+            "bytes": spill.bytes,
+            "oldest_updated_at": spill.oldest_updated_at,
+            "dropped": spill.dropped,
+        }),
+    );
 }
 
-#[instrument(skip(client))]
-async fn fetch_datafeed(client: &reqwest::Client) -> Result<(Value, DateTime<Utc>), FetchError> {
-    let resp = client
-        .get(datafeed_url(VnasEnvironment::Live))
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
-
-    let value: Value = serde_json::from_str(&resp)?;
-    if let Some(timestamp_val) = value.get("updatedAt")
-        && let Some(timestamp_str) = timestamp_val.as_str()
-    {
-        let timestamp = DateTime::parse_from_rfc3339(timestamp_str)?;
-        Ok((value, timestamp.with_timezone(&Utc)))
-    } else {
-        Err(FetchError::MissingUpdatedAt)
+impl FetcherState {
+    async fn enqueue(&self, payload: Value, updated_at: DateTime<Utc>) -> Result<(), EnqueueError> {
+        self.metrics
+            .time_enqueue(enqueue_datafeed(&self.db_pool, payload, updated_at))
+            .await
     }
 }
 
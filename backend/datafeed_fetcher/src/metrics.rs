@@ -0,0 +1,96 @@
+use opentelemetry::metrics::{Counter, Gauge, Histogram};
+use opentelemetry::{KeyValue, global};
+use shared::vnas::fetch::{FetchError, FetchOutcome};
+use tokio::time::Instant;
+
+#[derive(Clone)]
+pub struct SpillMetrics {
+    pub depth: Gauge<u64>,
+    pub bytes: Gauge<u64>,
+    pub oldest_age: Gauge<f64>,
+    pub dropped: Counter<u64>,
+}
+
+impl Default for SpillMetrics {
+    fn default() -> Self {
+        let meter = global::meter("datafeed_fetcher");
+        let depth = meter.u64_gauge("spill.depth").build();
+        let bytes = meter.u64_gauge("spill.bytes").with_unit("B").build();
+        let oldest_age = meter.f64_gauge("spill.oldest_age").with_unit("s").build();
+        let dropped = meter.u64_counter("spill.dropped").build();
+
+        Self {
+            depth,
+            bytes,
+            oldest_age,
+            dropped,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FetchMetrics {
+    /// Datafeed fetches, by `outcome`
+    pub fetches: Counter<u64>,
+    /// Time taken to fetch and parse the datafeed, by `outcome`
+    pub fetch_duration: Histogram<f64>,
+    /// Time taken to enqueue a datafeed into Postgres, by `outcome`
+    pub enqueue_duration: Histogram<f64>,
+}
+
+impl Default for FetchMetrics {
+    fn default() -> Self {
+        let meter = global::meter("datafeed_fetcher");
+        let fetches = meter.u64_counter("datafeed.fetches").build();
+        let fetch_duration = meter
+            .f64_histogram("datafeed.fetch.duration")
+            .with_unit("s")
+            .build();
+        let enqueue_duration = meter
+            .f64_histogram("datafeed.enqueue.duration")
+            .with_unit("s")
+            .build();
+
+        Self {
+            fetches,
+            fetch_duration,
+            enqueue_duration,
+        }
+    }
+}
+
+impl FetchMetrics {
+    /// Awaits a fetch, recording it as `modified`, `not_modified` or `error`
+    pub async fn time_fetch(
+        &self,
+        fetch: impl Future<Output = Result<FetchOutcome, FetchError>>,
+    ) -> Result<FetchOutcome, FetchError> {
+        let started = Instant::now();
+        let res = fetch.await;
+        let outcome = match &res {
+            Ok(FetchOutcome::Modified { .. }) => "modified",
+            Ok(FetchOutcome::NotModified) => "not_modified",
+            Err(_) => "error",
+        };
+        let attributes = [KeyValue::new("outcome", outcome)];
+        self.fetches.add(1, &attributes);
+        self.fetch_duration
+            .record(started.elapsed().as_secs_f64(), &attributes);
+        res
+    }
+
+    /// Awaits an enqueue, recording it as `ok` or `error`
+    pub async fn time_enqueue<T, E>(
+        &self,
+        enqueue: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let started = Instant::now();
+        let res = enqueue.await;
+        let outcome = if res.is_ok() { "ok" } else { "error" };
+        self.enqueue_duration.record(
+            started.elapsed().as_secs_f64(),
+            &[KeyValue::new("outcome", outcome)],
+        );
+        res
+    }
+}
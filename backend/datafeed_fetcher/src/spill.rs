@@ -0,0 +1,386 @@
+use crate::error::SpillError;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::Value;
+use shared::SpillConfig;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, instrument, warn};
+
+const EXTENSION: &str = "json.zst";
+const TMP_EXTENSION: &str = "tmp";
+const CORRUPT_EXTENSION: &str = "corrupt";
+/// Spilled datafeeds are usually written during an outage, so favour speed over ratio
+const ZSTD_LEVEL: i32 = 3;
+
+/// Datafeeds that couldn't be enqueued into Postgres, kept on disk so they survive restarts.
+///
+/// Each datafeed is written once to its own zstd-compressed file named
+/// `<sequence>-<updated_at millis>.json.zst`, so the queue drains in the order datafeeds were
+/// spilled. The queue is bounded by both count and size; once either is exceeded the oldest
+/// datafeeds are dropped.
+#[derive(Debug)]
+pub struct SpillQueue {
+    directory: PathBuf,
+    max_items: usize,
+    max_bytes: u64,
+    entries: VecDeque<SpillEntry>,
+    bytes: u64,
+    next_sequence: u64,
+    /// Datafeeds dropped to stay within the bounds since startup
+    dropped: u64,
+}
+
+#[derive(Debug)]
+struct SpillEntry {
+    sequence: u64,
+    updated_at: DateTime<Utc>,
+    size: u64,
+}
+
+/// Point-in-time view of a [`SpillQueue`] for health checks and metrics
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpillStats {
+    pub depth: usize,
+    pub bytes: u64,
+    pub oldest_updated_at: Option<DateTime<Utc>>,
+    /// Datafeeds dropped to stay within the bounds since startup
+    pub dropped: u64,
+}
+
+impl SpillStats {
+    pub fn oldest_age(&self, now: DateTime<Utc>) -> Option<TimeDelta> {
+        self.oldest_updated_at.map(|oldest| now - oldest)
+    }
+}
+
+impl SpillQueue {
+    /// Opens the spill directory, creating it if needed, and picks up any datafeeds spilled before
+    /// a restart
+    #[instrument]
+    pub async fn open(config: &SpillConfig) -> Result<Self, SpillError> {
+        fs::create_dir_all(&config.directory).await?;
+
+        let mut entries = Vec::new();
+        let mut dir = fs::read_dir(&config.directory).await?;
+        while let Some(file) = dir.next_entry().await? {
+            let path = file.path();
+            if path.extension().is_some_and(|ext| ext == TMP_EXTENSION) {
+                // Left behind by a write that never completed
+                fs::remove_file(&path).await?;
+                continue;
+            }
+            let Some((sequence, updated_at)) = parse_file_name(&path) else {
+                continue;
+            };
+            let size = file.metadata().await?.len();
+            entries.push(SpillEntry {
+                sequence,
+                updated_at,
+                size,
+            });
+        }
+        entries.sort_unstable_by_key(|e| e.sequence);
+
+        let queue = Self {
+            directory: config.directory.clone(),
+            max_items: config.max_items.max(1),
+            max_bytes: config.max_bytes,
+            bytes: entries.iter().map(|e| e.size).sum(),
+            next_sequence: entries.last().map_or(0, |e| e.sequence + 1),
+            entries: entries.into(),
+            dropped: 0,
+        };
+        if !queue.is_empty() {
+            info!(
+                name: "spill.opened",
+                depth = queue.len(),
+                bytes = queue.bytes,
+                "found datafeeds spilled before restart"
+            );
+        }
+        Ok(queue)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn stats(&self) -> SpillStats {
+        SpillStats {
+            depth: self.entries.len(),
+            bytes: self.bytes,
+            oldest_updated_at: self.entries.front().map(|e| e.updated_at),
+            dropped: self.dropped,
+        }
+    }
+
+    /// Appends a datafeed to the queue, returning how many of the oldest datafeeds were dropped to
+    /// make room for it
+    #[instrument(skip(self, payload))]
+    pub async fn push(
+        &mut self,
+        payload: &Value,
+        updated_at: DateTime<Utc>,
+    ) -> Result<u64, SpillError> {
+        let compressed = zstd::encode_all(serde_json::to_vec(payload)?.as_slice(), ZSTD_LEVEL)?;
+        let sequence = self.next_sequence;
+        let path = self.path(sequence, updated_at);
+
+        // Written to a temporary file first so a crash never leaves a partial datafeed behind
+        let tmp_path = path.with_extension(TMP_EXTENSION);
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(&compressed).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        self.next_sequence += 1;
+        self.bytes += compressed.len() as u64;
+        self.entries.push_back(SpillEntry {
+            sequence,
+            updated_at,
+            size: compressed.len() as u64,
+        });
+
+        let mut dropped = 0;
+        while self.entries.len() > self.max_items
+            || (self.bytes > self.max_bytes && self.entries.len() > 1)
+        {
+            let oldest = self.remove_front().await?;
+            warn!(
+                name: "spill.dropped",
+                updated_at = ?oldest.updated_at,
+                "spill queue is full, dropped oldest datafeed"
+            );
+            dropped += 1;
+        }
+        self.dropped += dropped;
+        Ok(dropped)
+    }
+
+    /// Reads the oldest datafeed without removing it. A file that can't be decoded is renamed
+    /// aside with a `.corrupt` extension and skipped.
+    pub async fn front(&mut self) -> Result<Option<(Value, DateTime<Utc>)>, SpillError> {
+        while let Some(entry) = self.entries.front() {
+            let path = self.path(entry.sequence, entry.updated_at);
+            let updated_at = entry.updated_at;
+            match read_datafeed(&path).await {
+                Ok(payload) => return Ok(Some((payload, updated_at))),
+                Err(SpillError::Io(e)) => return Err(SpillError::Io(e)),
+                Err(e) => {
+                    warn!(
+                        name: "spill.corrupt",
+                        path = %path.display(),
+                        error = ?e,
+                        "could not decode spilled datafeed, moving it aside"
+                    );
+                    fs::rename(&path, path.with_extension(CORRUPT_EXTENSION)).await?;
+                    self.forget_front();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Removes the oldest datafeed, once it's been enqueued
+    pub async fn pop_front(&mut self) -> Result<(), SpillError> {
+        self.remove_front().await.map(|_| ())
+    }
+
+    async fn remove_front(&mut self) -> Result<SpillEntry, SpillError> {
+        let entry = self.forget_front().ok_or(SpillError::Empty)?;
+        fs::remove_file(self.path(entry.sequence, entry.updated_at)).await?;
+        Ok(entry)
+    }
+
+    fn forget_front(&mut self) -> Option<SpillEntry> {
+        let entry = self.entries.pop_front()?;
+        self.bytes -= entry.size;
+        Some(entry)
+    }
+
+    fn path(&self, sequence: u64, updated_at: DateTime<Utc>) -> PathBuf {
+        self.directory.join(format!(
+            "{sequence:020}-{}.{EXTENSION}",
+            updated_at.timestamp_millis()
+        ))
+    }
+}
+
+async fn read_datafeed(path: &Path) -> Result<Value, SpillError> {
+    let compressed = fs::read(path).await?;
+    let json = zstd::decode_all(compressed.as_slice()).map_err(SpillError::Decompress)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+fn parse_file_name(path: &Path) -> Option<(u64, DateTime<Utc>)> {
+    let name = path
+        .file_name()?
+        .to_str()?
+        .strip_suffix(&format!(".{EXTENSION}"))?;
+    let (sequence, updated_at) = name.split_once('-')?;
+    Some((
+        sequence.parse().ok()?,
+        DateTime::from_timestamp_millis(updated_at.parse().ok()?)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn config(max_items: usize, max_bytes: u64) -> SpillConfig {
+        SpillConfig {
+            directory: std::env::temp_dir().join(format!("spill-test-{}", Uuid::now_v7())),
+            max_items,
+            max_bytes,
+        }
+    }
+
+    fn datafeed(i: i64) -> (Value, DateTime<Utc>) {
+        let updated_at =
+            "2025-12-20T18:00:00Z".parse::<DateTime<Utc>>().unwrap() + TimeDelta::seconds(15 * i);
+        (
+            json!({ "updatedAt": updated_at, "controllers": [] }),
+            updated_at,
+        )
+    }
+
+    fn files_with_extension(directory: &Path, extension: &str) -> Vec<PathBuf> {
+        std::fs::read_dir(directory)
+            .unwrap()
+            .map(|file| file.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+            .collect()
+    }
+
+    async fn drain(queue: &mut SpillQueue) -> Vec<DateTime<Utc>> {
+        let mut drained = Vec::new();
+        while let Some((_, updated_at)) = queue.front().await.unwrap() {
+            drained.push(updated_at);
+            queue.pop_front().await.unwrap();
+        }
+        drained
+    }
+
+    #[tokio::test]
+    async fn keeps_order_across_reopen() {
+        let config = config(100, u64::MAX);
+        let mut queue = SpillQueue::open(&config).await.unwrap();
+        // Spilled out of `updatedAt` order, which the queue has to keep
+        for i in [2, 0, 1] {
+            let (payload, updated_at) = datafeed(i);
+            queue.push(&payload, updated_at).await.unwrap();
+        }
+        drop(queue);
+
+        let mut queue = SpillQueue::open(&config).await.unwrap();
+        assert_eq!(queue.len(), 3);
+        let (payload, updated_at) = datafeed(3);
+        queue.push(&payload, updated_at).await.unwrap();
+
+        let (front, _) = queue.front().await.unwrap().unwrap();
+        assert_eq!(front, datafeed(2).0);
+        let expected = [2, 0, 1, 3].map(|i| datafeed(i).1);
+        assert_eq!(drain(&mut queue).await, expected);
+        assert!(queue.is_empty());
+
+        std::fs::remove_dir_all(&config.directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drops_oldest_past_max_items() {
+        let config = config(2, u64::MAX);
+        let mut queue = SpillQueue::open(&config).await.unwrap();
+        let mut dropped = 0;
+        for i in 0..3 {
+            let (payload, updated_at) = datafeed(i);
+            dropped += queue.push(&payload, updated_at).await.unwrap();
+        }
+
+        assert_eq!(dropped, 1);
+        assert_eq!(queue.stats().dropped, 1);
+        assert_eq!(files_with_extension(&config.directory, "zst").len(), 2);
+        assert_eq!(drain(&mut queue).await, [1, 2].map(|i| datafeed(i).1));
+
+        std::fs::remove_dir_all(&config.directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn drops_oldest_past_max_bytes_but_keeps_newest() {
+        // Smaller than a single datafeed, so only the newest is ever kept
+        let config = config(100, 1);
+        let mut queue = SpillQueue::open(&config).await.unwrap();
+        let (payload, updated_at) = datafeed(0);
+        assert_eq!(queue.push(&payload, updated_at).await.unwrap(), 0);
+        let (payload, updated_at) = datafeed(1);
+        assert_eq!(queue.push(&payload, updated_at).await.unwrap(), 1);
+
+        let stats = queue.stats();
+        assert_eq!(stats.depth, 1);
+        assert_eq!(stats.oldest_updated_at, Some(datafeed(1).1));
+        assert_eq!(
+            stats.bytes,
+            std::fs::metadata(&files_with_extension(&config.directory, "zst")[0])
+                .unwrap()
+                .len()
+        );
+
+        std::fs::remove_dir_all(&config.directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn removes_leftover_tmp_files() {
+        let config = config(100, u64::MAX);
+        let mut queue = SpillQueue::open(&config).await.unwrap();
+        let (payload, updated_at) = datafeed(0);
+        queue.push(&payload, updated_at).await.unwrap();
+        drop(queue);
+        // As if the process died between writing and renaming
+        let tmp = config.directory.join(format!(
+            "{:020}-{}.json.tmp",
+            1,
+            datafeed(1).1.timestamp_millis()
+        ));
+        std::fs::write(&tmp, b"partial").unwrap();
+
+        let mut queue = SpillQueue::open(&config).await.unwrap();
+        assert!(!tmp.exists());
+        assert_eq!(drain(&mut queue).await, [datafeed(0).1]);
+
+        std::fs::remove_dir_all(&config.directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn moves_corrupt_entries_aside() {
+        let config = config(100, u64::MAX);
+        std::fs::create_dir_all(&config.directory).unwrap();
+        let corrupt = config.directory.join(format!(
+            "{:020}-{}.json.zst",
+            0,
+            datafeed(0).1.timestamp_millis()
+        ));
+        std::fs::write(&corrupt, b"not zstd").unwrap();
+
+        let mut queue = SpillQueue::open(&config).await.unwrap();
+        assert_eq!(queue.len(), 1);
+        let (payload, updated_at) = datafeed(1);
+        queue.push(&payload, updated_at).await.unwrap();
+
+        let (front, updated_at) = queue.front().await.unwrap().unwrap();
+        assert_eq!(front, payload);
+        assert_eq!(updated_at, datafeed(1).1);
+        assert_eq!(queue.len(), 1);
+        assert!(!corrupt.exists());
+        assert_eq!(files_with_extension(&config.directory, "corrupt").len(), 1);
+
+        std::fs::remove_dir_all(&config.directory).unwrap();
+    }
+}
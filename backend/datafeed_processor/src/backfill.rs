@@ -0,0 +1,87 @@
+use crate::database::queries::{
+    QueryError, backfill_session_activity_stats, fetch_high_water_mark,
+};
+use crate::error::BackfillError;
+use crate::maintenance::{last_complete_day, rollup_day};
+use chrono::{NaiveDate, NaiveTime, TimeDelta};
+use sqlx::{Pool, Postgres};
+use tracing::{info, instrument};
+
+#[derive(Debug, Default)]
+pub struct BackfillSummary {
+    pub days: u64,
+    pub inserted: u64,
+    pub updated: u64,
+}
+
+/// Recomputes `session_activity_stats` for every UTC day from `from` to `to` inclusive, from the
+/// `active_span` of controller, callsign and position sessions. Each day is written in its own
+/// statement so a long backfill doesn't hold one huge transaction, and can be resumed by running
+/// it again. Nothing is written past the current time, so `to` can be today. The rollups pick the
+/// rows up on the next maintenance run.
+#[instrument(skip(pool))]
+pub async fn backfill_activity_stats(
+    pool: &Pool<Postgres>,
+    from: NaiveDate,
+    to: NaiveDate,
+    step_seconds: i32,
+    overwrite: bool,
+) -> Result<BackfillSummary, QueryError> {
+    let mut summary = BackfillSummary::default();
+    for day in from.iter_days().take_while(|day| *day <= to) {
+        let start = day.and_time(NaiveTime::MIN).and_utc();
+        let (inserted, updated) = backfill_session_activity_stats(
+            pool,
+            start,
+            start + TimeDelta::days(1),
+            step_seconds,
+            overwrite,
+        )
+        .await?;
+        info!(
+            name: "activity.backfill.day_completed",
+            %day,
+            inserted,
+            updated,
+            "backfilled activity stats for day"
+        );
+
+        summary.days += 1;
+        summary.inserted += inserted;
+        summary.updated += updated;
+    }
+
+    Ok(summary)
+}
+
+/// Recomputes the daily session totals for every UTC day from `from` to `to` inclusive, e.g. after
+/// sessions were corrected. Days are recomputed even if they were already rolled up, but `to` must
+/// have ended before the newest applied datafeed, as it would be for maintenance. Returns the
+/// number of days and rows written.
+#[instrument(skip(pool))]
+pub async fn backfill_daily_rollups(
+    pool: &Pool<Postgres>,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<(u64, u64), BackfillError> {
+    let high_water_mark = fetch_high_water_mark(pool).await?;
+    if high_water_mark.is_none_or(|mark| to > last_complete_day(mark)) {
+        return Err(BackfillError::IncompleteDay { to });
+    }
+
+    let (mut days, mut rows) = (0, 0);
+    for day in from.iter_days().take_while(|day| *day <= to) {
+        let written = rollup_day(pool, day.and_time(NaiveTime::MIN).and_utc()).await?;
+        info!(
+            name: "activity.daily_rollup.day_completed",
+            %day,
+            rows = written,
+            "rolled up session totals for day"
+        );
+
+        days += 1;
+        rows += written;
+    }
+
+    Ok((days, rows))
+}
@@ -0,0 +1,72 @@
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use shared::compression::DEFAULT_MAX_DICTIONARY_SIZE;
+
+#[derive(Debug, Parser)]
+#[command(
+    about = "Processes queued vNAS datafeeds into controller, callsign and position sessions"
+)]
+pub struct Cli {
+    /// Runs a one-off maintenance command instead of the processor service
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Moves dead-lettered datafeeds that now deserialize back into the processing queue
+    RedriveDeadLetters {
+        /// Maximum number of dead letters to inspect
+        #[arg(long, default_value_t = 1000)]
+        limit: i64,
+        /// Only report which dead letters would be re-driven
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Trains a zstd dictionary from recently stored datafeeds. New payloads use it once the
+    /// processor is restarted with `compression.use_dictionary` enabled.
+    TrainDictionary {
+        /// Number of recent datafeeds to train on
+        #[arg(long, default_value_t = 1000)]
+        samples: i64,
+        /// Maximum size of the dictionary in bytes
+        #[arg(long, default_value_t = DEFAULT_MAX_DICTIONARY_SIZE)]
+        max_size: usize,
+    },
+    /// Recompresses every stored datafeed with the current compression settings
+    RecompressPayloads {
+        /// Number of datafeeds recompressed per transaction
+        #[arg(long, default_value_t = 500)]
+        batch_size: i64,
+    },
+    /// Rolls up activity stats and prunes rows past their retention once, as the processor does
+    /// every `maintenance.interval_seconds`
+    Maintain,
+    /// Recomputes activity stats for past UTC days from the sessions' active spans, so graphs
+    /// cover history from before the stats were recorded
+    BackfillActivityStats {
+        /// First day to backfill
+        #[arg(long)]
+        from: NaiveDate,
+        /// Last day to backfill, defaults to today. Snapshots stop at the current time.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        /// Seconds between computed snapshots, matching the datafeed's update interval
+        #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(i32).range(1..))]
+        step_seconds: i32,
+        /// Also recompute snapshots that already exist, rather than only filling gaps
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Recomputes the daily callsign, position, controller and ARTCC session totals for past UTC
+    /// days, which maintenance only rolls up once per day
+    BackfillDailyRollups {
+        /// First day to recompute
+        #[arg(long)]
+        from: NaiveDate,
+        /// Last day to recompute, defaults to yesterday. Must be before the day of the newest
+        /// applied datafeed, since later days' sessions can still change.
+        #[arg(long)]
+        to: Option<NaiveDate>,
+    },
+}
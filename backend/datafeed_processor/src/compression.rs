@@ -0,0 +1,156 @@
+use crate::database::queries::{
+    QueryError, fetch_payloads_to_recompress, fetch_recent_payloads, update_payload_compression,
+};
+use shared::compression::{
+    Codec, Dictionary, DictionaryCache, insert_dictionary, train_dictionary,
+};
+use sqlx::{Pool, Postgres};
+use std::sync::Arc;
+use tokio::time::{Duration, MissedTickBehavior, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
+
+#[derive(Debug, Default)]
+pub struct RecompressSummary {
+    pub recompressed: usize,
+    pub bytes_before: usize,
+    pub bytes_after: usize,
+}
+
+/// Trains a dictionary from the `samples` most recent stored payloads and stores it.
+#[instrument(skip(pool))]
+pub async fn train_and_store_dictionary(
+    pool: &Pool<Postgres>,
+    samples: i64,
+    max_size: usize,
+) -> Result<Dictionary, QueryError> {
+    let payloads = fetch_recent_payloads(pool, samples).await?;
+    let mut cache = DictionaryCache::default();
+    let mut decompressed = Vec::with_capacity(payloads.len());
+    for payload in &payloads {
+        decompressed.push(
+            cache
+                .decompress(
+                    pool,
+                    &payload.payload_compressed,
+                    &payload.compression_algo,
+                    payload.dictionary_id,
+                )
+                .await?,
+        );
+    }
+
+    let dictionary = train_dictionary(&decompressed, max_size)?;
+    let sample_count = i32::try_from(decompressed.len())?;
+    Ok(insert_dictionary(pool, dictionary, sample_count).await?)
+}
+
+/// Recompresses up to `batch_size` stored payloads that weren't written with `codec`, oldest first.
+#[instrument(skip(pool, codec, cache))]
+pub async fn recompress_batch(
+    pool: &Pool<Postgres>,
+    codec: &Codec,
+    cache: &mut DictionaryCache,
+    batch_size: i64,
+) -> Result<RecompressSummary, QueryError> {
+    let (algo, dictionary_id) = codec.target();
+    let mut tx = pool.begin().await?;
+    let payloads = fetch_payloads_to_recompress(&mut *tx, algo, dictionary_id, batch_size).await?;
+    let mut summary = RecompressSummary::default();
+
+    for payload in payloads {
+        let decompressed = cache
+            .decompress(
+                &mut *tx,
+                &payload.payload_compressed,
+                &payload.compression_algo,
+                payload.dictionary_id,
+            )
+            .await?;
+        let compressed = codec.compress(&decompressed)?;
+
+        summary.recompressed += 1;
+        summary.bytes_before += payload.payload_compressed.len();
+        summary.bytes_after += compressed.bytes.len();
+        update_payload_compression(
+            tx.as_mut(),
+            payload.id,
+            compressed.bytes,
+            compressed.algo,
+            compressed.dictionary_id,
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(summary)
+}
+
+/// Recompresses batches until every stored payload has been written with `codec`.
+pub async fn recompress_all(
+    pool: &Pool<Postgres>,
+    codec: &Codec,
+    batch_size: i64,
+) -> Result<RecompressSummary, QueryError> {
+    let mut cache = DictionaryCache::default();
+    if let Some(dictionary) = &codec.dictionary {
+        cache.insert(Arc::clone(dictionary));
+    }
+
+    let mut total = RecompressSummary::default();
+    loop {
+        let batch = recompress_batch(pool, codec, &mut cache, batch_size).await?;
+        if batch.recompressed == 0 {
+            return Ok(total);
+        }
+        info!(
+            name: "compression.recompress.batch_completed",
+            recompressed = batch.recompressed,
+            bytes_before = batch.bytes_before,
+            bytes_after = batch.bytes_after,
+            "recompressed batch of stored datafeeds"
+        );
+        total.recompressed += batch.recompressed;
+        total.bytes_before += batch.bytes_before;
+        total.bytes_after += batch.bytes_after;
+    }
+}
+
+/// Recompresses one batch of stored payloads every `period` until shutdown. Failures are logged
+/// and retried on the next tick rather than stopping the processor.
+pub async fn run_recompression_loop(
+    pool: Pool<Postgres>,
+    codec: Codec,
+    period: Duration,
+    batch_size: i64,
+    shutdown: CancellationToken,
+) {
+    let mut cache = DictionaryCache::default();
+    if let Some(dictionary) = &codec.dictionary {
+        cache.insert(Arc::clone(dictionary));
+    }
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            () = shutdown.cancelled() => {
+                info!(name: "compression.recompress.shutdown.received", "shutdown requested, exiting recompression loop");
+                break;
+            }
+            _ = ticker.tick() => {
+                match recompress_batch(&pool, &codec, &mut cache, batch_size).await {
+                    Ok(summary) if summary.recompressed > 0 => info!(
+                        name: "compression.recompress.batch_completed",
+                        recompressed = summary.recompressed,
+                        bytes_before = summary.bytes_before,
+                        bytes_after = summary.bytes_after,
+                        "recompressed batch of stored datafeeds"
+                    ),
+                    Ok(_) => debug!(name: "compression.recompress.batch_completed", "no stored datafeeds left to recompress"),
+                    Err(e) => warn!(name: "compression.recompress.batch_failed", error = ?e, "failed to recompress stored datafeeds"),
+                }
+            }
+        }
+    }
+}
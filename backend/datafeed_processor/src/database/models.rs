@@ -44,6 +44,30 @@ pub struct QueuedDatafeed {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, sqlx::FromRow, Clone)]
+pub struct ArtccActivity {
+    pub artcc_id: String,
+    pub active_controllers: i32,
+    pub active_callsigns: i32,
+    pub active_positions: i32,
+}
+
+#[derive(Debug, sqlx::FromRow, Clone, Copy)]
+pub struct QueueStats {
+    pub depth: i64,
+    pub oldest_created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, sqlx::FromRow, Clone)]
+pub struct DeadLetter {
+    pub id: Uuid,
+    pub queue_id: Uuid,
+    pub updated_at: DateTime<Utc>,
+    pub payload: Value,
+    pub enqueued_at: DateTime<Utc>,
+}
+
+#[allow(dead_code)]
 #[derive(Debug, sqlx::FromRow, Clone)]
 pub struct CallsignSession {
     pub id: Uuid,
@@ -57,6 +81,7 @@ pub struct CallsignSession {
     pub created_at: DateTime<Utc>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, sqlx::FromRow, Clone)]
 pub struct PositionSession {
     pub id: Uuid,
@@ -69,6 +94,8 @@ pub struct PositionSession {
     pub created_at: DateTime<Utc>,
 }
 
+// Only read through its Debug impl when logging session changes
+#[allow(dead_code)]
 #[derive(Clone, Debug, sqlx::FromRow)]
 pub struct PositionSessionDetails {
     pub id: Uuid,
@@ -92,10 +119,11 @@ pub enum UserRating {
     Instructor3,
     Supervisor,
     Administrator,
+    Unknown,
 }
 
-impl From<DatafeedUserRating> for UserRating {
-    fn from(value: DatafeedUserRating) -> Self {
+impl From<&DatafeedUserRating> for UserRating {
+    fn from(value: &DatafeedUserRating) -> Self {
         match value {
             DatafeedUserRating::Observer => Self::Observer,
             DatafeedUserRating::Student1 => Self::Student1,
@@ -109,6 +137,7 @@ impl From<DatafeedUserRating> for UserRating {
             DatafeedUserRating::Instructor3 => Self::Instructor3,
             DatafeedUserRating::Supervisor => Self::Supervisor,
             DatafeedUserRating::Administrator => Self::Administrator,
+            DatafeedUserRating::Unknown(_) => Self::Unknown,
         }
     }
 }
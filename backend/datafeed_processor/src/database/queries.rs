@@ -1,10 +1,12 @@
 use crate::database::models::{
-    ActiveSessionKey, CallsignSession, PositionSession, PositionSessionDetails, QueuedDatafeed,
-    UserRating,
+    ActiveSessionKey, ArtccActivity, CallsignSession, DeadLetter, PositionSession,
+    PositionSessionDetails, QueueStats, QueuedDatafeed, UserRating,
 };
 use crate::metrics::DatafeedsMetrics;
 use chrono::{DateTime, Utc};
-use opentelemetry::KeyValue;
+use shared::DATAFEED_PROCESSED_CHANNEL;
+use shared::compression::{Codec, CompressionError};
+use shared::payloads::{EncodedPayload, StoredPayload};
 use shared::vnas::datafeed::Controller;
 use sqlx::{Executor, Postgres};
 use std::num::TryFromIntError;
@@ -19,7 +21,7 @@ pub enum QueryError {
     #[error("payload serialization failed: {0}")]
     Serialize(#[from] serde_json::Error),
     #[error("payload compression failed: {0}")]
-    Compress(#[from] std::io::Error),
+    Compression(#[from] CompressionError),
     #[error("payload too large: {0}")]
     PayloadTooLarge(#[from] TryFromIntError),
 }
@@ -58,10 +60,12 @@ where
 
 /// Returns a tuple `(Uuid, bool)` where the Uuid is the payload primary key in the
 /// database and the bool indicates whether a new row was inserted
-#[instrument(level = "debug", skip(executor, message, metrics))]
+#[instrument(level = "debug", skip(executor, message, encoded, codec, metrics))]
 pub async fn upsert_datafeed_payload<'e, E>(
     executor: &mut E,
     message: &QueuedDatafeed,
+    encoded: &EncodedPayload,
+    codec: &Codec,
     metrics: &DatafeedsMetrics,
 ) -> Result<(Uuid, bool), QueryError>
 where
@@ -69,8 +73,8 @@ where
 {
     let payload_bytes = serde_json::to_vec(&message.payload)?;
     let original_size = i32::try_from(payload_bytes.len()).map_err(QueryError::PayloadTooLarge)?;
-    let payload_compressed = zstd::encode_all(payload_bytes.as_slice(), 3)?;
-    let payload_compressed_size = payload_compressed.len();
+    let compressed = codec.compress(&encoded.bytes)?;
+    let payload_compressed_size = compressed.bytes.len();
 
     if let Some(id) = sqlx::query_scalar::<_, Uuid>(
         r"
@@ -80,21 +84,35 @@ where
             payload_compressed,
             original_size_bytes,
             compression_algo,
+            dictionary_id,
+            payload_kind,
+            base_payload_id,
             created_at
         )
-        VALUES ($1, $2, $3, $4, 'zstd', $5)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
         ON CONFLICT (updated_at) DO NOTHING
         RETURNING id
         ",
     )
     .bind(Uuid::now_v7())
     .bind(message.updated_at)
-    .bind(payload_compressed)
+    .bind(compressed.bytes)
     .bind(original_size)
+    .bind(compressed.algo)
+    .bind(compressed.dictionary_id)
+    .bind(encoded.kind)
+    .bind(encoded.base_payload_id)
     .bind(message.created_at)
     .fetch_optional(&mut *executor)
     .await?
     {
+        // Add metrics to track size of datafeeds
+        metrics
+            .bytes_uncompressed
+            .add(payload_bytes.len() as u64, &[]);
+        metrics
+            .bytes_compressed
+            .add(payload_compressed_size as u64, &[]);
         return Ok((id, true));
     }
 
@@ -104,19 +122,30 @@ where
             .fetch_one(&mut *executor)
             .await?;
 
-    // Add metrics to track size of datafeeds
-    metrics.bytes_uncompressed.add(
-        original_size as u64,
-        &[KeyValue::new("updated_at", message.updated_at.to_string())],
-    );
-    metrics.bytes_compressed.add(
-        payload_compressed_size as u64,
-        &[KeyValue::new("updated_at", message.updated_at.to_string())],
-    );
-
     Ok((existing_id, false))
 }
 
+/// Tells `data_api` that sessions have been updated up to the datafeed at `updated_at`. Like any
+/// `NOTIFY`, it's only delivered once the transaction commits.
+#[instrument(level = "debug", skip(executor))]
+pub async fn notify_datafeed_processed<'e, E>(
+    executor: &mut E,
+    updated_at: DateTime<Utc>,
+) -> Result<(), QueryError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(DATAFEED_PROCESSED_CHANNEL)
+        .bind(updated_at.to_rfc3339())
+        .execute(&mut *executor)
+        .await
+        .map(|_| ())
+        .map_err(QueryError::from)
+}
+
+/// `out_of_order` marks a datafeed that was stored without being applied to sessions, because a
+/// newer one had already been applied
 #[instrument(level = "debug", skip(executor))]
 pub async fn insert_datafeed_message<'e, E>(
     executor: &mut E,
@@ -124,14 +153,22 @@ pub async fn insert_datafeed_message<'e, E>(
     payload_id: Uuid,
     enqueued_at: DateTime<Utc>,
     processed_at: DateTime<Utc>,
+    out_of_order: bool,
 ) -> Result<(), QueryError>
 where
     for<'c> &'c mut E: Executor<'c, Database = Postgres>,
 {
     sqlx::query(
         r"
-        INSERT INTO datafeed_messages (id, queue_id, payload_id, enqueued_at, processed_at)
-        VALUES ($1, $2, $3, $4, $5)
+        INSERT INTO datafeed_messages (
+            id,
+            queue_id,
+            payload_id,
+            enqueued_at,
+            processed_at,
+            out_of_order
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
         ",
     )
     .bind(Uuid::now_v7())
@@ -139,6 +176,7 @@ where
     .bind(payload_id)
     .bind(enqueued_at)
     .bind(processed_at)
+    .bind(out_of_order)
     .execute(&mut *executor)
     .await
     .map(|_| ())
@@ -158,6 +196,240 @@ where
         .map_err(QueryError::from)
 }
 
+/// Moves a queued datafeed that could not be deserialized into `datafeed_dead_letters`. The caller
+/// is still responsible for deleting it from the queue.
+#[instrument(level = "debug", skip(executor, message))]
+pub async fn insert_dead_letter<'e, E>(
+    executor: &mut E,
+    message: &QueuedDatafeed,
+    error: &str,
+) -> Result<(), QueryError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query(
+        r"
+        INSERT INTO datafeed_dead_letters (id, queue_id, updated_at, payload, error, enqueued_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (queue_id) DO UPDATE
+        SET error = EXCLUDED.error,
+            dead_lettered_at = now()
+        ",
+    )
+    .bind(Uuid::now_v7())
+    .bind(message.id)
+    .bind(message.updated_at)
+    .bind(&message.payload)
+    .bind(error)
+    .bind(message.created_at)
+    .execute(&mut *executor)
+    .await
+    .map(|_| ())
+    .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn count_dead_letters<'e, E>(executor: E) -> Result<i64, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar::<_, i64>("SELECT count(*) FROM datafeed_dead_letters")
+        .fetch_one(executor)
+        .await
+        .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_queue_stats<'e, E>(executor: E) -> Result<QueueStats, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, QueueStats>(
+        r"
+        SELECT count(*) AS depth, min(created_at) AS oldest_created_at
+        FROM datafeed_queue
+        ",
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(QueryError::from)
+}
+
+/// `updated_at` of the newest stored datafeed. Datafeeds are only stored out of order without
+/// being applied, so this is also the newest datafeed applied to sessions.
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_high_water_mark<'e, E>(executor: E) -> Result<Option<DateTime<Utc>>, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar::<_, Option<DateTime<Utc>>>("SELECT max(updated_at) FROM datafeed_payloads")
+        .fetch_one(executor)
+        .await
+        .map_err(QueryError::from)
+}
+
+/// Returns up to `limit` of the most recently updated stored payloads
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_recent_payloads<'e, E>(
+    executor: E,
+    limit: i64,
+) -> Result<Vec<StoredPayload>, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, StoredPayload>(
+        r"
+        SELECT id, updated_at, payload_kind, base_payload_id, payload_compressed,
+               compression_algo, dictionary_id
+        FROM datafeed_payloads
+        ORDER BY updated_at DESC
+        LIMIT $1
+        ",
+    )
+    .bind(limit)
+    .fetch_all(executor)
+    .await
+    .map_err(QueryError::from)
+}
+
+/// Returns up to `limit` stored payloads, oldest first, that weren't compressed with the given
+/// `compression_algo` and `dictionary_id`
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_payloads_to_recompress<'e, E>(
+    executor: E,
+    algo: &str,
+    dictionary_id: Option<Uuid>,
+    limit: i64,
+) -> Result<Vec<StoredPayload>, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, StoredPayload>(
+        r"
+        SELECT id, updated_at, payload_kind, base_payload_id, payload_compressed,
+               compression_algo, dictionary_id
+        FROM datafeed_payloads
+        WHERE (compression_algo, dictionary_id) IS DISTINCT FROM ($1::text, $2::uuid)
+        ORDER BY updated_at
+        FOR UPDATE SKIP LOCKED
+        LIMIT $3
+        ",
+    )
+    .bind(algo)
+    .bind(dictionary_id)
+    .bind(limit)
+    .fetch_all(executor)
+    .await
+    .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor, payload_compressed))]
+pub async fn update_payload_compression<'e, E>(
+    executor: &mut E,
+    id: Uuid,
+    payload_compressed: Vec<u8>,
+    algo: &str,
+    dictionary_id: Option<Uuid>,
+) -> Result<(), QueryError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query(
+        r"
+        UPDATE datafeed_payloads
+        SET payload_compressed = $2,
+            compression_algo = $3,
+            dictionary_id = $4
+        WHERE id = $1
+        ",
+    )
+    .bind(id)
+    .bind(payload_compressed)
+    .bind(algo)
+    .bind(dictionary_id)
+    .execute(&mut *executor)
+    .await
+    .map(|_| ())
+    .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_dead_letters<'e, E>(
+    executor: E,
+    limit: i64,
+) -> Result<Vec<DeadLetter>, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, DeadLetter>(
+        r"
+        SELECT id, queue_id, updated_at, payload, enqueued_at
+        FROM datafeed_dead_letters
+        ORDER BY updated_at
+        FOR UPDATE SKIP LOCKED
+        LIMIT $1
+        ",
+    )
+    .bind(limit)
+    .fetch_all(executor)
+    .await
+    .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn update_dead_letter_error<'e, E>(
+    executor: &mut E,
+    id: Uuid,
+    error: &str,
+) -> Result<(), QueryError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query("UPDATE datafeed_dead_letters SET error = $2 WHERE id = $1")
+        .bind(id)
+        .bind(error)
+        .execute(&mut *executor)
+        .await
+        .map(|_| ())
+        .map_err(QueryError::from)
+}
+
+/// Moves a dead letter back into `datafeed_queue` under its original queue id and notifies any
+/// listening processors.
+#[instrument(level = "debug", skip(executor, dead_letter), fields(id = %dead_letter.id))]
+pub async fn requeue_dead_letter<'e, E>(
+    executor: &mut E,
+    dead_letter: &DeadLetter,
+) -> Result<(), QueryError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query(
+        r"
+        INSERT INTO datafeed_queue (id, updated_at, payload, created_at)
+        VALUES ($1, $2, $3, $4)
+        ",
+    )
+    .bind(dead_letter.queue_id)
+    .bind(dead_letter.updated_at)
+    .bind(&dead_letter.payload)
+    .bind(dead_letter.enqueued_at)
+    .execute(&mut *executor)
+    .await?;
+
+    sqlx::query("DELETE FROM datafeed_dead_letters WHERE id = $1")
+        .bind(dead_letter.id)
+        .execute(&mut *executor)
+        .await?;
+
+    sqlx::query("SELECT pg_notify('datafeed_queue', $1)")
+        .bind(dead_letter.queue_id.to_string())
+        .execute(&mut *executor)
+        .await
+        .map(|_| ())
+        .map_err(QueryError::from)
+}
+
 #[instrument(level = "debug", skip(executor))]
 pub async fn get_active_controller_session_keys<'e, E>(
     executor: E,
@@ -189,8 +461,8 @@ pub async fn insert_controller_session<'e, E>(
 where
     E: Executor<'e, Database = Postgres>,
 {
-    let user_rating: UserRating = controller.vatsim_data.user_rating.into();
-    let requested_rating: UserRating = controller.vatsim_data.requested_rating.into();
+    let user_rating = UserRating::from(&controller.vatsim_data.user_rating);
+    let requested_rating = UserRating::from(&controller.vatsim_data.requested_rating);
     let id = Uuid::now_v7();
 
     sqlx::query(
@@ -248,8 +520,8 @@ pub async fn update_active_controller_session<'e, E>(
 where
     E: Executor<'e, Database = Postgres>,
 {
-    let user_rating: UserRating = controller.vatsim_data.user_rating.into();
-    let requested_rating: UserRating = controller.vatsim_data.requested_rating.into();
+    let user_rating = UserRating::from(&controller.vatsim_data.user_rating);
+    let requested_rating = UserRating::from(&controller.vatsim_data.requested_rating);
 
     sqlx::query(
         r"
@@ -641,3 +913,747 @@ where
     .map(|_| ())
     .map_err(QueryError::from)
 }
+
+/// Records how many of the given active sessions fall under each root ARTCC, with a row for every
+/// ARTCC (including idle ones) so per-ARTCC curves have no gaps. Sessions on positions that aren't
+/// in `facility_positions` aren't attributed to any ARTCC. Returns the rows inserted.
+#[instrument(level = "debug", skip(executor, controller_session_ids, position_ids))]
+pub async fn insert_artcc_activity_stats<'e, E>(
+    executor: E,
+    observed_at: DateTime<Utc>,
+    controller_session_ids: &[Uuid],
+    position_ids: &[String],
+) -> Result<Vec<ArtccActivity>, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, ArtccActivity>(
+        r"
+        WITH controllers AS (
+            SELECT f.root_artcc_id AS artcc_id, cs.callsign_session_id
+            FROM controller_sessions cs
+            JOIN facility_positions fp ON fp.id = cs.primary_position_id
+            JOIN facilities f ON f.id = fp.facility_id
+            WHERE cs.id = ANY($2)
+        ),
+        positions AS (
+            SELECT f.root_artcc_id AS artcc_id
+            FROM facility_positions fp
+            JOIN facilities f ON f.id = fp.facility_id
+            WHERE fp.id = ANY($3)
+        )
+        INSERT INTO artcc_activity_stats (
+            artcc_id,
+            observed_at,
+            active_controllers,
+            active_callsigns,
+            active_positions
+        )
+        SELECT
+            a.id,
+            $1,
+            (SELECT count(*) FROM controllers c WHERE c.artcc_id = a.id),
+            (SELECT count(DISTINCT c.callsign_session_id) FROM controllers c WHERE c.artcc_id = a.id),
+            (SELECT count(*) FROM positions p WHERE p.artcc_id = a.id)
+        FROM facilities a
+        WHERE a.facility_type = 'Artcc'
+        ON CONFLICT (artcc_id, observed_at) DO NOTHING
+        RETURNING artcc_id, active_controllers, active_callsigns, active_positions
+        ",
+    )
+    .bind(observed_at)
+    .bind(controller_session_ids)
+    .bind(position_ids)
+    .fetch_all(executor)
+    .await
+    .map_err(QueryError::from)
+}
+
+/// Records how many of the given active sessions fall under each facility. Unlike the per-ARTCC
+/// stats, only facilities with at least one active session get a row.
+#[instrument(level = "debug", skip(executor, controller_session_ids, position_ids))]
+pub async fn insert_facility_activity_stats<'e, E>(
+    executor: E,
+    observed_at: DateTime<Utc>,
+    controller_session_ids: &[Uuid],
+    position_ids: &[String],
+) -> Result<u64, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query(
+        r"
+        WITH controllers AS (
+            SELECT f.id AS facility_id, f.root_artcc_id AS artcc_id, cs.callsign_session_id
+            FROM controller_sessions cs
+            JOIN facility_positions fp ON fp.id = cs.primary_position_id
+            JOIN facilities f ON f.id = fp.facility_id
+            WHERE cs.id = ANY($2)
+        ),
+        positions AS (
+            SELECT f.id AS facility_id, f.root_artcc_id AS artcc_id
+            FROM facility_positions fp
+            JOIN facilities f ON f.id = fp.facility_id
+            WHERE fp.id = ANY($3)
+        )
+        INSERT INTO facility_activity_stats (
+            facility_id,
+            observed_at,
+            artcc_id,
+            active_controllers,
+            active_callsigns,
+            active_positions
+        )
+        SELECT
+            facility_id,
+            $1,
+            artcc_id,
+            coalesce(c.controllers, 0),
+            coalesce(c.callsigns, 0),
+            coalesce(p.positions, 0)
+        FROM (
+            SELECT
+                facility_id,
+                artcc_id,
+                count(*) AS controllers,
+                count(DISTINCT callsign_session_id) AS callsigns
+            FROM controllers
+            GROUP BY facility_id, artcc_id
+        ) c
+        FULL JOIN (
+            SELECT facility_id, artcc_id, count(*) AS positions
+            FROM positions
+            GROUP BY facility_id, artcc_id
+        ) p USING (facility_id, artcc_id)
+        ON CONFLICT (facility_id, observed_at) DO NOTHING
+        ",
+    )
+    .bind(observed_at)
+    .bind(controller_session_ids)
+    .bind(position_ids)
+    .execute(executor)
+    .await
+    .map(|r| r.rows_affected())
+    .map_err(QueryError::from)
+}
+
+/// Recomputes `session_activity_stats` every `step_seconds` in `[from, to)` from the sessions'
+/// active spans. The window stops at `now()`, since sessions aren't known past it. Steps that
+/// already have a snapshot are skipped unless `overwrite` is set, in which case existing snapshots
+/// in the window are recomputed too. Returns the number of rows inserted and updated.
+#[instrument(level = "debug", skip(executor))]
+pub async fn backfill_session_activity_stats<'e, E>(
+    executor: E,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    step_seconds: i32,
+    overwrite: bool,
+) -> Result<(u64, u64), QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let inserted = sqlx::query_scalar::<_, bool>(
+        r"
+        WITH points AS (
+            SELECT t AS observed_at
+            FROM generate_series($1::timestamptz, LEAST($2::timestamptz, now()), $3 * interval '1 second') AS t
+            WHERE t < LEAST($2::timestamptz, now())
+              AND NOT EXISTS (
+                  SELECT 1
+                  FROM session_activity_stats s
+                  WHERE s.observed_at >= t AND s.observed_at < t + $3 * interval '1 second'
+              )
+            UNION
+            SELECT observed_at
+            FROM session_activity_stats
+            WHERE $4 AND observed_at >= $1 AND observed_at < $2
+        )
+        INSERT INTO session_activity_stats (
+            observed_at,
+            active_controllers,
+            active_callsigns,
+            active_positions
+        )
+        SELECT
+            p.observed_at,
+            (SELECT count(*) FROM controller_sessions c WHERE c.active_span @> p.observed_at),
+            (SELECT count(*) FROM callsign_sessions c WHERE c.active_span @> p.observed_at),
+            (SELECT count(*) FROM position_sessions c WHERE c.active_span @> p.observed_at)
+        FROM points p
+        ON CONFLICT (observed_at) DO UPDATE
+        SET active_controllers = EXCLUDED.active_controllers,
+            active_callsigns = EXCLUDED.active_callsigns,
+            active_positions = EXCLUDED.active_positions,
+            -- Bumped so the rollups pick up recomputed snapshots
+            created_at = now()
+        RETURNING xmax = 0
+        ",
+    )
+    .bind(from)
+    .bind(to)
+    .bind(step_seconds)
+    .bind(overwrite)
+    .fetch_all(executor)
+    .await?;
+
+    let total = inserted.len() as u64;
+    let inserted = inserted.into_iter().filter(|inserted| *inserted).count() as u64;
+    Ok((inserted, total - inserted))
+}
+
+/// A resolution that `session_activity_stats` is rolled up into. Each is rolled up from the
+/// previous one, starting from the raw table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivityRollup {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl ActivityRollup {
+    /// In the order they have to be rolled up
+    pub const ALL: [Self; 3] = [Self::Minute, Self::Hour, Self::Day];
+
+    const fn table(self) -> &'static str {
+        match self {
+            Self::Minute => "session_activity_stats_1m",
+            Self::Hour => "session_activity_stats_1h",
+            Self::Day => "session_activity_stats_1d",
+        }
+    }
+
+    const fn unit(self) -> &'static str {
+        match self {
+            Self::Minute => "minute",
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+
+    /// Bucket width. Days are 24 hours, since buckets are in UTC.
+    const fn width(self) -> &'static str {
+        match self {
+            Self::Minute => "1 minute",
+            Self::Hour => "1 hour",
+            Self::Day => "24 hours",
+        }
+    }
+}
+
+/// Recomputes every `rollup` bucket whose source rows were written since the rollup last ran.
+/// Returns the number of buckets written.
+///
+/// Source rows are found by when they were written rather than when they were observed, so
+/// backlogged or backfilled stats are rolled up too. The few minutes of overlap with the previous
+/// run covers transactions that were still in flight during it.
+#[instrument(level = "debug", skip(executor))]
+pub async fn rollup_session_activity<'e, E>(
+    executor: E,
+    rollup: ActivityRollup,
+) -> Result<u64, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    let (table, unit, width) = (rollup.table(), rollup.unit(), rollup.width());
+    let query = match rollup {
+        ActivityRollup::Minute => format!(
+            r"
+            WITH touched AS (
+                SELECT DISTINCT date_trunc('{unit}', observed_at, 'UTC') AS bucket
+                FROM session_activity_stats
+                WHERE created_at > (
+                    SELECT coalesce(max(updated_at), '-infinity') FROM {table}
+                ) - interval '5 minutes'
+            )
+            INSERT INTO {table} (
+                bucket, samples,
+                min_controllers, max_controllers, avg_controllers,
+                min_callsigns, max_callsigns, avg_callsigns,
+                min_positions, max_positions, avg_positions
+            )
+            SELECT
+                t.bucket,
+                count(*),
+                min(s.active_controllers), max(s.active_controllers), avg(s.active_controllers),
+                min(s.active_callsigns), max(s.active_callsigns), avg(s.active_callsigns),
+                min(s.active_positions), max(s.active_positions), avg(s.active_positions)
+            FROM touched t
+            JOIN session_activity_stats s
+              ON s.observed_at >= t.bucket AND s.observed_at < t.bucket + interval '{width}'
+            GROUP BY t.bucket
+            "
+        ),
+        ActivityRollup::Hour | ActivityRollup::Day => {
+            let source = if rollup == ActivityRollup::Hour {
+                ActivityRollup::Minute.table()
+            } else {
+                ActivityRollup::Hour.table()
+            };
+            format!(
+                r"
+                WITH touched AS (
+                    SELECT DISTINCT date_trunc('{unit}', bucket, 'UTC') AS bucket
+                    FROM {source}
+                    WHERE updated_at > (
+                        SELECT coalesce(max(updated_at), '-infinity') FROM {table}
+                    ) - interval '5 minutes'
+                )
+                INSERT INTO {table} (
+                    bucket, samples,
+                    min_controllers, max_controllers, avg_controllers,
+                    min_callsigns, max_callsigns, avg_callsigns,
+                    min_positions, max_positions, avg_positions
+                )
+                SELECT
+                    t.bucket,
+                    sum(s.samples),
+                    min(s.min_controllers), max(s.max_controllers),
+                    sum(s.avg_controllers * s.samples) / sum(s.samples),
+                    min(s.min_callsigns), max(s.max_callsigns),
+                    sum(s.avg_callsigns * s.samples) / sum(s.samples),
+                    min(s.min_positions), max(s.max_positions),
+                    sum(s.avg_positions * s.samples) / sum(s.samples)
+                FROM touched t
+                JOIN {source} s
+                  ON s.bucket >= t.bucket AND s.bucket < t.bucket + interval '{width}'
+                GROUP BY t.bucket
+                "
+            )
+        }
+    };
+
+    let query = format!(
+        r"
+        {query}
+        ON CONFLICT (bucket) DO UPDATE
+        SET samples = EXCLUDED.samples,
+            min_controllers = EXCLUDED.min_controllers,
+            max_controllers = EXCLUDED.max_controllers,
+            avg_controllers = EXCLUDED.avg_controllers,
+            min_callsigns = EXCLUDED.min_callsigns,
+            max_callsigns = EXCLUDED.max_callsigns,
+            avg_callsigns = EXCLUDED.avg_callsigns,
+            min_positions = EXCLUDED.min_positions,
+            max_positions = EXCLUDED.max_positions,
+            avg_positions = EXCLUDED.avg_positions,
+            updated_at = now()
+        "
+    );
+
+    sqlx::query(&query)
+        .execute(executor)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(QueryError::from)
+}
+
+/// A table of per-day session totals. Each is computed straight from the sessions, so they can
+/// be rolled up in any order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DailyRollup {
+    Callsign,
+    Position,
+    Controller,
+    Artcc,
+}
+
+impl DailyRollup {
+    pub const ALL: [Self; 4] = [
+        Self::Callsign,
+        Self::Position,
+        Self::Controller,
+        Self::Artcc,
+    ];
+
+    const fn table(self) -> &'static str {
+        match self {
+            Self::Callsign => "callsign_daily_stats",
+            Self::Position => "position_daily_stats",
+            Self::Controller => "controller_daily_stats",
+            Self::Artcc => "artcc_daily_stats",
+        }
+    }
+
+    /// Columns the totals are grouped by, besides the day
+    const fn keys(self) -> &'static str {
+        match self {
+            Self::Callsign => "prefix, suffix",
+            Self::Position => "position_id",
+            Self::Controller => "cid, connected_callsign, primary_position_id",
+            Self::Artcc => "artcc_id",
+        }
+    }
+
+    /// The sessions totalled, with their keys, `start_time`, `end_time` and `active_span`
+    const fn source(self) -> &'static str {
+        match self {
+            Self::Callsign => {
+                "SELECT prefix, suffix, start_time, end_time, active_span FROM callsign_sessions"
+            }
+            Self::Position => {
+                "SELECT position_id, start_time, end_time, active_span FROM position_sessions"
+            }
+            Self::Controller => {
+                r"
+                SELECT cid, connected_callsign, primary_position_id, name, start_time, end_time, active_span
+                FROM controller_sessions
+                WHERE NOT is_observer
+                "
+            }
+            Self::Artcc => {
+                r"
+                SELECT f.root_artcc_id AS artcc_id, ps.start_time, ps.end_time, ps.active_span
+                FROM position_sessions ps
+                JOIN facility_positions fp ON fp.id = ps.position_id
+                JOIN facilities f ON f.id = fp.facility_id
+                "
+            }
+        }
+    }
+
+    /// Columns besides the totals, and the aggregates of `spans` they're set to
+    const fn extra_columns(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Controller => (
+                ", name, last_start_time",
+                ", (array_agg(s.name ORDER BY s.start_time DESC))[1], max(s.start_time)",
+            ),
+            Self::Callsign | Self::Position | Self::Artcc => ("", ""),
+        }
+    }
+}
+
+/// Recomputes every [`DailyRollup`] for the UTC day starting at `day` and marks the day as rolled
+/// up, replacing any totals from an earlier run. Run it in a transaction so readers never see a
+/// day partly rolled up. Returns the number of rows written.
+///
+/// Sessions still active are counted up to the end of the day, so a day should only be rolled up
+/// once every datafeed in it has been applied.
+#[instrument(level = "debug", skip(executor))]
+pub async fn rollup_daily_session_stats<E>(
+    executor: &mut E,
+    day: DateTime<Utc>,
+) -> Result<u64, QueryError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    let mut rows = 0;
+    for rollup in DailyRollup::ALL {
+        let (table, keys, source) = (rollup.table(), rollup.keys(), rollup.source());
+        let (extra_columns, extra_values) = rollup.extra_columns();
+
+        sqlx::query(&format!("DELETE FROM {table} WHERE day = $1"))
+            .bind(day)
+            .execute(&mut *executor)
+            .await?;
+
+        // Concurrency is a running count of sessions starting and ending, with ends counted
+        // first so back-to-back sessions don't overlap
+        let query = format!(
+            r"
+            WITH spans AS (
+                SELECT
+                    s.*,
+                    GREATEST(s.start_time, $1) AS span_start,
+                    LEAST(COALESCE(s.end_time, $1 + interval '24 hours'), $1 + interval '24 hours') AS span_end
+                FROM ({source}) s
+                WHERE s.active_span && tstzrange($1, $1 + interval '24 hours')
+            ),
+            events AS (
+                SELECT {keys}, span_start AS at, 1 AS delta FROM spans
+                UNION ALL
+                SELECT {keys}, span_end AS at, -1 AS delta FROM spans
+            ),
+            concurrency AS (
+                SELECT {keys}, max(running) AS max_concurrent
+                FROM (
+                    SELECT
+                        {keys},
+                        sum(delta) OVER (
+                            PARTITION BY {keys}
+                            ORDER BY at, delta
+                            ROWS UNBOUNDED PRECEDING
+                        ) AS running
+                    FROM events
+                ) e
+                GROUP BY {keys}
+            )
+            INSERT INTO {table} (
+                day, {keys}, seconds_active, session_count, max_concurrent{extra_columns}
+            )
+            SELECT
+                $1,
+                {keys},
+                sum(EXTRACT(EPOCH FROM (s.span_end - s.span_start)))::BIGINT,
+                count(*),
+                max(c.max_concurrent){extra_values}
+            FROM spans s
+            JOIN concurrency c USING ({keys})
+            GROUP BY {keys}
+            "
+        );
+        rows += sqlx::query(&query)
+            .bind(day)
+            .execute(&mut *executor)
+            .await?
+            .rows_affected();
+    }
+
+    sqlx::query(
+        r"
+        INSERT INTO daily_session_rollups (day)
+        VALUES ($1)
+        ON CONFLICT (day) DO UPDATE SET rolled_up_at = now()
+        ",
+    )
+    .bind(day)
+    .execute(&mut *executor)
+    .await?;
+
+    Ok(rows)
+}
+
+/// UTC days that haven't been rolled up yet, from the day of the earliest session to the day
+/// before `until`, oldest first
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_pending_daily_rollups<'e, E>(
+    executor: E,
+    until: DateTime<Utc>,
+) -> Result<Vec<DateTime<Utc>>, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar::<_, DateTime<Utc>>(
+        r"
+        SELECT d.day
+        FROM generate_series(
+            date_trunc('day', (SELECT min(start_time) FROM callsign_sessions), 'UTC'),
+            date_trunc('day', $1, 'UTC') - interval '24 hours',
+            interval '24 hours'
+        ) AS d(day)
+        WHERE NOT EXISTS (SELECT 1 FROM daily_session_rollups r WHERE r.day = d.day)
+        ORDER BY d.day
+        ",
+    )
+    .bind(until)
+    .fetch_all(executor)
+    .await
+    .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn prune_session_activity_stats<'e, E>(
+    executor: E,
+    before: DateTime<Utc>,
+) -> Result<u64, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query("DELETE FROM session_activity_stats WHERE observed_at < $1")
+        .bind(before)
+        .execute(executor)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn prune_minute_activity_rollups<'e, E>(
+    executor: E,
+    before: DateTime<Utc>,
+) -> Result<u64, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query("DELETE FROM session_activity_stats_1m WHERE bucket < $1")
+        .bind(before)
+        .execute(executor)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(QueryError::from)
+}
+
+/// Prunes both per-ARTCC and per-facility activity snapshots
+#[instrument(level = "debug", skip(executor))]
+pub async fn prune_artcc_activity_stats<'e, E>(
+    executor: E,
+    before: DateTime<Utc>,
+) -> Result<u64, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_scalar::<_, i64>(
+        r"
+        WITH artccs AS (
+            DELETE FROM artcc_activity_stats WHERE observed_at < $1 RETURNING 1
+        ),
+        facilities AS (
+            DELETE FROM facility_activity_stats WHERE observed_at < $1 RETURNING 1
+        )
+        SELECT (SELECT count(*) FROM artccs) + (SELECT count(*) FROM facilities)
+        ",
+    )
+    .bind(before)
+    .fetch_one(executor)
+    .await
+    .map(i64::cast_unsigned)
+    .map_err(QueryError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn prune_datafeed_messages<'e, E>(
+    executor: E,
+    before: DateTime<Utc>,
+) -> Result<u64, QueryError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query("DELETE FROM datafeed_messages WHERE processed_at < $1")
+        .bind(before)
+        .execute(executor)
+        .await
+        .map(|r| r.rows_affected())
+        .map_err(QueryError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use shared::test_database::TestDatabase;
+    use sqlx::PgPool;
+
+    async fn db_now(pool: &PgPool) -> DateTime<Utc> {
+        sqlx::query_scalar("SELECT now()")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn backfill_stops_at_now() {
+        let db = TestDatabase::create().await;
+        let now = db_now(&db.pool).await;
+        let from = now - TimeDelta::hours(1);
+
+        let (inserted, updated) =
+            backfill_session_activity_stats(&db.pool, from, now + TimeDelta::days(1), 60, false)
+                .await
+                .unwrap();
+        assert_eq!(updated, 0);
+        assert!((59..=61).contains(&inserted), "inserted {inserted}");
+        let latest: DateTime<Utc> =
+            sqlx::query_scalar("SELECT max(observed_at) FROM session_activity_stats")
+                .fetch_one(&db.pool)
+                .await
+                .unwrap();
+        assert!(latest <= db_now(&db.pool).await);
+
+        // A window entirely in the future writes nothing
+        let tomorrow = now + TimeDelta::days(1);
+        let (inserted, _) = backfill_session_activity_stats(
+            &db.pool,
+            tomorrow,
+            tomorrow + TimeDelta::days(1),
+            60,
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(inserted, 0);
+    }
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    /// Seconds active per key, read with `query`
+    async fn totals(pool: &PgPool, query: &str) -> Vec<(String, i64)> {
+        sqlx::query_as(query).fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn rolled_up_days_total_the_same_as_raw_sessions() {
+        let db = TestDatabase::create().await;
+        let sessions = [
+            (1, "SJU_CTR", "2025-12-19T23:00:00Z", "2025-12-20T07:30:00Z"),
+            (2, "SJU_APP", "2025-12-20T10:00:00Z", "2025-12-21T03:00:00Z"),
+            (
+                3,
+                "SJU_1_APP",
+                "2025-12-22T12:00:00Z",
+                "2025-12-22T13:00:00Z",
+            ),
+            (
+                4,
+                "SJU_1_APP",
+                "2025-12-22T12:30:00Z",
+                "2025-12-22T14:00:00Z",
+            ),
+        ];
+        for (cid, callsign, start, end) in sessions {
+            db.insert_session(1_000_000 + cid, callsign, at(start), at(end))
+                .await;
+        }
+        for day in ["19", "20", "21", "22"] {
+            let mut tx = db.pool.begin().await.unwrap();
+            rollup_daily_session_stats(tx.as_mut(), at(&format!("2025-12-{day}T00:00:00Z")))
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+        }
+
+        let raw_seconds = "sum(EXTRACT(EPOCH FROM (end_time - start_time)))::BIGINT";
+        // SJU_1_APP is totalled with SJU_APP
+        for (key, sessions, daily_stats, keys) in [
+            (
+                "prefix || '_' || suffix",
+                "callsign_sessions",
+                "callsign_daily_stats",
+                2,
+            ),
+            (
+                "position_id",
+                "position_sessions",
+                "position_daily_stats",
+                4,
+            ),
+            (
+                "cid::TEXT",
+                "controller_sessions",
+                "controller_daily_stats",
+                4,
+            ),
+        ] {
+            let raw = totals(
+                &db.pool,
+                &format!("SELECT {key}, {raw_seconds} FROM {sessions} GROUP BY 1 ORDER BY 1"),
+            )
+            .await;
+            let rolled_up = totals(
+                &db.pool,
+                &format!(
+                    "SELECT {key}, sum(seconds_active)::BIGINT FROM {daily_stats} GROUP BY 1 ORDER BY 1"
+                ),
+            )
+            .await;
+            assert_eq!(raw.len(), keys, "{sessions}");
+            assert_eq!(rolled_up, raw, "{daily_stats}");
+        }
+
+        // Overlapping sessions on the same callsign are counted separately and concurrently
+        let (session_count, max_concurrent): (i32, i32) = sqlx::query_as(
+            r"
+            SELECT session_count, max_concurrent
+            FROM callsign_daily_stats
+            WHERE day = '2025-12-22' AND prefix = 'SJU' AND suffix = 'APP'
+            ",
+        )
+        .fetch_one(&db.pool)
+        .await
+        .unwrap();
+        assert_eq!((session_count, max_concurrent), (2, 2));
+    }
+}
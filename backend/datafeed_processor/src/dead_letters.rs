@@ -0,0 +1,69 @@
+use crate::database::queries::{
+    QueryError, fetch_dead_letters, requeue_dead_letter, update_dead_letter_error,
+};
+use shared::vnas::datafeed::DatafeedRoot;
+use sqlx::{Pool, Postgres};
+use tracing::{info, instrument, warn};
+
+#[derive(Debug, Default)]
+pub struct RedriveSummary {
+    pub inspected: usize,
+    pub redriven: usize,
+    pub still_failing: usize,
+}
+
+/// Re-drives dead-lettered datafeeds into `datafeed_queue`, oldest first. Only payloads that now
+/// deserialize into [`DatafeedRoot`] are re-queued; the rest stay in the dead-letter table with
+/// their error text refreshed.
+#[instrument(skip(pool))]
+pub async fn redrive_dead_letters(
+    pool: &Pool<Postgres>,
+    limit: i64,
+    dry_run: bool,
+) -> Result<RedriveSummary, QueryError> {
+    let mut tx = pool.begin().await?;
+    let dead_letters = fetch_dead_letters(&mut *tx, limit).await?;
+    let mut summary = RedriveSummary {
+        inspected: dead_letters.len(),
+        ..RedriveSummary::default()
+    };
+
+    for dead_letter in &dead_letters {
+        match serde_json::from_value::<DatafeedRoot>(dead_letter.payload.clone()) {
+            Ok(_) => {
+                info!(
+                    name: "dead_letters.redrive.requeued",
+                    id = %dead_letter.id,
+                    updated_at = ?dead_letter.updated_at,
+                    dry_run,
+                    "dead-lettered datafeed now deserializes, re-queueing"
+                );
+                if !dry_run {
+                    requeue_dead_letter(tx.as_mut(), dead_letter).await?;
+                }
+                summary.redriven += 1;
+            }
+            Err(e) => {
+                warn!(
+                    name: "dead_letters.redrive.still_failing",
+                    id = %dead_letter.id,
+                    updated_at = ?dead_letter.updated_at,
+                    error = ?e,
+                    "dead-lettered datafeed still fails to deserialize"
+                );
+                if !dry_run {
+                    update_dead_letter_error(tx.as_mut(), dead_letter.id, &e.to_string()).await?;
+                }
+                summary.still_failing += 1;
+            }
+        }
+    }
+
+    if dry_run {
+        tx.rollback().await?;
+    } else {
+        tx.commit().await?;
+    }
+
+    Ok(summary)
+}
@@ -1,5 +1,7 @@
 use crate::database::queries::QueryError;
+use chrono::NaiveDate;
 use shared::error::InitializationError;
+use shared::leader::FenceError;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -8,10 +10,38 @@ pub enum ProcessorMainError {
     Initialization(#[from] InitializationError),
     #[error("failed to clear initial backlog of fetched datafeeds")]
     InitialBacklog(#[from] BacklogProcessingError),
+    #[error("query error: {0}")]
+    Query(#[from] QueryError),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Join(#[from] tokio::task::JoinError),
+    #[error(transparent)]
+    Backfill(#[from] BackfillError),
+}
+
+impl ProcessorMainError {
+    /// Whether another replica was elected leader while this one was still processing, which only
+    /// means returning to standby
+    pub fn is_superseded(&self) -> bool {
+        matches!(
+            self,
+            Self::InitialBacklog(
+                BacklogProcessingError::Fence(FenceError::Superseded { .. })
+                    | BacklogProcessingError::Payload(PayloadProcessingError::Fence(
+                        FenceError::Superseded { .. }
+                    ))
+            )
+        )
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BackfillError {
+    #[error("query error: {0}")]
+    Query(#[from] QueryError),
+    #[error("{to} can't be rolled up until every datafeed in it has been applied")]
+    IncompleteDay { to: NaiveDate },
 }
 
 #[derive(Debug, Error)]
@@ -20,16 +50,18 @@ pub enum BacklogProcessingError {
     Query(#[from] QueryError),
     #[error("payload processing error: {0}")]
     Payload(#[from] PayloadProcessingError),
+    #[error(transparent)]
+    Fence(#[from] FenceError),
     #[error("db transaction error: {0}")]
     TransactionError(#[from] sqlx::Error),
 }
 
 #[derive(Debug, Error)]
 pub enum PayloadProcessingError {
-    #[error("datafeed deserialization error: {0}")]
-    Deserialize(#[from] serde_json::Error),
     #[error("query error: {0}")]
     Query(#[from] QueryError),
+    #[error(transparent)]
+    Fence(#[from] FenceError),
     #[error("db transaction error: {0}")]
     TransactionError(#[from] sqlx::Error),
 }
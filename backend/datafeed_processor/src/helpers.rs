@@ -152,6 +152,16 @@ pub enum ControllerCloseReason {
     DeactivatedPosition,
 }
 
+impl ControllerCloseReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MissingFromDatafeed => "missing_from_datafeed",
+            Self::ReconnectedOrChangedPosition => "reconnected_or_changed_position",
+            Self::DeactivatedPosition => "deactivated_position",
+        }
+    }
+}
+
 #[instrument(skip(tx, active_callsign_sessions_map))]
 pub async fn ensure_callsign_session(
     tx: &mut Transaction<'_, Postgres>,
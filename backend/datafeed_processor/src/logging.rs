@@ -2,11 +2,47 @@ use crate::database::queries::{
     QueryError, fetch_callsign_session_details, fetch_position_session_details,
 };
 use crate::helpers::ControllerAction;
+use crate::metrics::DatafeedsMetrics;
+use opentelemetry::KeyValue;
+use shared::vnas::datafeed::DatafeedRoot;
 use sqlx::{Pool, Postgres, Transaction};
 use std::collections::HashSet;
 use tracing::{Level, debug, event_enabled, warn};
 use uuid::Uuid;
 
+/// Logs and counts controllers that were skipped because they could not be deserialized, and enum
+/// values that the datafeed DTOs don't recognise yet.
+pub fn report_datafeed_anomalies(datafeed: &DatafeedRoot, metrics: &DatafeedsMetrics) {
+    for skipped in &datafeed.skipped_controllers {
+        warn!(
+            name: "datafeed.processed.controller.skipped",
+            index = skipped.index,
+            cid = skipped.cid,
+            callsign = skipped.callsign,
+            error = skipped.error,
+            "skipping controller that could not be deserialized"
+        );
+    }
+    if !datafeed.skipped_controllers.is_empty() {
+        metrics
+            .controllers_skipped
+            .add(datafeed.skipped_controllers.len() as u64, &[]);
+    }
+
+    for (field, value) in datafeed.unknown_values() {
+        debug!(
+            name: "datafeed.processed.unknown_value",
+            field = field,
+            value = value,
+            "datafeed contains an unrecognised value"
+        );
+        // The value itself is only logged, so new upstream values don't add time series
+        metrics
+            .unknown_values
+            .add(1, &[KeyValue::new("field", field)]);
+    }
+}
+
 pub async fn debug_log_sessions_changes(
     pool: &Pool<Postgres>,
     controller_actions: &[ControllerAction],
@@ -1,16 +1,29 @@
 #[warn(clippy::pedantic)]
+mod backfill;
+mod cli;
+mod compression;
 mod database;
+mod dead_letters;
 mod error;
 mod helpers;
 mod logging;
+mod maintenance;
 mod metrics;
+mod monitoring;
 
+use crate::backfill::{backfill_activity_stats, backfill_daily_rollups};
+use crate::cli::{Cli, Command};
+use crate::compression::{recompress_all, run_recompression_loop, train_and_store_dictionary};
+use crate::database::models::QueuedDatafeed;
 use crate::database::queries::{
-    complete_controller_sessions, delete_queued_datafeed, fetch_datafeed_batch,
-    insert_controller_session, insert_datafeed_message, insert_session_activity_stats,
+    QueryError, complete_controller_sessions, count_dead_letters, delete_queued_datafeed,
+    fetch_datafeed_batch, fetch_high_water_mark, insert_artcc_activity_stats,
+    insert_controller_session, insert_datafeed_message, insert_dead_letter,
+    insert_facility_activity_stats, insert_session_activity_stats, notify_datafeed_processed,
     update_active_controller_session, update_callsign_session_last_seen,
     update_position_session_last_seen, upsert_datafeed_payload,
 };
+use crate::dead_letters::redrive_dead_letters;
 use crate::error::{BacklogProcessingError, PayloadProcessingError, ProcessorMainError};
 use crate::helpers::ControllerCloseReason;
 use crate::helpers::{
@@ -18,55 +31,211 @@ use crate::helpers::{
     ensure_position_session, finalize_callsign_sessions, finalize_position_sessions,
     load_active_state, login_times_match, parse_controller_parts,
 };
-use crate::logging::debug_log_sessions_changes;
-use crate::metrics::Metrics;
-use axum::Router;
-use axum::extract::State;
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::routing::get;
-use chrono::{DateTime, Utc};
+use crate::logging::{debug_log_sessions_changes, report_datafeed_anomalies};
+use crate::maintenance::{log_summary, run_maintenance, run_maintenance_loop};
+use crate::metrics::{DatafeedOutcome, Metrics};
+use crate::monitoring::run_queue_monitor_loop;
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::Parser;
 use opentelemetry::KeyValue;
-use parking_lot::RwLock;
+use shared::compression::Codec;
 use shared::error::InitializationError;
+use shared::health::{self, Checks, Health};
+use shared::leader::{Fence, LeaderElection, LeaderLock};
+use shared::payloads::PayloadWriter;
+use shared::telemetry::{Telemetry, init_telemetry};
 use shared::vnas::datafeed::DatafeedRoot;
-use shared::{init_tracing_and_oltp, initialize_db, load_config, shutdown_listener};
+use shared::vnas::fetch::{DatafeedFetcher, FetchOutcome, PollSchedule};
+use shared::{
+    ActivityConfig, Config, FetcherConfig, MaintenanceConfig, PayloadStorageConfig, ProcessorMode,
+    initialize_db, load_config, shutdown_listener,
+};
 use sqlx::postgres::PgListener;
-use sqlx::{Pool, Postgres};
+use sqlx::{Pool, Postgres, Transaction};
 use std::collections::HashSet;
-use std::sync::Arc;
-use tokio::net::TcpListener;
-use tokio::time::{Duration, sleep};
+use tokio::time::{Duration, Instant, sleep};
 use tokio_util::sync::CancellationToken;
 use tracing::{Instrument, Level, debug, event_enabled, info, info_span, instrument, trace, warn};
 use uuid::Uuid;
 
 #[tokio::main]
 async fn main() -> Result<(), ProcessorMainError> {
-    let (tracer_provider, meter_provider) = init_tracing_and_oltp("artcc_updater")?;
-
-    // Set up config
+    let cli = Cli::parse();
+    // Set up config, which decides how telemetry is exported
     let config = load_config().map_err(InitializationError::from)?;
+    let telemetry = init_telemetry("datafeed_processor", &config.telemetry)?;
     info!(name: "config.loaded", config = ?config, "config loaded");
 
     // Initialize DB
     let db_pool = initialize_db(&config.postgres, true).await?;
 
-    // Arc for state for health check endpoint
-    let last_processed_datafeed = Arc::new(RwLock::new(None));
+    let res = match cli.command {
+        Some(command) => run_command(command, &db_pool, &config).await,
+        None => run_service(db_pool, &config, &telemetry).await,
+    };
+
+    telemetry.shutdown();
+
+    res
+}
+
+async fn run_command(
+    command: Command,
+    db_pool: &Pool<Postgres>,
+    config: &Config,
+) -> Result<(), ProcessorMainError> {
+    match command {
+        Command::RedriveDeadLetters { limit, dry_run } => {
+            let summary = redrive_dead_letters(db_pool, limit, dry_run).await?;
+            info!(
+                name: "dead_letters.redrive.completed",
+                inspected = summary.inspected,
+                redriven = summary.redriven,
+                still_failing = summary.still_failing,
+                dry_run,
+                "completed re-driving dead-lettered datafeeds"
+            );
+        }
+        Command::TrainDictionary { samples, max_size } => {
+            let dictionary = train_and_store_dictionary(db_pool, samples, max_size).await?;
+            info!(
+                name: "compression.dictionary.trained",
+                id = %dictionary.id,
+                sample_count = dictionary.sample_count,
+                size = dictionary.dictionary.len(),
+                "trained and stored compression dictionary"
+            );
+        }
+        Command::RecompressPayloads { batch_size } => {
+            let codec = Codec::load(db_pool, &config.compression)
+                .await
+                .map_err(QueryError::from)?;
+            let summary = recompress_all(db_pool, &codec, batch_size).await?;
+            info!(
+                name: "compression.recompress.completed",
+                recompressed = summary.recompressed,
+                bytes_before = summary.bytes_before,
+                bytes_after = summary.bytes_after,
+                "completed recompressing stored datafeeds"
+            );
+        }
+        Command::Maintain => {
+            log_summary(&run_maintenance(db_pool, &config.maintenance).await?);
+        }
+        Command::BackfillActivityStats {
+            from,
+            to,
+            step_seconds,
+            overwrite,
+        } => {
+            let to = to.unwrap_or_else(|| Utc::now().date_naive());
+            let summary =
+                backfill_activity_stats(db_pool, from, to, step_seconds, overwrite).await?;
+            info!(
+                name: "activity.backfill.completed",
+                days = summary.days,
+                inserted = summary.inserted,
+                updated = summary.updated,
+                "completed backfilling activity stats"
+            );
+        }
+        Command::BackfillDailyRollups { from, to } => {
+            let to = to.unwrap_or_else(|| Utc::now().date_naive() - TimeDelta::days(1));
+            let (days, rows) = backfill_daily_rollups(db_pool, from, to).await?;
+            info!(
+                name: "activity.daily_rollup.completed",
+                days,
+                rows,
+                "completed recomputing daily session totals"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_service(
+    db_pool: Pool<Postgres>,
+    config: &Config,
+    telemetry: &Telemetry,
+) -> Result<(), ProcessorMainError> {
+    let compression = &config.compression;
+    let health = Health::new(
+        "datafeed_processor",
+        &config.health,
+        Checks {
+            success: false,
+            lag: true,
+            queue_age: true,
+        },
+    )
+    .with_database(db_pool.clone());
+    let dead_letters = count_dead_letters(&db_pool).await?;
+    health.set_detail("dead_letters", dead_letters);
+    health.set_detail("mode", format!("{:?}", config.processor.mode));
+    let codec = Codec::load(&db_pool, compression)
+        .await
+        .map_err(QueryError::from)?;
 
     // Cancellation token shared across tasks; listener cancels on SIGINT/SIGTERM.
     let shutdown_token = CancellationToken::new();
 
+    // Existing payloads are migrated to the current compression settings in the background
+    let recompression_handle = compression.recompress_interval_seconds.map(|seconds| {
+        tokio::spawn(run_recompression_loop(
+            db_pool.clone(),
+            codec.clone(),
+            Duration::from_secs(seconds),
+            compression.recompress_batch_size,
+            shutdown_token.clone(),
+        ))
+    });
+
+    // Queue depth and age are sampled in the background for metrics and readiness
+    let metrics = Metrics::default();
+    let monitor_handle = tokio::spawn(run_queue_monitor_loop(
+        db_pool.clone(),
+        health.clone(),
+        metrics.queue.clone(),
+        Duration::from_secs(config.processor.monitor_interval_seconds),
+        shutdown_token.clone(),
+    ));
+
     // Spawn listener, axum (health check endpoint) and datafeed processor tasks
     let mut signal_handle = tokio::spawn(shutdown_listener(Some(shutdown_token.clone())));
-    let mut axum_handle = tokio::spawn(run_health_server(
-        Arc::clone(&last_processed_datafeed),
-        shutdown_token.clone(),
+    let mut axum_handle = tokio::spawn(health::serve(
+        config.health.bind_address,
+        health::router(health.clone()).merge(telemetry.metrics_router()),
+        shutdown_token.clone().cancelled_owned(),
     ));
+    // Recompression claims each payload with `SKIP LOCKED` and rewrites it in place, so it runs on
+    // every replica. Session state, rollups and pruning are only touched by the leader, whose
+    // processing transactions check its fence in case it has lost the lock without noticing yet.
+    let election = LeaderElection::new(
+        db_pool.clone(),
+        LeaderLock::Processor,
+        &config.leader_election,
+        health.clone(),
+    );
+    let processing = Processing {
+        pool: db_pool,
+        codec,
+        payload_writer: PayloadWriter::new(&config.payload_storage),
+        payload_storage: config.payload_storage.clone(),
+        activity: config.activity.clone(),
+        health,
+        dead_letters,
+        high_water_mark: None,
+        fence: None,
+        out_of_order: 0,
+        metrics,
+    };
     let mut processor_handle = tokio::spawn(run_datafeed_processing_loop(
-        db_pool,
-        Arc::clone(&last_processed_datafeed),
+        processing,
+        config.processor.mode,
+        config.fetcher.clone().unwrap_or_default(),
+        config.maintenance.clone(),
+        election,
         shutdown_token.clone(),
     ));
 
@@ -150,12 +319,18 @@ async fn main() -> Result<(), ProcessorMainError> {
         }
     }
 
-    if let Err(e) = tracer_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
+    if let Some(handle) = recompression_handle {
+        info!(name: "recompression.completion.awaiting", "awaiting completion of recompression task");
+        if let Err(join) = handle.await {
+            info!(name: "recompression.completed", error = ?join, "recompression task completed with error");
+            first_err.get_or_insert(join.into());
+        }
     }
 
-    if let Err(e) = meter_provider.shutdown() {
-        eprintln!("failed to shut down tracer provider: {e:?}");
+    info!(name: "queue_monitor.completion.awaiting", "awaiting completion of queue monitor task");
+    if let Err(join) = monitor_handle.await {
+        info!(name: "queue_monitor.completed", error = ?join, "queue monitor task completed with error");
+        first_err.get_or_insert(join.into());
     }
 
     if let Some(err) = first_err {
@@ -165,57 +340,114 @@ async fn main() -> Result<(), ProcessorMainError> {
     }
 }
 
-#[derive(Clone)]
-struct AxumState {
-    last_processed_datafeed: Arc<RwLock<Option<DateTime<Utc>>>>,
+/// State carried across batches by the processing loop, in either [`ProcessorMode`]
+struct Processing {
+    pool: Pool<Postgres>,
+    codec: Codec,
+    /// Tracks the previously stored payload that deltas are encoded against. Reset with every
+    /// leader term, since other replicas store payloads (and the archiver prunes them) meanwhile.
+    payload_writer: PayloadWriter,
+    payload_storage: PayloadStorageConfig,
+    activity: ActivityConfig,
+    health: Health,
+    /// Number of rows in `datafeed_dead_letters`
+    dead_letters: i64,
+    /// `updated_at` of the newest datafeed applied to sessions. Older datafeeds are stored but not
+    /// applied, since replaying them would reopen and close sessions out of order.
+    high_water_mark: Option<DateTime<Utc>>,
+    /// The current leader term, `None` on standby or with election disabled
+    fence: Option<Fence>,
+    /// Datafeeds stored without being applied because they arrived out of order, since startup
+    out_of_order: u64,
+    metrics: Metrics,
 }
 
-async fn run_health_server(
-    last_processed_datafeed: Arc<RwLock<Option<DateTime<Utc>>>>,
+/// Processes datafeeds and runs maintenance for as long as this replica is leader, going back to
+/// standby whenever leadership is lost. Only the leader touches session state, so datafeeds are
+/// never applied by two replicas at once.
+async fn run_datafeed_processing_loop(
+    mut processing: Processing,
+    mode: ProcessorMode,
+    fetcher_config: FetcherConfig,
+    maintenance: MaintenanceConfig,
+    election: LeaderElection,
     shutdown: CancellationToken,
-) -> Result<(), std::io::Error> {
-    info!(name: "axum.initialized", "starting axum health server");
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .with_state(AxumState {
-            last_processed_datafeed,
+) -> Result<(), ProcessorMainError> {
+    while let Some(leadership) = election.acquire(&shutdown).await {
+        processing.fence = leadership.fence();
+        processing.payload_writer = PayloadWriter::new(&processing.payload_storage);
+        // Stopped with the term, whether it ends by losing the lock or by an error
+        let term = leadership.token().child_token();
+        // Activity stats are rolled up and old rows pruned in the background
+        let maintenance_handle = maintenance.interval_seconds.map(|seconds| {
+            tokio::spawn(run_maintenance_loop(
+                processing.pool.clone(),
+                maintenance.clone(),
+                Duration::from_secs(seconds),
+                term.clone(),
+            ))
         });
-    let listener = TcpListener::bind("127.0.0.1:3000").await?;
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown.cancelled_owned())
-        .await?;
 
-    Ok(())
-}
-
-async fn health_check(State(state): State<AxumState>) -> impl IntoResponse {
-    let last_processed_datafeed = *state.last_processed_datafeed.read();
-    let msg = if let Some(timestamp) = last_processed_datafeed {
-        format!("Last processed datafeed updated_at: {timestamp}")
-    } else {
-        "No datafeeds processed yet".into()
-    };
+        let res = process_while_leader(&mut processing, mode, &fetcher_config, term.clone()).await;
+        term.cancel();
+        if let Some(handle) = maintenance_handle
+            && let Err(join) = handle.await
+        {
+            warn!(name: "maintenance.completed", error = ?join, "maintenance task completed with error");
+        }
+        leadership.release().await;
+        processing.fence = None;
+        match res {
+            Err(e) if e.is_superseded() => {
+                warn!(name: "processor.leadership.superseded", error = ?e, "another replica was elected leader mid-batch");
+            }
+            res => res?,
+        }
+        if shutdown.is_cancelled() {
+            break;
+        }
+        warn!(name: "processor.leadership.lost", "lost leadership, returning to standby");
+    }
 
-    (StatusCode::OK, msg)
+    Ok(())
 }
 
-async fn run_datafeed_processing_loop(
-    db_pool: Pool<Postgres>,
-    last_processed_datafeed: Arc<RwLock<Option<DateTime<Utc>>>>,
+async fn process_while_leader(
+    processing: &mut Processing,
+    mode: ProcessorMode,
+    fetcher_config: &FetcherConfig,
     shutdown: CancellationToken,
 ) -> Result<(), ProcessorMainError> {
-    // Initialize metrics
-    let metrics = Metrics::default();
+    // Another replica may have applied newer datafeeds while this one was on standby
+    processing.high_water_mark = processing
+        .metrics
+        .queries
+        .time(
+            "fetch_high_water_mark",
+            fetch_high_water_mark(&processing.pool),
+        )
+        .await?;
 
-    // Process any backlog before listening
+    // Process any backlog before listening or fetching
     info!(name: "processing.backlog.started", "starting processing backlog of queued datafeeds");
-    process_pending_datafeeds(&db_pool, &last_processed_datafeed, 25, &metrics)
+    process_pending_datafeeds(processing, 25)
         .instrument(info_span!("process_backlog"))
         .await?;
     info!(name: "processing.backlog.completed", "completed processing backlog of queued datafeeds");
 
-    // Listen for new datafeeds
-    let mut listener = PgListener::connect_with(&db_pool)
+    match mode {
+        ProcessorMode::Queue => listen_for_datafeeds(processing, shutdown).await,
+        ProcessorMode::Direct => {
+            fetch_and_process_datafeeds(processing, fetcher_config, shutdown).await
+        }
+    }
+}
+
+async fn listen_for_datafeeds(
+    processing: &mut Processing,
+    shutdown: CancellationToken,
+) -> Result<(), ProcessorMainError> {
+    let mut listener = PgListener::connect_with(&processing.pool)
         .await
         .map_err(InitializationError::from)?;
     listener
@@ -235,7 +467,7 @@ async fn run_datafeed_processing_loop(
                     Ok(notification) => {
                         trace!(name:"datafeed_loop.listener.received", payload = notification.payload(), "received datafeed notification");
                         // Process pending datafeeds; if this fails, propagate the error after finishing this payload.
-                        process_pending_datafeeds(&db_pool, &last_processed_datafeed, 10, &metrics).await?;
+                        process_pending_datafeeds(processing, 10).await?;
                     }
                     Err(e) => {
                         warn!(name:"datafeed_loop.listener.received", error = ?e, "error receiving Postgres notification");
@@ -249,79 +481,326 @@ async fn run_datafeed_processing_loop(
     Ok(())
 }
 
-#[instrument(skip(pool, last_processed_datafeed, metrics))]
+/// Fetches the datafeed on the fetcher's [`PollSchedule`] and processes it in-process, without
+/// going through `datafeed_queue`. Anything that does land in the queue (re-driven dead letters,
+/// or a fetcher that's still running) is processed before each fetch.
+async fn fetch_and_process_datafeeds(
+    processing: &mut Processing,
+    fetcher_config: &FetcherConfig,
+    shutdown: CancellationToken,
+) -> Result<(), ProcessorMainError> {
+    let mut fetcher = DatafeedFetcher::new(reqwest::Client::new());
+    let mut schedule = PollSchedule::new(fetcher_config);
+    let mut last_fetched: Option<DateTime<Utc>> = None;
+    info!(name: "datafeed_loop.fetcher.started", "fetching datafeeds directly");
+
+    let mut initial_loop = true;
+    loop {
+        let delay = if initial_loop {
+            initial_loop = false;
+            Duration::ZERO
+        } else {
+            schedule.next_delay(Utc::now())
+        };
+        tokio::select! {
+            () = shutdown.cancelled() => {
+                info!(name: "datafeed_loop.shutdown.received", "shutdown requested, exiting processor loop");
+                break;
+            }
+            () = sleep(delay) => {}
+        }
+
+        process_pending_datafeeds(processing, 10).await?;
+
+        let (payload, updated_at) = match fetcher.fetch().await {
+            Ok(FetchOutcome::Modified {
+                payload,
+                updated_at,
+            }) => (payload, updated_at),
+            Ok(FetchOutcome::NotModified) => {
+                schedule.record_unchanged();
+                trace!(name: "datafeed_loop.fetcher.not_modified", "datafeed not modified since last fetch");
+                continue;
+            }
+            Err(e) => {
+                schedule.record_failure();
+                warn!(
+                    name: "datafeed_loop.fetcher.failed",
+                    error = ?e,
+                    consecutive_failures = schedule.consecutive_failures(),
+                    "failed to fetch and deserialize datafeed"
+                );
+                processing.health.record_error(e);
+                continue;
+            }
+        };
+        schedule.record_success(updated_at);
+        if last_fetched == Some(updated_at) {
+            trace!(name: "datafeed_loop.fetcher.unchanged", updated_at = ?updated_at, "datafeed unchanged since last fetch");
+            continue;
+        }
+
+        let now = Utc::now();
+        let message = QueuedDatafeed {
+            id: Uuid::now_v7(),
+            updated_at,
+            payload,
+            created_at: now,
+        };
+        let tx = processing
+            .pool
+            .begin()
+            .await
+            .map_err(BacklogProcessingError::from)?;
+        process_messages(processing, tx, vec![message], false).await?;
+        last_fetched = Some(updated_at);
+    }
+
+    Ok(())
+}
+
+#[instrument(skip(processing))]
 async fn process_pending_datafeeds(
-    pool: &Pool<Postgres>,
-    last_processed_datafeed: &RwLock<Option<DateTime<Utc>>>,
+    processing: &mut Processing,
     limit: i64,
-    metrics: &Metrics,
 ) -> Result<(), BacklogProcessingError> {
     loop {
-        let mut tx = pool.begin().await.map_err(BacklogProcessingError::from)?;
-        let messages = fetch_datafeed_batch(&mut *tx, limit).await?;
+        let mut tx = processing
+            .pool
+            .begin()
+            .await
+            .map_err(BacklogProcessingError::from)?;
+        let messages = processing
+            .metrics
+            .queries
+            .time(
+                "fetch_datafeed_batch",
+                fetch_datafeed_batch(&mut *tx, limit),
+            )
+            .await?;
 
         if messages.is_empty() {
             tx.commit().await?;
             break;
         }
 
-        let mut latest = None;
-        for message in messages {
-            let parsed = serde_json::from_value::<DatafeedRoot>(message.payload.clone());
-            let datafeed_root = match parsed {
-                Ok(root) => root,
-                Err(e) => {
-                    tx.rollback().await?;
-                    return Err(PayloadProcessingError::Deserialize(e).into());
-                }
-            };
+        process_messages(processing, tx, messages, true).await?;
+    }
 
-            // Upsert payload; if not inserted (already seen), skip session processing.
-            let (payload_id, new_payload) =
-                upsert_datafeed_payload(tx.as_mut(), &message, &metrics.datafeeds).await?;
+    Ok(())
+}
 
-            if new_payload {
-                debug!(name: "datafeed.inspected.found_new", updated_at = ?datafeed_root.updated_at, "new datafeed update received");
-                if let Err(e) = process_datafeed_payload(pool, &datafeed_root, metrics).await {
-                    tx.rollback().await?;
-                    return Err(e.into());
-                }
-            } else {
-                trace!(
-                    name: "datafeed.inspected.found_duplicate",
-                    updated_at = ?datafeed_root.updated_at,
-                    "skipping processing; datafeed already processed"
+/// Stores and processes `messages` in order, committing `tx` at the end. Queued messages are
+/// removed from `datafeed_queue` in the same transaction when `dequeue` is set.
+async fn process_messages(
+    processing: &mut Processing,
+    mut tx: Transaction<'_, Postgres>,
+    messages: Vec<QueuedDatafeed>,
+    dequeue: bool,
+) -> Result<(), BacklogProcessingError> {
+    let Processing {
+        pool,
+        codec,
+        payload_writer,
+        payload_storage: _,
+        activity,
+        health,
+        dead_letters,
+        high_water_mark,
+        fence,
+        out_of_order,
+        metrics,
+    } = processing;
+
+    if let Some(fence) = fence {
+        fence.check(tx.as_mut()).await?;
+    }
+
+    // Only advance the writer once this batch is committed, so deltas never reference a
+    // payload that was rolled back
+    let mut batch_writer = payload_writer.clone();
+    let mut batch_high_water_mark = *high_water_mark;
+    let mut latest = None;
+    let mut dead_lettered: i64 = 0;
+    let mut batch_out_of_order: u64 = 0;
+    for message in messages {
+        let started = Instant::now();
+        let parsed = serde_json::from_value::<DatafeedRoot>(message.payload.clone());
+        let datafeed_root = match parsed {
+            Ok(root) => root,
+            Err(e) => {
+                // A payload that doesn't match our DTOs would fail forever, so move it aside
+                // and keep processing the rest of the queue.
+                warn!(
+                    name: "datafeed.inspected.dead_lettered",
+                    queue_id = %message.id,
+                    updated_at = ?message.updated_at,
+                    error = ?e,
+                    "datafeed could not be deserialized, moving to dead letters"
                 );
+                metrics
+                    .queries
+                    .time(
+                        "insert_dead_letter",
+                        insert_dead_letter(tx.as_mut(), &message, &e.to_string()),
+                    )
+                    .await?;
+                if dequeue {
+                    metrics
+                        .queries
+                        .time(
+                            "delete_queued_datafeed",
+                            delete_queued_datafeed(tx.as_mut(), message.id),
+                        )
+                        .await?;
+                }
+                dead_lettered += 1;
+                metrics
+                    .datafeeds
+                    .record_processed(DatafeedOutcome::DeadLettered, started);
+                continue;
             }
+        };
 
-            insert_datafeed_message(
-                tx.as_mut(),
-                message.id,
+        // Upsert payload; if not inserted (already seen), skip session processing.
+        let encoded = batch_writer
+            .encode(&message.payload, message.updated_at)
+            .map_err(QueryError::from)?;
+        let (payload_id, new_payload) = metrics
+            .queries
+            .time(
+                "upsert_datafeed_payload",
+                upsert_datafeed_payload(tx.as_mut(), &message, &encoded, codec, &metrics.datafeeds),
+            )
+            .await?;
+        if new_payload {
+            batch_writer.stored(
                 payload_id,
-                message.created_at,
-                Utc::now(),
+                message.updated_at,
+                message.payload.clone(),
+                encoded.kind,
+            );
+        }
+
+        let is_out_of_order =
+            new_payload && batch_high_water_mark.is_some_and(|mark| message.updated_at < mark);
+        if is_out_of_order {
+            warn!(
+                name: "datafeed.inspected.found_out_of_order",
+                queue_id = %message.id,
+                updated_at = ?message.updated_at,
+                high_water_mark = ?batch_high_water_mark,
+                "datafeed is older than one already applied, storing without updating sessions"
+            );
+            batch_out_of_order += 1;
+        } else if new_payload {
+            debug!(name: "datafeed.inspected.found_new", updated_at = ?datafeed_root.updated_at, "new datafeed update received");
+            if let Err(e) =
+                process_datafeed_payload(pool, *fence, &datafeed_root, activity, metrics).await
+            {
+                tx.rollback().await?;
+                return Err(e.into());
+            }
+            batch_high_water_mark = Some(message.updated_at);
+        } else {
+            trace!(
+                name: "datafeed.inspected.found_duplicate",
+                updated_at = ?datafeed_root.updated_at,
+                "skipping processing; datafeed already processed"
+            );
+        }
+
+        let processed_at = Utc::now();
+        metrics
+            .queries
+            .time(
+                "insert_datafeed_message",
+                insert_datafeed_message(
+                    tx.as_mut(),
+                    message.id,
+                    payload_id,
+                    message.created_at,
+                    processed_at,
+                    is_out_of_order,
+                ),
             )
             .await?;
-            delete_queued_datafeed(tx.as_mut(), message.id).await?;
+        metrics
+            .datafeeds
+            .processing_delay
+            .record((processed_at - message.created_at).as_seconds_f64(), &[]);
+        if dequeue {
+            metrics
+                .queries
+                .time(
+                    "delete_queued_datafeed",
+                    delete_queued_datafeed(tx.as_mut(), message.id),
+                )
+                .await?;
+        }
+        let outcome = if is_out_of_order {
+            DatafeedOutcome::OutOfOrder
+        } else if new_payload {
+            DatafeedOutcome::New
+        } else {
+            DatafeedOutcome::Duplicate
+        };
+        metrics.datafeeds.record_processed(outcome, started);
+        if !is_out_of_order {
             latest = Some(datafeed_root.updated_at);
         }
+    }
+
+    if batch_high_water_mark != *high_water_mark
+        && let Some(applied) = batch_high_water_mark
+    {
+        metrics
+            .queries
+            .time(
+                "notify_datafeed_processed",
+                notify_datafeed_processed(tx.as_mut(), applied),
+            )
+            .await?;
+    }
 
-        tx.commit().await.map_err(BacklogProcessingError::from)?;
-        *last_processed_datafeed.write() = latest;
+    tx.commit().await.map_err(BacklogProcessingError::from)?;
+    *payload_writer = batch_writer;
+    if let Some(latest) = latest {
+        health.set_datafeed_updated_at(latest);
+        health.record_success(Utc::now());
+    }
+    if dead_lettered > 0 {
+        *dead_letters += dead_lettered;
+        health.set_detail("dead_letters", *dead_letters);
+    }
+    *high_water_mark = batch_high_water_mark;
+    if batch_out_of_order > 0 {
+        *out_of_order += batch_out_of_order;
+        health.set_detail("out_of_order", *out_of_order);
     }
 
     Ok(())
 }
 
-#[instrument(skip(pool, datafeed, metrics))]
+#[instrument(skip(pool, fence, datafeed, activity, metrics))]
 async fn process_datafeed_payload(
     pool: &Pool<Postgres>,
+    fence: Option<Fence>,
     datafeed: &DatafeedRoot,
+    activity: &ActivityConfig,
     metrics: &Metrics,
 ) -> Result<(), PayloadProcessingError> {
+    report_datafeed_anomalies(datafeed, &metrics.datafeeds);
+
     let mut tx = pool.begin().await?;
+    if let Some(fence) = fence {
+        fence.check(&mut *tx).await?;
+    }
 
-    let mut existing_state = load_active_state(&mut tx).await?;
+    let queries = &metrics.queries;
+    let mut existing_state = queries
+        .time("load_active_state", load_active_state(&mut tx))
+        .await?;
     let ActiveState {
         active_by_cid: existing_active_by_cid,
         active_callsign_sessions: existing_active_callsign_sessions,
@@ -469,12 +948,16 @@ async fn process_datafeed_payload(
         .collect();
 
     if !close_controller_session_ids.is_empty() {
-        let _ = complete_controller_sessions(
-            &mut *tx,
-            &close_controller_session_ids,
-            datafeed.updated_at,
-        )
-        .await?;
+        let _ = queries
+            .time(
+                "complete_controller_sessions",
+                complete_controller_sessions(
+                    &mut *tx,
+                    &close_controller_session_ids,
+                    datafeed.updated_at,
+                ),
+            )
+            .await?;
     }
 
     // Now, handle updates and inserts (any matches on Close do nothing)
@@ -486,25 +969,37 @@ async fn process_datafeed_payload(
                 callsign_session_id,
                 position_session_id,
             } => {
-                update_callsign_session_last_seen(
-                    tx.as_mut(),
-                    *callsign_session_id,
-                    datafeed.updated_at,
-                )
-                .await?;
-                update_position_session_last_seen(
-                    tx.as_mut(),
-                    *position_session_id,
-                    datafeed.updated_at,
-                )
-                .await?;
-                update_active_controller_session(
-                    tx.as_mut(),
-                    *session_id,
-                    controller,
-                    datafeed.updated_at,
-                )
-                .await?;
+                queries
+                    .time(
+                        "update_callsign_session_last_seen",
+                        update_callsign_session_last_seen(
+                            tx.as_mut(),
+                            *callsign_session_id,
+                            datafeed.updated_at,
+                        ),
+                    )
+                    .await?;
+                queries
+                    .time(
+                        "update_position_session_last_seen",
+                        update_position_session_last_seen(
+                            tx.as_mut(),
+                            *position_session_id,
+                            datafeed.updated_at,
+                        ),
+                    )
+                    .await?;
+                queries
+                    .time(
+                        "update_active_controller_session",
+                        update_active_controller_session(
+                            tx.as_mut(),
+                            *session_id,
+                            controller,
+                            datafeed.updated_at,
+                        ),
+                    )
+                    .await?;
                 active_controller_session_ids.insert(*session_id);
                 active_callsign_ids.insert(*callsign_session_id);
                 active_position_ids.insert(controller.primary_position_id.clone());
@@ -515,37 +1010,49 @@ async fn process_datafeed_payload(
                 position_id,
                 cid,
             } => {
-                let (callsign_session_id, callsign_created) = ensure_callsign_session(
-                    &mut tx,
-                    existing_active_callsign_sessions_map,
-                    callsign_key,
-                    datafeed.updated_at,
-                )
-                .await?;
+                let (callsign_session_id, callsign_created) = queries
+                    .time(
+                        "ensure_callsign_session",
+                        ensure_callsign_session(
+                            &mut tx,
+                            existing_active_callsign_sessions_map,
+                            callsign_key,
+                            datafeed.updated_at,
+                        ),
+                    )
+                    .await?;
                 if callsign_created {
                     new_callsign_session_ids.insert(callsign_session_id);
                 }
 
-                let (position_session_id, position_created) = ensure_position_session(
-                    &mut tx,
-                    existing_active_position_sessions,
-                    position_id,
-                    datafeed.updated_at,
-                )
-                .await?;
+                let (position_session_id, position_created) = queries
+                    .time(
+                        "ensure_position_session",
+                        ensure_position_session(
+                            &mut tx,
+                            existing_active_position_sessions,
+                            position_id,
+                            datafeed.updated_at,
+                        ),
+                    )
+                    .await?;
                 if position_created {
                     new_position_session_ids.insert(position_session_id);
                 }
 
-                let controller_session_id = insert_controller_session(
-                    tx.as_mut(),
-                    controller,
-                    *cid,
-                    datafeed.updated_at,
-                    callsign_session_id,
-                    position_session_id,
-                )
-                .await?;
+                let controller_session_id = queries
+                    .time(
+                        "insert_controller_session",
+                        insert_controller_session(
+                            tx.as_mut(),
+                            controller,
+                            *cid,
+                            datafeed.updated_at,
+                            callsign_session_id,
+                            position_session_id,
+                        ),
+                    )
+                    .await?;
                 active_controller_session_ids.insert(controller_session_id);
                 active_callsign_ids.insert(callsign_session_id);
                 active_position_ids.insert(position_id.to_string());
@@ -556,32 +1063,71 @@ async fn process_datafeed_payload(
     }
     trace!(name: "datafeed.processed.controllers.completed", "completed processing controller sessions");
 
-    let closed_callsign_session_ids = finalize_callsign_sessions(
-        &mut tx,
-        existing_active_callsign_sessions,
-        &active_callsign_ids,
-        datafeed.updated_at,
-    )
-    .await?;
+    let closed_callsign_session_ids = queries
+        .time(
+            "finalize_callsign_sessions",
+            finalize_callsign_sessions(
+                &mut tx,
+                existing_active_callsign_sessions,
+                &active_callsign_ids,
+                datafeed.updated_at,
+            ),
+        )
+        .await?;
     trace!(name: "datafeed.processed.callsigns.completed", "completed processing callsign sessions");
 
-    let closed_position_session_ids = finalize_position_sessions(
-        &mut tx,
-        existing_active_position_sessions,
-        &active_position_ids,
-        datafeed.updated_at,
-    )
-    .await?;
+    let closed_position_session_ids = queries
+        .time(
+            "finalize_position_sessions",
+            finalize_position_sessions(
+                &mut tx,
+                existing_active_position_sessions,
+                &active_position_ids,
+                datafeed.updated_at,
+            ),
+        )
+        .await?;
     trace!(name: "datafeed.processed.positions.completed", "completed processing position sessions");
 
-    insert_session_activity_stats(
-        tx.as_mut(),
-        datafeed.updated_at,
-        active_controller_session_ids.len() as i64,
-        active_callsign_ids.len() as i64,
-        active_position_ids.len() as i64,
-    )
-    .await?;
+    queries
+        .time(
+            "insert_session_activity_stats",
+            insert_session_activity_stats(
+                tx.as_mut(),
+                datafeed.updated_at,
+                active_controller_session_ids.len() as i64,
+                active_callsign_ids.len() as i64,
+                active_position_ids.len() as i64,
+            ),
+        )
+        .await?;
+
+    let controller_session_ids: Vec<Uuid> = active_controller_session_ids.iter().copied().collect();
+    let position_ids: Vec<String> = active_position_ids.iter().cloned().collect();
+    let artcc_activity = queries
+        .time(
+            "insert_artcc_activity_stats",
+            insert_artcc_activity_stats(
+                tx.as_mut(),
+                datafeed.updated_at,
+                &controller_session_ids,
+                &position_ids,
+            ),
+        )
+        .await?;
+    if activity.record_facilities {
+        queries
+            .time(
+                "insert_facility_activity_stats",
+                insert_facility_activity_stats(
+                    tx.as_mut(),
+                    datafeed.updated_at,
+                    &controller_session_ids,
+                    &position_ids,
+                ),
+            )
+            .await?;
+    }
 
     tx.commit().await?;
 
@@ -597,20 +1143,150 @@ async fn process_datafeed_payload(
     )
     .await?;
 
-    let metrics_key = [KeyValue::new("updated_at", datafeed.updated_at.to_string())];
-    metrics.datafeeds.processed.add(1, &metrics_key);
     metrics
         .active
         .controllers
-        .record(active_controller_session_ids.len() as u64, &metrics_key);
+        .record(active_controller_session_ids.len() as u64, &[]);
     metrics
         .active
         .callsigns
-        .record(active_callsign_ids.len() as u64, &metrics_key);
+        .record(active_callsign_ids.len() as u64, &[]);
     metrics
         .active
         .positions
-        .record(active_position_ids.len() as u64, &metrics_key);
+        .record(active_position_ids.len() as u64, &[]);
+    for artcc in &artcc_activity {
+        let artcc_key = [KeyValue::new("artcc", artcc.artcc_id.clone())];
+        let count = |n: i32| u64::try_from(n).unwrap_or_default();
+        metrics
+            .active
+            .artcc_controllers
+            .record(count(artcc.active_controllers), &artcc_key);
+        metrics
+            .active
+            .artcc_callsigns
+            .record(count(artcc.active_callsigns), &artcc_key);
+        metrics
+            .active
+            .artcc_positions
+            .record(count(artcc.active_positions), &artcc_key);
+    }
+
+    let opened_controllers = controller_actions
+        .iter()
+        .filter(|a| matches!(a, ControllerAction::CreateNew { .. }))
+        .count();
+    metrics
+        .sessions
+        .controller_opened
+        .add(opened_controllers as u64, &[]);
+    metrics
+        .sessions
+        .callsign_opened
+        .add(new_callsign_session_ids.len() as u64, &[]);
+    metrics
+        .sessions
+        .position_opened
+        .add(new_position_session_ids.len() as u64, &[]);
+    for action in &controller_actions {
+        if let ControllerAction::Close { reason, .. } = action {
+            metrics.sessions.record_controller_closed(reason);
+        }
+    }
+    metrics
+        .sessions
+        .callsign_closed
+        .add(closed_callsign_session_ids.len() as u64, &[]);
+    metrics
+        .sessions
+        .position_closed
+        .add(closed_position_session_ids.len() as u64, &[]);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::health::HealthConfig;
+    use shared::test_database::TestDatabase;
+    use sqlx::PgPool;
+
+    fn processing(pool: &PgPool) -> Processing {
+        let payload_storage = PayloadStorageConfig::default();
+        Processing {
+            pool: pool.clone(),
+            codec: Codec::default(),
+            payload_writer: PayloadWriter::new(&payload_storage),
+            payload_storage,
+            activity: ActivityConfig::default(),
+            health: Health::new(
+                "datafeed_processor",
+                &HealthConfig::default(),
+                Checks::default(),
+            ),
+            dead_letters: 0,
+            high_water_mark: None,
+            fence: None,
+            out_of_order: 0,
+            metrics: Metrics::default(),
+        }
+    }
+
+    async fn count(pool: &PgPool, table: &str) -> i64 {
+        sqlx::query_scalar(&format!("SELECT count(*) FROM {table}"))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn undeserializable_datafeeds_are_dead_lettered_then_redriven() {
+        let db = TestDatabase::create().await;
+        let queue_id = Uuid::now_v7();
+        sqlx::query("INSERT INTO datafeed_queue (id, updated_at, payload) VALUES ($1, now(), $2)")
+            .bind(queue_id)
+            .bind(serde_json::json!({ "controllers": "not a list" }))
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let mut processing = processing(&db.pool);
+        let mut tx = db.pool.begin().await.unwrap();
+        let messages = fetch_datafeed_batch(&mut *tx, 10).await.unwrap();
+        assert_eq!(messages.len(), 1);
+        process_messages(&mut processing, tx, messages, true)
+            .await
+            .unwrap();
+
+        assert_eq!(count(&db.pool, "datafeed_queue").await, 0);
+        assert_eq!(count(&db.pool, "datafeed_dead_letters").await, 1);
+        assert_eq!(count(&db.pool, "datafeed_payloads").await, 0);
+        assert_eq!(processing.dead_letters, 1);
+
+        // Still failing, so it stays put
+        let summary = redrive_dead_letters(&db.pool, 10, false).await.unwrap();
+        assert_eq!((summary.redriven, summary.still_failing), (0, 1));
+        assert_eq!(count(&db.pool, "datafeed_queue").await, 0);
+
+        // Fixed by hand, as after a DTO change
+        sqlx::query(
+            r"
+            UPDATE datafeed_dead_letters
+            SET payload = jsonb_build_object('updatedAt', updated_at, 'controllers', '[]'::jsonb)
+            ",
+        )
+        .execute(&db.pool)
+        .await
+        .unwrap();
+        let summary = redrive_dead_letters(&db.pool, 10, false).await.unwrap();
+        assert_eq!((summary.redriven, summary.still_failing), (1, 0));
+        assert_eq!(count(&db.pool, "datafeed_dead_letters").await, 0);
+        let requeued: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM datafeed_queue")
+            .fetch_all(&db.pool)
+            .await
+            .unwrap();
+        assert_eq!(requeued, [queue_id]);
+    }
+}
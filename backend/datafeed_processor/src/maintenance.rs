@@ -0,0 +1,154 @@
+use crate::database::queries::{
+    ActivityRollup, QueryError, fetch_high_water_mark, fetch_pending_daily_rollups,
+    prune_artcc_activity_stats, prune_datafeed_messages, prune_minute_activity_rollups,
+    prune_session_activity_stats, rollup_daily_session_stats, rollup_session_activity,
+};
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, Utc};
+use shared::MaintenanceConfig;
+use sqlx::{Pool, Postgres};
+use tokio::time::{Duration, MissedTickBehavior, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
+
+#[derive(Debug, Default)]
+pub struct MaintenanceSummary {
+    pub minute_buckets: u64,
+    pub hour_buckets: u64,
+    pub day_buckets: u64,
+    pub session_days: u64,
+    pub pruned_activity_stats: u64,
+    pub pruned_minute_buckets: u64,
+    pub pruned_artcc_activity_stats: u64,
+    pub pruned_messages: u64,
+}
+
+/// Rolls `session_activity_stats` up into every resolution and sessions up into daily totals, then
+/// prunes rows past their retention, for tables that have one configured. Rollups run first so
+/// nothing is pruned before it's been rolled up.
+#[instrument(skip(pool))]
+pub async fn run_maintenance(
+    pool: &Pool<Postgres>,
+    config: &MaintenanceConfig,
+) -> Result<MaintenanceSummary, QueryError> {
+    let mut summary = MaintenanceSummary::default();
+    for rollup in ActivityRollup::ALL {
+        let buckets = rollup_session_activity(pool, rollup).await?;
+        match rollup {
+            ActivityRollup::Minute => summary.minute_buckets = buckets,
+            ActivityRollup::Hour => summary.hour_buckets = buckets,
+            ActivityRollup::Day => summary.day_buckets = buckets,
+        }
+    }
+    summary.session_days = rollup_completed_days(pool).await?;
+
+    let now = Utc::now();
+    let days_ago = |days: u32| now - ChronoDuration::days(i64::from(days));
+    if let Some(days) = config.activity_raw_retention_days {
+        summary.pruned_activity_stats = prune_session_activity_stats(pool, days_ago(days)).await?;
+    }
+    if let Some(days) = config.activity_minute_retention_days {
+        summary.pruned_minute_buckets = prune_minute_activity_rollups(pool, days_ago(days)).await?;
+    }
+    if let Some(days) = config.activity_artcc_retention_days {
+        summary.pruned_artcc_activity_stats =
+            prune_artcc_activity_stats(pool, days_ago(days)).await?;
+    }
+    if let Some(days) = config.message_retention_days {
+        summary.pruned_messages = prune_datafeed_messages(pool, days_ago(days)).await?;
+    }
+
+    Ok(summary)
+}
+
+/// Rolls up the session totals of every UTC day that ended before the newest applied datafeed and
+/// hasn't been rolled up yet. Returns the number of days rolled up.
+#[instrument(skip(pool))]
+pub async fn rollup_completed_days(pool: &Pool<Postgres>) -> Result<u64, QueryError> {
+    let Some(high_water_mark) = fetch_high_water_mark(pool).await? else {
+        return Ok(0);
+    };
+
+    let days = fetch_pending_daily_rollups(pool, high_water_mark).await?;
+    for day in &days {
+        let rows = rollup_day(pool, *day).await?;
+        debug!(name: "maintenance.daily_rollup.day_completed", %day, rows, "rolled up session totals for day");
+    }
+
+    Ok(days.len() as u64)
+}
+
+/// The latest UTC day whose sessions can be rolled up once every datafeed up to
+/// `high_water_mark` has been applied. The day the high water mark falls in can still change.
+pub fn last_complete_day(high_water_mark: DateTime<Utc>) -> NaiveDate {
+    high_water_mark.date_naive() - ChronoDuration::days(1)
+}
+
+/// Recomputes one UTC day's session totals in its own transaction. Returns the rows written.
+pub async fn rollup_day(pool: &Pool<Postgres>, day: DateTime<Utc>) -> Result<u64, QueryError> {
+    let mut tx = pool.begin().await?;
+    let rows = rollup_daily_session_stats(tx.as_mut(), day).await?;
+    tx.commit().await?;
+    Ok(rows)
+}
+
+/// Runs maintenance every `period` until shutdown. Failures are logged and retried on the next
+/// tick rather than stopping the processor.
+pub async fn run_maintenance_loop(
+    pool: Pool<Postgres>,
+    config: MaintenanceConfig,
+    period: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            () = shutdown.cancelled() => {
+                info!(name: "maintenance.shutdown.received", "shutdown requested, exiting maintenance loop");
+                break;
+            }
+            _ = ticker.tick() => {
+                match run_maintenance(&pool, &config).await {
+                    Ok(summary) => log_summary(&summary),
+                    Err(e) => warn!(name: "maintenance.failed", error = ?e, "failed to run maintenance"),
+                }
+            }
+        }
+    }
+}
+
+pub fn log_summary(summary: &MaintenanceSummary) {
+    info!(
+        name: "maintenance.completed",
+        minute_buckets = summary.minute_buckets,
+        hour_buckets = summary.hour_buckets,
+        day_buckets = summary.day_buckets,
+        session_days = summary.session_days,
+        pruned_activity_stats = summary.pruned_activity_stats,
+        pruned_minute_buckets = summary.pruned_minute_buckets,
+        pruned_artcc_activity_stats = summary.pruned_artcc_activity_stats,
+        pruned_messages = summary.pruned_messages,
+        "rolled up activity stats and pruned old rows"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_complete_day_is_before_the_high_water_mark() {
+        let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+        let day = |date: &str| date.parse().unwrap();
+        assert_eq!(
+            last_complete_day(at("2025-12-21T13:45:00Z")),
+            day("2025-12-20")
+        );
+        // Nothing of the 21st has been applied yet, but the 20th has ended
+        assert_eq!(
+            last_complete_day(at("2025-12-21T00:00:00Z")),
+            day("2025-12-20")
+        );
+    }
+}
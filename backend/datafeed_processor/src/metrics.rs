@@ -1,18 +1,63 @@
-use opentelemetry::global;
-use opentelemetry::metrics::{Counter, Gauge};
+//! Every attribute attached here has a small, fixed set of values (an outcome, a close reason, a
+//! query name or an ARTCC ID), so the number of time series stays bounded no matter how many
+//! datafeeds are processed.
+
+use crate::helpers::ControllerCloseReason;
+use opentelemetry::metrics::{Counter, Gauge, Histogram};
+use opentelemetry::{KeyValue, global};
+use std::future::Future;
+use tokio::time::Instant;
 
 #[derive(Clone, Default)]
 pub struct Metrics {
     pub datafeeds: DatafeedsMetrics,
     pub sessions: SessionsMetrics,
     pub active: ActiveMetrics,
+    pub queue: QueueMetrics,
+    pub queries: QueryMetrics,
+}
+
+/// What happened to a datafeed taken off the queue or fetched directly
+#[derive(Debug, Clone, Copy)]
+pub enum DatafeedOutcome {
+    /// Not seen before, so sessions were updated from it
+    New,
+    /// Already stored, so only the message was recorded
+    Duplicate,
+    /// Couldn't be deserialized and was moved to `datafeed_dead_letters`
+    DeadLettered,
+    /// Older than a datafeed already applied, so stored without updating sessions
+    OutOfOrder,
+}
+
+impl DatafeedOutcome {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Duplicate => "duplicate",
+            Self::DeadLettered => "dead_lettered",
+            Self::OutOfOrder => "out_of_order",
+        }
+    }
+
+    pub fn attributes(self) -> [KeyValue; 1] {
+        [KeyValue::new("outcome", self.as_str())]
+    }
 }
 
 #[derive(Clone)]
 pub struct DatafeedsMetrics {
+    /// Datafeeds handled, by `outcome`
     pub processed: Counter<u64>,
+    /// Time spent handling a single datafeed, by `outcome`
+    pub processing_duration: Histogram<f64>,
+    pub controllers_skipped: Counter<u64>,
+    /// Unrecognised enum values, by the `field` they were found in
+    pub unknown_values: Counter<u64>,
     pub bytes_uncompressed: Counter<u64>,
     pub bytes_compressed: Counter<u64>,
+    /// Time from a datafeed being enqueued to it being processed
+    pub processing_delay: Histogram<f64>,
 }
 
 #[derive(Clone)]
@@ -20,6 +65,16 @@ pub struct SessionsMetrics {
     pub controller_opened: Counter<u64>,
     pub callsign_opened: Counter<u64>,
     pub position_opened: Counter<u64>,
+    /// Controller sessions closed, by `reason`
+    pub controller_closed: Counter<u64>,
+    pub callsign_closed: Counter<u64>,
+    pub position_closed: Counter<u64>,
+}
+
+#[derive(Clone)]
+pub struct QueueMetrics {
+    pub depth: Gauge<u64>,
+    pub oldest_age: Gauge<f64>,
 }
 
 #[derive(Clone)]
@@ -27,12 +82,28 @@ pub struct ActiveMetrics {
     pub controllers: Gauge<u64>,
     pub callsigns: Gauge<u64>,
     pub positions: Gauge<u64>,
+    /// Active sessions under each root ARTCC, by `artcc`
+    pub artcc_controllers: Gauge<u64>,
+    pub artcc_callsigns: Gauge<u64>,
+    pub artcc_positions: Gauge<u64>,
+}
+
+#[derive(Clone)]
+pub struct QueryMetrics {
+    /// Time taken by a query, by `query` name and `outcome`
+    pub duration: Histogram<f64>,
 }
 
 impl Default for DatafeedsMetrics {
     fn default() -> Self {
         let meter = global::meter("datafeed_processor");
         let processed = meter.u64_counter("datafeeds.processed").build();
+        let processing_duration = meter
+            .f64_histogram("datafeeds.processing.duration")
+            .with_unit("s")
+            .build();
+        let controllers_skipped = meter.u64_counter("datafeeds.controllers.skipped").build();
+        let unknown_values = meter.u64_counter("datafeeds.unknown_values").build();
         let bytes_uncompressed = meter
             .u64_counter("datafeeds.processed.bytes.uncompressed")
             .with_unit("B")
@@ -41,41 +112,120 @@ impl Default for DatafeedsMetrics {
             .u64_counter("datafeeds.processed.bytes.compressed")
             .with_unit("B")
             .build();
+        let processing_delay = meter
+            .f64_histogram("datafeeds.processing_delay")
+            .with_unit("s")
+            .build();
 
         Self {
             processed,
+            processing_duration,
+            controllers_skipped,
+            unknown_values,
             bytes_uncompressed,
             bytes_compressed,
+            processing_delay,
         }
     }
 }
 
+impl DatafeedsMetrics {
+    pub fn record_processed(&self, outcome: DatafeedOutcome, started: Instant) {
+        let attributes = outcome.attributes();
+        self.processed.add(1, &attributes);
+        self.processing_duration
+            .record(started.elapsed().as_secs_f64(), &attributes);
+    }
+}
+
 impl Default for SessionsMetrics {
     fn default() -> Self {
         let meter = global::meter("datafeed_processor");
         let controller_opened = meter.u64_counter("sessions.controller.opened").build();
         let callsign_opened = meter.u64_counter("sessions.callsign.opened").build();
         let position_opened = meter.u64_counter("sessions.position.opened").build();
+        let controller_closed = meter.u64_counter("sessions.controller.closed").build();
+        let callsign_closed = meter.u64_counter("sessions.callsign.closed").build();
+        let position_closed = meter.u64_counter("sessions.position.closed").build();
 
         Self {
             controller_opened,
             callsign_opened,
             position_opened,
+            controller_closed,
+            callsign_closed,
+            position_closed,
         }
     }
 }
 
+impl SessionsMetrics {
+    pub fn record_controller_closed(&self, reason: &ControllerCloseReason) {
+        self.controller_closed
+            .add(1, &[KeyValue::new("reason", reason.as_str())]);
+    }
+}
+
 impl Default for ActiveMetrics {
     fn default() -> Self {
         let meter = global::meter("datafeed_processor");
         let controllers = meter.u64_gauge("sessions.controller.active").build();
         let callsigns = meter.u64_gauge("sessions.callsign.active").build();
         let positions = meter.u64_gauge("sessions.position.active").build();
+        let artcc_controllers = meter.u64_gauge("artcc.sessions.controller.active").build();
+        let artcc_callsigns = meter.u64_gauge("artcc.sessions.callsign.active").build();
+        let artcc_positions = meter.u64_gauge("artcc.sessions.position.active").build();
 
         Self {
             controllers,
             callsigns,
             positions,
+            artcc_controllers,
+            artcc_callsigns,
+            artcc_positions,
         }
     }
 }
+
+impl Default for QueueMetrics {
+    fn default() -> Self {
+        let meter = global::meter("datafeed_processor");
+        let depth = meter.u64_gauge("queue.depth").build();
+        let oldest_age = meter.f64_gauge("queue.oldest_age").with_unit("s").build();
+
+        Self { depth, oldest_age }
+    }
+}
+
+impl Default for QueryMetrics {
+    fn default() -> Self {
+        let meter = global::meter("datafeed_processor");
+        let duration = meter
+            .f64_histogram("db.query.duration")
+            .with_unit("s")
+            .build();
+
+        Self { duration }
+    }
+}
+
+impl QueryMetrics {
+    /// Awaits `query`, recording how long it took under the `query` name
+    pub async fn time<T, E>(
+        &self,
+        query: &'static str,
+        future: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let started = Instant::now();
+        let res = future.await;
+        let outcome = if res.is_ok() { "ok" } else { "error" };
+        self.duration.record(
+            started.elapsed().as_secs_f64(),
+            &[
+                KeyValue::new("query", query),
+                KeyValue::new("outcome", outcome),
+            ],
+        );
+        res
+    }
+}
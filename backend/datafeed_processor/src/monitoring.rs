@@ -0,0 +1,58 @@
+use crate::database::queries::{QueryError, fetch_queue_stats};
+use crate::metrics::QueueMetrics;
+use chrono::{TimeDelta, Utc};
+use shared::health::Health;
+use sqlx::{Pool, Postgres};
+use tokio::time::{Duration, MissedTickBehavior, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, instrument, warn};
+
+/// Samples `datafeed_queue` every `period` until shutdown, so a processor that has stopped
+/// keeping up is visible in metrics and readiness even while it isn't processing anything.
+pub async fn run_queue_monitor_loop(
+    pool: Pool<Postgres>,
+    health: Health,
+    metrics: QueueMetrics,
+    period: Duration,
+    shutdown: CancellationToken,
+) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            () = shutdown.cancelled() => {
+                info!(name: "queue_monitor.shutdown.received", "shutdown requested, exiting queue monitor loop");
+                break;
+            }
+            _ = ticker.tick() => {
+                if let Err(e) = sample_queue(&pool, &health, &metrics).await {
+                    warn!(name: "queue_monitor.failed", error = ?e, "failed to sample datafeed queue");
+                }
+            }
+        }
+    }
+}
+
+#[instrument(skip_all)]
+async fn sample_queue(
+    pool: &Pool<Postgres>,
+    health: &Health,
+    metrics: &QueueMetrics,
+) -> Result<(), QueryError> {
+    let stats = fetch_queue_stats(pool).await?;
+    let depth = u64::try_from(stats.depth).unwrap_or_default();
+    let oldest_age = stats
+        .oldest_created_at
+        .map(|created_at| Utc::now() - created_at);
+    debug!(name: "queue_monitor.sampled", depth, oldest_age = ?oldest_age, "sampled datafeed queue");
+
+    metrics.depth.record(depth, &[]);
+    metrics
+        .oldest_age
+        .record(oldest_age.map_or(0.0, TimeDelta::as_seconds_f64), &[]);
+    health.set_queue_depth(depth);
+    health.set_oldest_queued_at(stats.oldest_created_at);
+
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(about = "Reports drift between stored vNAS payloads and the shared DTOs")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+    /// Output format of the report
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    pub format: Format,
+    /// Exit with a non-zero status if any drift was found
+    #[arg(long, global = true)]
+    pub fail_on_drift: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Checks datafeed payloads against `DatafeedRoot`
+    Datafeed(DatafeedArgs),
+    /// Checks responses from the vNAS ARTCCs endpoint against `ArtccRoot`
+    Artccs {
+        /// Directory of JSON files to check
+        #[arg(long)]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+pub struct DatafeedSource {
+    /// Directory of JSON files to check
+    #[arg(long)]
+    pub dir: Option<PathBuf>,
+    /// Read stored payloads from `datafeed_payloads`, using the configured database. Days that
+    /// have been pruned are read from the configured archive.
+    #[arg(long)]
+    pub database: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DatafeedArgs {
+    #[command(flatten)]
+    pub source: DatafeedSource,
+    /// Only check stored payloads updated at or after this time (RFC 3339)
+    #[arg(long, requires = "database")]
+    pub since: Option<DateTime<Utc>>,
+    /// Maximum number of stored payloads to check, oldest first
+    #[arg(long, requires = "database", default_value_t = 1000)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
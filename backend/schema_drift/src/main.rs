@@ -0,0 +1,67 @@
+#[warn(clippy::pedantic)]
+mod cli;
+mod source;
+
+use clap::Parser;
+use cli::{Cli, Command, Format};
+use shared::archive::ArchiveStore;
+use shared::error::InitializationError;
+use shared::vnas::drift::{DriftReport, check_artccs, check_datafeed};
+use shared::{initialize_db, load_config};
+use source::{SourceError, read_datafeed_payloads, read_dir};
+use std::process::ExitCode;
+use thiserror::Error;
+
+#[tokio::main]
+async fn main() -> Result<ExitCode, AppError> {
+    let cli = Cli::parse();
+
+    let mut report = DriftReport::default();
+    match cli.command {
+        Command::Datafeed(args) => {
+            let payloads = if let Some(dir) = args.source.dir {
+                read_dir(&dir)?
+            } else {
+                let config = load_config().map_err(InitializationError::from)?;
+                let pool = initialize_db(&config.postgres, false).await?;
+                let archive = config
+                    .archive
+                    .as_ref()
+                    .map(ArchiveStore::from_config)
+                    .transpose()?;
+                read_datafeed_payloads(&pool, archive.as_ref(), args.since, args.limit).await?
+            };
+            for payload in &payloads {
+                report.add(&payload.source, payload.check(check_datafeed));
+            }
+        }
+        Command::Artccs { dir } => {
+            for payload in &read_dir(&dir)? {
+                report.add(&payload.source, payload.check(check_artccs));
+            }
+        }
+    }
+
+    match cli.format {
+        Format::Text => print!("{report}"),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+    }
+
+    if cli.fail_on_drift && !report.is_empty() {
+        Ok(ExitCode::FAILURE)
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[derive(Debug, Error)]
+enum AppError {
+    #[error("initialization error: {0}")]
+    Initialization(#[from] InitializationError),
+    #[error(transparent)]
+    Source(#[from] SourceError),
+    #[error("failed to open archive: {0}")]
+    Archive(#[from] shared::archive::ArchiveError),
+    #[error("failed to serialize report: {0}")]
+    Serialize(#[from] serde_json::Error),
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use shared::archive::{ArchiveError, ArchiveStore, read_datafeeds};
+use shared::vnas::drift::{DriftKind, Finding};
+use sqlx::{Pool, Postgres};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SourceError {
+    #[error("failed to read {0}: {1}")]
+    Io(String, #[source] std::io::Error),
+    #[error("database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+}
+
+/// A stored payload, or the reason it couldn't be read as JSON.
+pub struct Payload {
+    pub source: String,
+    pub value: Result<Value, String>,
+}
+
+impl Payload {
+    /// Runs `check` against the payload, reporting unreadable payloads as incompatible.
+    pub fn check(&self, check: impl Fn(&Value) -> Vec<Finding>) -> Vec<Finding> {
+        match &self.value {
+            Ok(value) => check(value),
+            Err(e) => vec![Finding {
+                kind: DriftKind::Incompatible,
+                path: String::new(),
+                detail: e.clone(),
+            }],
+        }
+    }
+}
+
+/// Reads every `.json` file in `dir`, in file name order.
+pub fn read_dir(dir: &Path) -> Result<Vec<Payload>, SourceError> {
+    let io_error = |e| SourceError::Io(dir.display().to_string(), e);
+    let mut paths = std::fs::read_dir(dir)
+        .map_err(io_error)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(io_error)?;
+    paths.retain(|p| p.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+
+    paths
+        .into_iter()
+        .map(|path| {
+            let source = path.display().to_string();
+            let contents = std::fs::read(&path).map_err(|e| SourceError::Io(source.clone(), e))?;
+            let value = serde_json::from_slice(&contents).map_err(|e| e.to_string());
+            Ok(Payload { source, value })
+        })
+        .collect()
+}
+
+/// Reads up to `limit` stored payloads, oldest first, reconstructing any that were stored as
+/// deltas and reading pruned days from `archive`.
+pub async fn read_datafeed_payloads(
+    pool: &Pool<Postgres>,
+    archive: Option<&ArchiveStore>,
+    since: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<Vec<Payload>, SourceError> {
+    let mut conn = pool.acquire().await?;
+    let datafeeds = read_datafeeds(&mut conn, archive, since, limit).await?;
+
+    Ok(datafeeds
+        .into_iter()
+        .map(|datafeed| Payload {
+            source: datafeed.origin,
+            value: datafeed
+                .payload
+                .map_err(|e| format!("failed to read payload: {e}")),
+        })
+        .collect())
+}
@@ -0,0 +1,18 @@
+-- Queued datafeeds that could not be deserialized into the current DTOs. They are moved out of
+-- datafeed_queue so that processing can continue, and can be re-driven once the DTOs are fixed.
+
+CREATE TABLE IF NOT EXISTS datafeed_dead_letters (
+    id uuid PRIMARY KEY,
+    queue_id uuid NOT NULL,
+    updated_at timestamptz NOT NULL,
+    payload jsonb NOT NULL,
+    error text NOT NULL,
+    enqueued_at timestamptz NOT NULL,
+    dead_lettered_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_datafeed_dead_letters_queue_id
+    ON datafeed_dead_letters (queue_id);
+
+CREATE INDEX IF NOT EXISTS idx_datafeed_dead_letters_updated_at
+    ON datafeed_dead_letters (updated_at);
@@ -0,0 +1,2 @@
+-- Ratings the datafeed DTOs don't recognise are stored as 'unknown' rather than failing the payload.
+ALTER TYPE user_rating ADD VALUE IF NOT EXISTS 'unknown';
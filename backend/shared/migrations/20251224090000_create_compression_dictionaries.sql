@@ -0,0 +1,21 @@
+-- Trained zstd dictionaries. Consecutive datafeeds are very similar, so a dictionary trained on
+-- recent payloads compresses them far better than plain zstd. Payloads compressed with a
+-- dictionary reference it by id and can only be decompressed with that exact dictionary.
+
+CREATE TABLE IF NOT EXISTS compression_dictionaries (
+    id uuid PRIMARY KEY,
+    dictionary bytea NOT NULL,
+    sample_count integer NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now()
+);
+
+ALTER TABLE datafeed_payloads
+    ADD COLUMN IF NOT EXISTS dictionary_id uuid REFERENCES compression_dictionaries (id);
+
+ALTER TABLE datafeed_payloads
+    DROP CONSTRAINT IF EXISTS chk_datafeed_payloads_dictionary,
+    ADD CONSTRAINT chk_datafeed_payloads_dictionary
+        CHECK ((compression_algo = 'zstd_dict') = (dictionary_id IS NOT NULL));
+
+CREATE INDEX IF NOT EXISTS idx_datafeed_payloads_compression
+    ON datafeed_payloads (compression_algo, dictionary_id);
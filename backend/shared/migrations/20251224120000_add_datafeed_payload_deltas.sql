@@ -0,0 +1,18 @@
+-- Datafeeds can be stored as a delta against the previously stored datafeed instead of in full.
+-- Every delta chain starts at a snapshot (keyframe); payload_compressed then holds the compressed
+-- delta rather than the datafeed, while original_size_bytes is still the size of the full datafeed.
+
+ALTER TABLE datafeed_payloads
+    ADD COLUMN IF NOT EXISTS payload_kind text NOT NULL DEFAULT 'snapshot',
+    ADD COLUMN IF NOT EXISTS base_payload_id uuid REFERENCES datafeed_payloads (id);
+
+ALTER TABLE datafeed_payloads
+    DROP CONSTRAINT IF EXISTS chk_datafeed_payloads_kind,
+    DROP CONSTRAINT IF EXISTS chk_datafeed_payloads_base,
+    ADD CONSTRAINT chk_datafeed_payloads_kind
+        CHECK (payload_kind IN ('snapshot', 'delta')),
+    ADD CONSTRAINT chk_datafeed_payloads_base
+        CHECK ((payload_kind = 'delta') = (base_payload_id IS NOT NULL));
+
+CREATE INDEX IF NOT EXISTS idx_datafeed_payloads_base_payload_id
+    ON datafeed_payloads (base_payload_id);
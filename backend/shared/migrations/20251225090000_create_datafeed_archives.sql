@@ -0,0 +1,24 @@
+-- Days of datafeeds exported to the archive. Once an export has been verified, the day's rows are
+-- pruned from datafeed_payloads and pruned_at is set; the archive is then the only copy.
+
+CREATE TABLE IF NOT EXISTS datafeed_archives (
+    day date PRIMARY KEY,
+    object_key text NOT NULL,
+    parquet_key text,
+    payload_count integer NOT NULL,
+    first_updated_at timestamptz NOT NULL,
+    last_updated_at timestamptz NOT NULL,
+    size_bytes bigint NOT NULL,
+    archived_at timestamptz NOT NULL,
+    pruned_at timestamptz
+);
+
+-- Processing history outlives the payloads it refers to once they're pruned
+ALTER TABLE datafeed_messages
+    ALTER COLUMN payload_id DROP NOT NULL,
+    DROP CONSTRAINT IF EXISTS datafeed_messages_payload_fk,
+    ADD CONSTRAINT datafeed_messages_payload_fk
+        FOREIGN KEY (payload_id) REFERENCES datafeed_payloads (id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_datafeed_messages_payload_id
+    ON datafeed_messages (payload_id);
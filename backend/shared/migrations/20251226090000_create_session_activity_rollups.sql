@@ -0,0 +1,55 @@
+-- Downsampled session_activity_stats. Each resolution is rolled up from the next finer one
+-- (raw -> 1m -> 1h -> 1d), so finer data can be pruned once it's been rolled up.
+
+CREATE TABLE IF NOT EXISTS session_activity_stats_1m (
+    bucket timestamptz PRIMARY KEY,
+    samples integer NOT NULL,
+    min_controllers integer NOT NULL,
+    max_controllers integer NOT NULL,
+    avg_controllers double precision NOT NULL,
+    min_callsigns integer NOT NULL,
+    max_callsigns integer NOT NULL,
+    avg_callsigns double precision NOT NULL,
+    min_positions integer NOT NULL,
+    max_positions integer NOT NULL,
+    avg_positions double precision NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS session_activity_stats_1h (
+    bucket timestamptz PRIMARY KEY,
+    samples integer NOT NULL,
+    min_controllers integer NOT NULL,
+    max_controllers integer NOT NULL,
+    avg_controllers double precision NOT NULL,
+    min_callsigns integer NOT NULL,
+    max_callsigns integer NOT NULL,
+    avg_callsigns double precision NOT NULL,
+    min_positions integer NOT NULL,
+    max_positions integer NOT NULL,
+    avg_positions double precision NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS session_activity_stats_1d (
+    bucket timestamptz PRIMARY KEY,
+    samples integer NOT NULL,
+    min_controllers integer NOT NULL,
+    max_controllers integer NOT NULL,
+    avg_controllers double precision NOT NULL,
+    min_callsigns integer NOT NULL,
+    max_callsigns integer NOT NULL,
+    avg_callsigns double precision NOT NULL,
+    min_positions integer NOT NULL,
+    max_positions integer NOT NULL,
+    avg_positions double precision NOT NULL,
+    updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+-- Rollups only revisit buckets whose source rows changed since the last run
+CREATE INDEX IF NOT EXISTS idx_session_activity_stats_created_at
+    ON session_activity_stats (created_at);
+CREATE INDEX IF NOT EXISTS idx_session_activity_stats_1m_updated_at
+    ON session_activity_stats_1m (updated_at);
+CREATE INDEX IF NOT EXISTS idx_session_activity_stats_1h_updated_at
+    ON session_activity_stats_1h (updated_at);
@@ -0,0 +1,29 @@
+-- Per-datafeed snapshots of active session counts per root ARTCC, and optionally per facility.
+-- Sessions on positions that aren't in facility_positions aren't attributed to any ARTCC.
+
+CREATE TABLE IF NOT EXISTS artcc_activity_stats (
+    artcc_id text NOT NULL,
+    observed_at timestamptz NOT NULL,
+    active_controllers integer NOT NULL,
+    active_callsigns integer NOT NULL,
+    active_positions integer NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (artcc_id, observed_at)
+);
+
+CREATE INDEX IF NOT EXISTS idx_artcc_activity_stats_observed_at
+    ON artcc_activity_stats (observed_at);
+
+CREATE TABLE IF NOT EXISTS facility_activity_stats (
+    facility_id text NOT NULL,
+    observed_at timestamptz NOT NULL,
+    artcc_id text NOT NULL,
+    active_controllers integer NOT NULL,
+    active_callsigns integer NOT NULL,
+    active_positions integer NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (facility_id, observed_at)
+);
+
+CREATE INDEX IF NOT EXISTS idx_facility_activity_stats_observed_at
+    ON facility_activity_stats (observed_at);
@@ -0,0 +1,9 @@
+-- Datafeeds older than the newest one already applied are stored but not applied to sessions, so
+-- the window they fall in can be re-derived later if needed.
+
+ALTER TABLE datafeed_messages
+    ADD COLUMN IF NOT EXISTS out_of_order boolean NOT NULL DEFAULT false;
+
+CREATE INDEX IF NOT EXISTS idx_datafeed_messages_out_of_order
+    ON datafeed_messages (processed_at)
+    WHERE out_of_order;
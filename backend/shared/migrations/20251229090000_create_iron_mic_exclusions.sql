@@ -0,0 +1,26 @@
+-- Callsigns, positions and controllers left out of the Iron Mic leaderboards, managed through the
+-- data_api admin endpoints. Callsign values are patterns where `*` matches any run of characters.
+
+create type iron_mic_exclusion_kind as enum (
+    'callsign',
+    'position',
+    'cid'
+);
+
+CREATE TABLE IF NOT EXISTS iron_mic_exclusions (
+    id uuid PRIMARY KEY DEFAULT uuidv7(),
+    kind iron_mic_exclusion_kind NOT NULL,
+    value text NOT NULL,
+    reason text NOT NULL,
+    -- Exclusions without an expiry apply until they're deleted
+    expires_at timestamptz,
+    -- CID of the admin who added it; NULL for exclusions added by migrations
+    created_by integer,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    UNIQUE (kind, value)
+);
+
+-- Previously hard-coded in data_api
+INSERT INTO iron_mic_exclusions (kind, value, reason)
+VALUES ('callsign', 'SJU_APP', 'Excluded before exclusions were configurable')
+ON CONFLICT DO NOTHING;
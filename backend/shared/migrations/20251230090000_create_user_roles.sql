@@ -0,0 +1,19 @@
+-- Roles granted to VATSIM users in data_api. ARTCC staff roles are scoped to a root ARTCC, admin
+-- roles apply everywhere. Admins listed in the config have the role without a row here.
+
+create type user_role as enum (
+    'admin',
+    'artcc_staff'
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    id uuid PRIMARY KEY DEFAULT uuidv7(),
+    cid integer NOT NULL,
+    role user_role NOT NULL,
+    artcc_id text,
+    -- CID of the admin who granted it
+    granted_by integer NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    CONSTRAINT user_roles_artcc_scope CHECK ((role = 'artcc_staff') = (artcc_id IS NOT NULL)),
+    CONSTRAINT user_roles_grant_key UNIQUE NULLS NOT DISTINCT (cid, role, artcc_id)
+);
@@ -0,0 +1,24 @@
+-- API keys for programmatic access to data_api. Only a SHA-256 hash of each key is stored; the
+-- key itself is shown once, when it's created.
+
+create type api_key_scope as enum (
+    'read',
+    'manage'
+);
+
+CREATE TABLE IF NOT EXISTS api_keys (
+    id uuid PRIMARY KEY DEFAULT uuidv7(),
+    cid integer NOT NULL,
+    name text NOT NULL,
+    -- The start of the key, so its owner can tell keys apart
+    prefix text NOT NULL,
+    key_hash text NOT NULL UNIQUE,
+    scopes api_key_scope[] NOT NULL,
+    -- Requests per minute, or the configured default if NULL
+    rate_limit_per_minute integer,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    last_used_at timestamptz,
+    revoked_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS idx_api_keys_cid ON api_keys (cid);
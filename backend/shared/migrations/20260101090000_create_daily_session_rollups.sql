@@ -0,0 +1,55 @@
+-- Per-day totals of callsign, position, controller and ARTCC sessions, so long-range leaderboards
+-- only scan raw sessions for the partial days at the edges of their interval. Days are UTC and
+-- stored as the timestamptz they start at. A day is only rolled up once every datafeed in it has
+-- been applied, and is recorded in daily_session_rollups so readers know which days are complete.
+
+CREATE TABLE IF NOT EXISTS daily_session_rollups (
+    day timestamptz PRIMARY KEY,
+    rolled_up_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS callsign_daily_stats (
+    day timestamptz NOT NULL,
+    prefix text NOT NULL,
+    suffix text NOT NULL,
+    seconds_active bigint NOT NULL,
+    session_count integer NOT NULL,
+    max_concurrent integer NOT NULL,
+    PRIMARY KEY (day, prefix, suffix)
+);
+
+CREATE TABLE IF NOT EXISTS position_daily_stats (
+    day timestamptz NOT NULL,
+    position_id text NOT NULL,
+    seconds_active bigint NOT NULL,
+    session_count integer NOT NULL,
+    max_concurrent integer NOT NULL,
+    PRIMARY KEY (day, position_id)
+);
+
+-- Split by callsign and primary position so callsign and position exclusions still apply.
+-- Observer sessions aren't counted.
+CREATE TABLE IF NOT EXISTS controller_daily_stats (
+    day timestamptz NOT NULL,
+    cid integer NOT NULL,
+    connected_callsign text NOT NULL,
+    primary_position_id text NOT NULL,
+    -- Name and start of the controller's latest session in the group that day
+    name text NOT NULL,
+    last_start_time timestamptz NOT NULL,
+    seconds_active bigint NOT NULL,
+    session_count integer NOT NULL,
+    max_concurrent integer NOT NULL,
+    PRIMARY KEY (day, cid, connected_callsign, primary_position_id)
+);
+
+-- Position sessions per root ARTCC, so seconds_active is staffed position time. Positions that
+-- aren't in facility_positions aren't attributed to any ARTCC.
+CREATE TABLE IF NOT EXISTS artcc_daily_stats (
+    day timestamptz NOT NULL,
+    artcc_id text NOT NULL,
+    seconds_active bigint NOT NULL,
+    session_count integer NOT NULL,
+    max_concurrent integer NOT NULL,
+    PRIMARY KEY (day, artcc_id)
+);
@@ -0,0 +1,7 @@
+-- The current term of each leader lock, bumped whenever a replica is elected. Leaders check their
+-- term inside their transactions, so one that lost its lock without noticing can't commit.
+
+CREATE TABLE IF NOT EXISTS leader_terms (
+    lock_key bigint PRIMARY KEY,
+    term bigint NOT NULL
+);
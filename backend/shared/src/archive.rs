@@ -0,0 +1,311 @@
+use crate::ArchiveConfig;
+use crate::payloads::{PayloadError, PayloadReader, StoredPayload};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use object_store::aws::AmazonS3Builder;
+use object_store::local::LocalFileSystem;
+use object_store::path::Path;
+use object_store::prefix::PrefixStore;
+use object_store::{ObjectStore, ObjectStoreExt, PutPayload};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Executor, PgConnection, Postgres};
+use std::io::{BufRead, BufReader, Write};
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// zstd level for archives. They're written once and kept for a long time, so it's worth spending
+/// more CPU than for live payloads.
+const ARCHIVE_LEVEL: i32 = 19;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("object store error: {0}")]
+    Store(#[from] object_store::Error),
+    #[error("failed to create archive directory {0}: {1}")]
+    Directory(String, #[source] std::io::Error),
+    #[error("archive compression failed: {0}")]
+    Compression(#[from] std::io::Error),
+    #[error("archive record is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error(transparent)]
+    Payload(#[from] PayloadError),
+}
+
+/// A single line of a datafeed archive: a stored payload, reconstructed in full, and the metadata
+/// of its `datafeed_payloads` row.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivedPayload {
+    pub id: Uuid,
+    pub updated_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub original_size_bytes: i32,
+    pub payload: Value,
+}
+
+/// A day that has been exported to the archive, from `datafeed_archives`
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ArchivedDay {
+    pub day: NaiveDate,
+    pub object_key: String,
+    pub parquet_key: Option<String>,
+    pub payload_count: i32,
+    pub first_updated_at: DateTime<Utc>,
+    pub last_updated_at: DateTime<Utc>,
+    pub size_bytes: i64,
+    pub archived_at: DateTime<Utc>,
+    pub pruned_at: Option<DateTime<Utc>>,
+}
+
+/// Archived datafeeds on local disk or in S3-compatible storage. Each UTC day is stored as
+/// `datafeeds/YYYY/MM/DD.ndjson.zst`, and optionally as `controllers/YYYY/MM/DD.parquet`.
+#[derive(Debug, Clone)]
+pub struct ArchiveStore {
+    store: Arc<dyn ObjectStore>,
+}
+
+impl ArchiveStore {
+    pub fn from_config(config: &ArchiveConfig) -> Result<Self, ArchiveError> {
+        let store: Arc<dyn ObjectStore> =
+            if let Some(bucket_and_prefix) = config.location.strip_prefix("s3://") {
+                let (bucket, prefix) = bucket_and_prefix
+                    .split_once('/')
+                    .unwrap_or((bucket_and_prefix, ""));
+                let s3 = AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?;
+                if prefix.is_empty() {
+                    Arc::new(s3)
+                } else {
+                    Arc::new(PrefixStore::new(s3, prefix))
+                }
+            } else {
+                let dir = config
+                    .location
+                    .strip_prefix("file://")
+                    .unwrap_or(&config.location);
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| ArchiveError::Directory(dir.to_string(), e))?;
+                Arc::new(LocalFileSystem::new_with_prefix(dir)?)
+            };
+
+        Ok(Self { store })
+    }
+
+    pub fn new(store: Arc<dyn ObjectStore>) -> Self {
+        Self { store }
+    }
+
+    pub fn datafeeds_key(day: NaiveDate) -> String {
+        format!(
+            "datafeeds/{:04}/{:02}/{:02}.ndjson.zst",
+            day.year(),
+            day.month(),
+            day.day()
+        )
+    }
+
+    pub fn controllers_key(day: NaiveDate) -> String {
+        format!(
+            "controllers/{:04}/{:02}/{:02}.parquet",
+            day.year(),
+            day.month(),
+            day.day()
+        )
+    }
+
+    pub async fn put(&self, key: &str, bytes: Vec<u8>) -> Result<(), ArchiveError> {
+        self.store
+            .put(&Path::from(key), PutPayload::from(bytes))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, ArchiveError> {
+        let result = self.store.get(&Path::from(key)).await?;
+        Ok(result.bytes().await?.to_vec())
+    }
+
+    /// Reads every payload archived for `day`, in `updated_at` order
+    pub async fn read_day(&self, day: NaiveDate) -> Result<Vec<ArchivedPayload>, ArchiveError> {
+        decode_payloads(&self.get(&Self::datafeeds_key(day)).await?)
+    }
+}
+
+/// Writes payloads as zstd-compressed newline-delimited JSON, one at a time, so a day of
+/// datafeeds never has to be held in memory uncompressed
+pub struct ArchiveWriter {
+    encoder: zstd::Encoder<'static, Vec<u8>>,
+    count: usize,
+}
+
+impl ArchiveWriter {
+    pub fn new() -> Result<Self, ArchiveError> {
+        Ok(Self {
+            encoder: zstd::Encoder::new(Vec::new(), ARCHIVE_LEVEL)?,
+            count: 0,
+        })
+    }
+
+    pub fn write(&mut self, payload: &ArchivedPayload) -> Result<(), ArchiveError> {
+        serde_json::to_writer(&mut self.encoder, payload)?;
+        self.encoder.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Number of payloads written so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn finish(self) -> Result<Vec<u8>, ArchiveError> {
+        Ok(self.encoder.finish()?)
+    }
+}
+
+/// Encodes payloads as zstd-compressed newline-delimited JSON
+pub fn encode_payloads(payloads: &[ArchivedPayload]) -> Result<Vec<u8>, ArchiveError> {
+    let mut writer = ArchiveWriter::new()?;
+    for payload in payloads {
+        writer.write(payload)?;
+    }
+    writer.finish()
+}
+
+/// Decodes an archive lazily, one payload at a time
+pub fn iter_payloads(
+    bytes: &[u8],
+) -> Result<impl Iterator<Item = Result<ArchivedPayload, ArchiveError>> + '_, ArchiveError> {
+    Ok(BufReader::new(zstd::Decoder::new(bytes)?)
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(String::is_empty))
+        .map(|line| Ok(serde_json::from_str(&line?)?)))
+}
+
+pub fn decode_payloads(bytes: &[u8]) -> Result<Vec<ArchivedPayload>, ArchiveError> {
+    iter_payloads(bytes)?.collect()
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_archived_day<'e, E>(
+    executor: E,
+    day: NaiveDate,
+) -> Result<Option<ArchivedDay>, ArchiveError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, ArchivedDay>(
+        r"
+        SELECT day, object_key, parquet_key, payload_count, first_updated_at, last_updated_at,
+               size_bytes, archived_at, pruned_at
+        FROM datafeed_archives
+        WHERE day = $1
+        ",
+    )
+    .bind(day)
+    .fetch_optional(executor)
+    .await
+    .map_err(ArchiveError::from)
+}
+
+/// Returns archived days whose payloads have been pruned from Postgres and that contain payloads
+/// updated at or after `since`, oldest first
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_pruned_days<'e, E>(
+    executor: E,
+    since: Option<DateTime<Utc>>,
+) -> Result<Vec<ArchivedDay>, ArchiveError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, ArchivedDay>(
+        r"
+        SELECT day, object_key, parquet_key, payload_count, first_updated_at, last_updated_at,
+               size_bytes, archived_at, pruned_at
+        FROM datafeed_archives
+        WHERE pruned_at IS NOT NULL
+          AND ($1::timestamptz IS NULL OR last_updated_at >= $1)
+        ORDER BY day
+        ",
+    )
+    .bind(since)
+    .fetch_all(executor)
+    .await
+    .map_err(ArchiveError::from)
+}
+
+/// A stored datafeed, read from Postgres or from the archive
+#[derive(Debug)]
+pub struct HistoricalDatafeed {
+    pub updated_at: DateTime<Utc>,
+    /// Where the datafeed was read from, for reporting
+    pub origin: String,
+    pub payload: Result<Value, PayloadError>,
+}
+
+/// Reads up to `limit` stored datafeeds updated at or after `since`, oldest first. Days that have
+/// been archived and pruned from Postgres are read from `archive`, so callers don't need to care
+/// where a datafeed lives. Without an archive, only Postgres is read.
+pub async fn read_datafeeds(
+    conn: &mut PgConnection,
+    archive: Option<&ArchiveStore>,
+    since: Option<DateTime<Utc>>,
+    limit: usize,
+) -> Result<Vec<HistoricalDatafeed>, ArchiveError> {
+    let mut datafeeds = Vec::new();
+
+    if let Some(archive) = archive {
+        for day in fetch_pruned_days(&mut *conn, since).await? {
+            if datafeeds.len() >= limit {
+                return Ok(datafeeds);
+            }
+            let bytes = archive.get(&day.object_key).await?;
+            for payload in iter_payloads(&bytes)? {
+                let payload = payload?;
+                if since.is_some_and(|since| payload.updated_at < since) {
+                    continue;
+                }
+                if datafeeds.len() >= limit {
+                    break;
+                }
+                datafeeds.push(HistoricalDatafeed {
+                    updated_at: payload.updated_at,
+                    origin: format!("{}@{}", day.object_key, payload.updated_at.to_rfc3339()),
+                    payload: Ok(payload.payload),
+                });
+            }
+        }
+    }
+
+    let remaining = i64::try_from(limit - datafeeds.len()).unwrap_or(i64::MAX);
+    let rows = sqlx::query_as::<_, StoredPayload>(
+        r"
+        SELECT id, updated_at, payload_kind, base_payload_id, payload_compressed,
+               compression_algo, dictionary_id
+        FROM datafeed_payloads
+        WHERE $1::timestamptz IS NULL OR updated_at >= $1
+        ORDER BY updated_at
+        LIMIT $2
+        ",
+    )
+    .bind(since)
+    .bind(remaining)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut reader = PayloadReader::default();
+    for row in &rows {
+        datafeeds.push(HistoricalDatafeed {
+            updated_at: row.updated_at,
+            origin: format!("datafeed_payloads@{}", row.updated_at.to_rfc3339()),
+            payload: reader.read(&mut *conn, row).await,
+        });
+    }
+
+    Ok(datafeeds)
+}
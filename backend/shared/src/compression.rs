@@ -0,0 +1,256 @@
+use crate::CompressionConfig;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres};
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// `compression_algo` for payloads compressed with plain zstd
+pub const ZSTD: &str = "zstd";
+/// `compression_algo` for payloads compressed with zstd and a dictionary from
+/// `compression_dictionaries`, referenced by `dictionary_id`
+pub const ZSTD_DICT: &str = "zstd_dict";
+
+pub const DEFAULT_LEVEL: i32 = 3;
+/// zstd's own default maximum dictionary size
+pub const DEFAULT_MAX_DICTIONARY_SIZE: usize = 112_640;
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("zstd error: {0}")]
+    Zstd(#[from] std::io::Error),
+    #[error("unsupported compression algorithm {0}")]
+    UnsupportedAlgo(String),
+    #[error("payload compressed with {algo} requires a dictionary, but none was given")]
+    MissingDictionaryId { algo: String },
+    #[error("compression dictionary {0} does not exist")]
+    UnknownDictionary(Uuid),
+    #[error("database error: {0}")]
+    Db(#[from] sqlx::Error),
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Dictionary {
+    pub id: Uuid,
+    pub dictionary: Vec<u8>,
+    pub sample_count: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct Compressed {
+    pub bytes: Vec<u8>,
+    pub algo: &'static str,
+    pub dictionary_id: Option<Uuid>,
+}
+
+/// How new payloads are compressed: a zstd level and, optionally, a trained dictionary.
+#[derive(Debug, Clone)]
+pub struct Codec {
+    pub level: i32,
+    pub dictionary: Option<Arc<Dictionary>>,
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_LEVEL,
+            dictionary: None,
+        }
+    }
+}
+
+impl Codec {
+    /// Builds the codec for new payloads from config. With `use_dictionary`, the most recently
+    /// trained dictionary is used; dictionaries trained after startup are only picked up on restart.
+    pub async fn load<'e, E>(
+        executor: E,
+        config: &CompressionConfig,
+    ) -> Result<Self, CompressionError>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let dictionary = if config.use_dictionary {
+            let dictionary = fetch_latest_dictionary(executor).await?;
+            if dictionary.is_none() {
+                warn!(
+                    name: "compression.dictionary.missing",
+                    "dictionary compression is enabled but no dictionary has been trained, using plain zstd"
+                );
+            }
+            dictionary.map(Arc::new)
+        } else {
+            None
+        };
+
+        let codec = Self {
+            level: config.level,
+            dictionary,
+        };
+        info!(
+            name: "compression.codec.loaded",
+            level = codec.level,
+            target = ?codec.target(),
+            "loaded payload compression settings"
+        );
+        Ok(codec)
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Result<Compressed, CompressionError> {
+        match &self.dictionary {
+            Some(dictionary) => Ok(Compressed {
+                bytes: zstd::bulk::Compressor::with_dictionary(self.level, &dictionary.dictionary)?
+                    .compress(data)?,
+                algo: ZSTD_DICT,
+                dictionary_id: Some(dictionary.id),
+            }),
+            None => Ok(Compressed {
+                bytes: zstd::encode_all(data, self.level)?,
+                algo: ZSTD,
+                dictionary_id: None,
+            }),
+        }
+    }
+
+    /// The `(compression_algo, dictionary_id)` pair this codec writes
+    pub fn target(&self) -> (&'static str, Option<Uuid>) {
+        match &self.dictionary {
+            Some(dictionary) => (ZSTD_DICT, Some(dictionary.id)),
+            None => (ZSTD, None),
+        }
+    }
+}
+
+/// Decompresses `data` according to its stored `compression_algo`. `dictionary` must be the
+/// dictionary referenced by the payload's `dictionary_id` for `zstd_dict` payloads.
+pub fn decompress(
+    data: &[u8],
+    algo: &str,
+    dictionary: Option<&Dictionary>,
+) -> Result<Vec<u8>, CompressionError> {
+    match algo {
+        ZSTD => Ok(zstd::decode_all(data)?),
+        ZSTD_DICT => {
+            let dictionary = dictionary.ok_or_else(|| CompressionError::MissingDictionaryId {
+                algo: algo.to_string(),
+            })?;
+            let mut decoder =
+                zstd::stream::read::Decoder::with_dictionary(data, &dictionary.dictionary)?;
+            let mut decompressed = Vec::new();
+            decoder.read_to_end(&mut decompressed)?;
+            Ok(decompressed)
+        }
+        other => Err(CompressionError::UnsupportedAlgo(other.to_string())),
+    }
+}
+
+/// Trains a zstd dictionary of at most `max_size` bytes from sample payloads.
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> Result<Vec<u8>, CompressionError> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
+/// Dictionaries loaded from `compression_dictionaries`, so that decompressing many payloads only
+/// fetches each dictionary once.
+#[derive(Debug, Default)]
+pub struct DictionaryCache {
+    dictionaries: HashMap<Uuid, Arc<Dictionary>>,
+}
+
+impl DictionaryCache {
+    pub fn insert(&mut self, dictionary: Arc<Dictionary>) {
+        self.dictionaries.insert(dictionary.id, dictionary);
+    }
+
+    /// Decompresses a stored payload, loading its dictionary if it hasn't been seen yet.
+    pub async fn decompress<'e, E>(
+        &mut self,
+        executor: E,
+        data: &[u8],
+        algo: &str,
+        dictionary_id: Option<Uuid>,
+    ) -> Result<Vec<u8>, CompressionError>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let Some(id) = dictionary_id else {
+            return decompress(data, algo, None);
+        };
+
+        if !self.dictionaries.contains_key(&id) {
+            let dictionary = fetch_dictionary(executor, id)
+                .await?
+                .ok_or(CompressionError::UnknownDictionary(id))?;
+            self.insert(Arc::new(dictionary));
+        }
+        decompress(data, algo, self.dictionaries.get(&id).map(AsRef::as_ref))
+    }
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_dictionary<'e, E>(
+    executor: E,
+    id: Uuid,
+) -> Result<Option<Dictionary>, CompressionError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, Dictionary>(
+        r"
+        SELECT id, dictionary, sample_count, created_at
+        FROM compression_dictionaries
+        WHERE id = $1
+        ",
+    )
+    .bind(id)
+    .fetch_optional(executor)
+    .await
+    .map_err(CompressionError::from)
+}
+
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_latest_dictionary<'e, E>(
+    executor: E,
+) -> Result<Option<Dictionary>, CompressionError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, Dictionary>(
+        r"
+        SELECT id, dictionary, sample_count, created_at
+        FROM compression_dictionaries
+        ORDER BY created_at DESC, id DESC
+        LIMIT 1
+        ",
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(CompressionError::from)
+}
+
+#[instrument(level = "debug", skip(executor, dictionary))]
+pub async fn insert_dictionary<'e, E>(
+    executor: E,
+    dictionary: Vec<u8>,
+    sample_count: i32,
+) -> Result<Dictionary, CompressionError>
+where
+    E: Executor<'e, Database = Postgres>,
+{
+    sqlx::query_as::<_, Dictionary>(
+        r"
+        INSERT INTO compression_dictionaries (id, dictionary, sample_count, created_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, dictionary, sample_count, created_at
+        ",
+    )
+    .bind(Uuid::now_v7())
+    .bind(dictionary)
+    .bind(sample_count)
+    .bind(Utc::now())
+    .fetch_one(executor)
+    .await
+    .map_err(CompressionError::from)
+}
@@ -0,0 +1,374 @@
+//! Liveness and readiness endpoints shared by every service.
+//!
+//! Each service owns a [`Health`] handle, reports successes, errors and its view of the datafeed
+//! to it, and serves [`router`] on [`HealthConfig::bind_address`]. `/health/live` only says the
+//! process is up; `/health/ready` checks Postgres and the configured staleness thresholds and
+//! answers `503` when any of them fail. Readiness includes error messages, so services that also
+//! listen publicly expose only [`public_router`] there.
+
+use axum::Json;
+use axum::Router;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use chrono::{DateTime, TimeDelta, Utc};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{Pool, Postgres};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+/// How long the readiness check waits for Postgres before reporting it as unreachable
+const DATABASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HealthConfig {
+    pub bind_address: SocketAddr,
+    /// Services that work continuously are not ready once their last success is older than this
+    pub max_success_age_seconds: u64,
+    /// Services that track the datafeed are not ready once the latest datafeed they've handled
+    /// is older than this
+    pub max_lag_seconds: u64,
+    /// Services that consume a queue are not ready once its oldest entry is older than this
+    pub max_queue_age_seconds: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            max_success_age_seconds: 60,
+            max_lag_seconds: 120,
+            max_queue_age_seconds: 60,
+        }
+    }
+}
+
+/// Which staleness checks a service's readiness depends on
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Checks {
+    /// Fail once the last success is older than [`HealthConfig::max_success_age_seconds`]
+    pub success: bool,
+    /// Fail once the latest datafeed is older than [`HealthConfig::max_lag_seconds`]
+    pub lag: bool,
+    /// Fail once the oldest queued entry is older than [`HealthConfig::max_queue_age_seconds`]
+    pub queue_age: bool,
+}
+
+/// Shared, cheaply cloneable health state of a service
+#[derive(Clone)]
+pub struct Health {
+    service: &'static str,
+    config: HealthConfig,
+    checks: Checks,
+    db_pool: Option<Pool<Postgres>>,
+    started_at: DateTime<Utc>,
+    status: Arc<RwLock<Status>>,
+}
+
+#[derive(Default)]
+struct Status {
+    last_success_at: Option<DateTime<Utc>>,
+    last_error: Option<LastError>,
+    queue_depth: Option<u64>,
+    oldest_queued_at: Option<DateTime<Utc>>,
+    datafeed_updated_at: Option<DateTime<Utc>>,
+    standby: bool,
+    /// When the service last stopped being a standby
+    active_since: Option<DateTime<Utc>>,
+    details: BTreeMap<&'static str, Value>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LastError {
+    pub at: DateTime<Utc>,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Liveness {
+    pub status: &'static str,
+    pub service: &'static str,
+    pub started_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub service: &'static str,
+    pub checked_at: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_error: Option<LastError>,
+    pub queue_depth: Option<u64>,
+    /// When the oldest entry still in the queue was enqueued
+    pub oldest_queued_at: Option<DateTime<Utc>>,
+    /// Seconds between `oldest_queued_at` and `checked_at`
+    pub oldest_queued_age_seconds: Option<i64>,
+    /// `updatedAt` of the latest datafeed the service has handled
+    pub datafeed_updated_at: Option<DateTime<Utc>>,
+    /// Seconds between `datafeed_updated_at` and `checked_at`
+    pub lag_seconds: Option<i64>,
+    pub database: Option<DatabaseStatus>,
+    /// Whether another replica is leader, in which case staleness isn't checked
+    pub standby: bool,
+    /// Why the service isn't ready, empty when it is
+    pub problems: Vec<String>,
+    /// Service-specific state
+    pub details: BTreeMap<&'static str, Value>,
+}
+
+/// What [`public_router`] reveals about readiness
+#[derive(Debug, Serialize)]
+pub struct PublicReadiness {
+    pub ready: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseStatus {
+    pub connected: bool,
+    pub error: Option<String>,
+}
+
+impl Health {
+    pub fn new(service: &'static str, config: &HealthConfig, checks: Checks) -> Self {
+        Self {
+            service,
+            config: config.clone(),
+            checks,
+            db_pool: None,
+            started_at: Utc::now(),
+            status: Arc::new(RwLock::new(Status::default())),
+        }
+    }
+
+    /// Readiness also requires Postgres to answer a query
+    #[must_use]
+    pub fn with_database(mut self, db_pool: Pool<Postgres>) -> Self {
+        self.db_pool = Some(db_pool);
+        self
+    }
+
+    pub fn record_success(&self, at: DateTime<Utc>) {
+        self.status.write().last_success_at = Some(at);
+    }
+
+    pub fn record_error(&self, error: impl Display) {
+        self.status.write().last_error = Some(LastError {
+            at: Utc::now(),
+            message: error.to_string(),
+        });
+    }
+
+    pub fn set_queue_depth(&self, depth: u64) {
+        self.status.write().queue_depth = Some(depth);
+    }
+
+    /// `None` once the queue is empty
+    pub fn set_oldest_queued_at(&self, queued_at: Option<DateTime<Utc>>) {
+        self.status.write().oldest_queued_at = queued_at;
+    }
+
+    pub fn set_datafeed_updated_at(&self, updated_at: DateTime<Utc>) {
+        self.status.write().datafeed_updated_at = Some(updated_at);
+    }
+
+    /// Standbys are idle by design, so only Postgres decides their readiness. Staleness is measured
+    /// afresh once a standby becomes active.
+    pub fn set_standby(&self, standby: bool) {
+        let mut status = self.status.write();
+        if status.standby && !standby {
+            status.active_since = Some(Utc::now());
+        }
+        status.standby = standby;
+    }
+
+    /// Adds or replaces a service-specific value reported under `details`
+    pub fn set_detail(&self, key: &'static str, value: impl Into<Value>) {
+        self.status.write().details.insert(key, value.into());
+    }
+
+    pub fn last_success_at(&self) -> Option<DateTime<Utc>> {
+        self.status.read().last_success_at
+    }
+
+    pub fn liveness(&self) -> Liveness {
+        Liveness {
+            status: "ok",
+            service: self.service,
+            started_at: self.started_at,
+        }
+    }
+
+    pub async fn readiness(&self) -> Readiness {
+        let database = match &self.db_pool {
+            Some(pool) => Some(check_database(pool).await),
+            None => None,
+        };
+
+        let now = Utc::now();
+        let status = self.status.read();
+        let mut problems = Vec::new();
+        if let Some(DatabaseStatus {
+            connected: false, ..
+        }) = database
+        {
+            problems.push("database is unreachable".to_string());
+        }
+        // Until the first success or datafeed, staleness is measured from startup (or from taking
+        // over from another replica) so a service isn't reported unready while it's still starting
+        let since = |at: Option<DateTime<Utc>>| match (at, status.active_since) {
+            (Some(at), Some(active_since)) => at.max(active_since),
+            (at, active_since) => at.or(active_since).unwrap_or(self.started_at),
+        };
+        if self.checks.success && !status.standby {
+            let since = since(status.last_success_at);
+            if now - since > seconds(self.config.max_success_age_seconds) {
+                problems.push(match status.last_success_at {
+                    Some(at) => format!(
+                        "last success at {at} is older than {}s",
+                        self.config.max_success_age_seconds
+                    ),
+                    None => format!(
+                        "no success in the {}s since startup",
+                        self.config.max_success_age_seconds
+                    ),
+                });
+            }
+        }
+        if self.checks.lag && !status.standby {
+            let since = since(status.datafeed_updated_at);
+            if now - since > seconds(self.config.max_lag_seconds) {
+                problems.push(match status.datafeed_updated_at {
+                    Some(at) => format!(
+                        "latest datafeed from {at} lags by more than {}s",
+                        self.config.max_lag_seconds
+                    ),
+                    None => format!(
+                        "no datafeed in the {}s since startup",
+                        self.config.max_lag_seconds
+                    ),
+                });
+            }
+        }
+        if self.checks.queue_age
+            && !status.standby
+            && let Some(queued_at) = status.oldest_queued_at
+            && now - queued_at > seconds(self.config.max_queue_age_seconds)
+        {
+            problems.push(format!(
+                "oldest queued entry from {queued_at} has waited more than {}s",
+                self.config.max_queue_age_seconds
+            ));
+        }
+
+        Readiness {
+            ready: problems.is_empty(),
+            service: self.service,
+            checked_at: now,
+            started_at: self.started_at,
+            last_success_at: status.last_success_at,
+            last_error: status.last_error.clone(),
+            queue_depth: status.queue_depth,
+            oldest_queued_at: status.oldest_queued_at,
+            oldest_queued_age_seconds: status
+                .oldest_queued_at
+                .map(|queued_at| (now - queued_at).num_seconds()),
+            datafeed_updated_at: status.datafeed_updated_at,
+            lag_seconds: status
+                .datafeed_updated_at
+                .map(|updated_at| (now - updated_at).num_seconds()),
+            database,
+            standby: status.standby,
+            problems,
+            details: status.details.clone(),
+        }
+    }
+}
+
+fn seconds(seconds: u64) -> TimeDelta {
+    TimeDelta::seconds(i64::try_from(seconds).unwrap_or(i64::MAX))
+}
+
+async fn check_database(pool: &Pool<Postgres>) -> DatabaseStatus {
+    let query = sqlx::query("SELECT 1").execute(pool);
+    let error = match tokio::time::timeout(DATABASE_TIMEOUT, query).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!(
+            "no response within {}s",
+            DATABASE_TIMEOUT.as_secs()
+        )),
+    };
+    DatabaseStatus {
+        connected: error.is_none(),
+        error,
+    }
+}
+
+/// `/health/live` and `/health/ready`, plus `/health` as an alias of the latter
+pub fn router<S>(health: Health) -> Router<S> {
+    Router::new()
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+        .route("/health", get(ready))
+        .with_state(health)
+}
+
+/// `/health` answering only whether the service is ready, for listeners reachable by anyone
+pub fn public_router<S>(health: Health) -> Router<S> {
+    Router::new()
+        .route("/health", get(public_ready))
+        .with_state(health)
+}
+
+async fn live(State(health): State<Health>) -> impl IntoResponse {
+    Json(health.liveness())
+}
+
+async fn ready(State(health): State<Health>) -> impl IntoResponse {
+    let readiness = health.readiness().await;
+    let code = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(readiness))
+}
+
+async fn public_ready(State(health): State<Health>) -> impl IntoResponse {
+    let ready = health.readiness().await.ready;
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (code, Json(PublicReadiness { ready }))
+}
+
+/// Serves `app` on `bind_address`, usually [`HealthConfig::bind_address`], until `shutdown`
+/// completes
+pub async fn serve(
+    bind_address: SocketAddr,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), std::io::Error> {
+    let listener = TcpListener::bind(bind_address)
+        .await
+        .inspect_err(|e| {
+            warn!(name: "health.bind", address = %bind_address, error = ?e, "failed to bind health server");
+        })?;
+    info!(name: "health.initialized", address = %bind_address, "starting health server");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown)
+        .await
+}
@@ -0,0 +1,339 @@
+//! Leader election over Postgres advisory locks, so hot-standby replicas of a service can run
+//! without more than one of them doing its work at a time.
+//!
+//! Advisory locks belong to the session that took them, so the leader keeps its lock on a
+//! dedicated connection for as long as it leads. If the leader dies, Postgres ends its session and
+//! releases the lock, and the next standby to poll takes over.
+//!
+//! The leader only notices a lost lock when it next checks, so each election also bumps the lock's
+//! term in `leader_terms`. Leaders check their [`Fence`] inside every transaction that must not
+//! be committed by two replicas, which holds the new leader off until those transactions end and
+//! makes any later ones from the old leader fail.
+
+use crate::health::Health;
+use serde::Deserialize;
+use sqlx::{Connection, Executor, PgConnection, Pool, Postgres};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio::time::{MissedTickBehavior, interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LeaderElectionConfig {
+    /// Without election every replica works at once, which is only safe when there's one
+    pub enabled: bool,
+    /// How often standbys try to take the lock, and how often the leader checks it still holds it
+    pub check_interval_seconds: u64,
+}
+
+impl Default for LeaderElectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            check_interval_seconds: 5,
+        }
+    }
+}
+
+/// The advisory lock a service elects its leader with, so each service has its own leader
+#[derive(Debug, Clone, Copy)]
+pub enum LeaderLock {
+    Fetcher,
+    Processor,
+}
+
+impl LeaderLock {
+    fn key(self) -> i64 {
+        // "vnas" followed by a per-service suffix, to stay clear of any other advisory locks
+        match self {
+            Self::Fetcher => 0x766e_6173_0001,
+            Self::Processor => 0x766e_6173_0002,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Fetcher => "fetcher",
+            Self::Processor => "processor",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LeaderElection {
+    pool: Pool<Postgres>,
+    lock: LeaderLock,
+    enabled: bool,
+    check_interval: Duration,
+    health: Health,
+}
+
+/// A term as leader, from [`LeaderElection::acquire`] until [`Leadership::release`] or until the
+/// lock is lost
+pub struct Leadership {
+    fence: Option<Fence>,
+    lost: CancellationToken,
+    stop: CancellationToken,
+    keeper: Option<JoinHandle<()>>,
+}
+
+impl LeaderElection {
+    pub fn new(
+        pool: Pool<Postgres>,
+        lock: LeaderLock,
+        config: &LeaderElectionConfig,
+        health: Health,
+    ) -> Self {
+        Self {
+            pool,
+            lock,
+            enabled: config.enabled,
+            check_interval: Duration::from_secs(config.check_interval_seconds),
+            health,
+        }
+    }
+
+    /// Waits until this replica is the leader, or returns `None` once `shutdown` is cancelled.
+    /// With election disabled, leadership is granted straight away and never lost.
+    pub async fn acquire(&self, shutdown: &CancellationToken) -> Option<Leadership> {
+        if !self.enabled {
+            self.health.set_standby(false);
+            return Some(Leadership {
+                fence: None,
+                lost: shutdown.child_token(),
+                stop: CancellationToken::new(),
+                keeper: None,
+            });
+        }
+
+        self.health.set_standby(true);
+        let lock = self.lock.as_str();
+        info!(name: "leader.standby", lock, "waiting to become leader");
+
+        let mut ticker = interval(self.check_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // Kept between attempts so standbys don't reconnect every tick
+        let mut conn: Option<PgConnection> = None;
+
+        loop {
+            tokio::select! {
+                () = shutdown.cancelled() => {
+                    if let Some(conn) = conn {
+                        let _ = conn.close().await;
+                    }
+                    return None;
+                }
+                _ = ticker.tick() => {}
+            }
+
+            let mut attempt = match conn.take() {
+                Some(conn) => conn,
+                None => match self.pool.acquire().await {
+                    Ok(conn) => conn.detach(),
+                    Err(e) => {
+                        warn!(name: "leader.acquire.failed", lock, error = ?e, "failed to connect for leader election");
+                        continue;
+                    }
+                },
+            };
+            match try_lock(&mut attempt, self.lock).await {
+                Ok(true) => match next_term(&mut attempt, self.lock).await {
+                    Ok(term) => {
+                        info!(name: "leader.acquired", lock, term, "became leader");
+                        self.health.set_standby(false);
+                        let fence = Fence {
+                            lock: self.lock,
+                            term,
+                        };
+                        return Some(self.lead(attempt, fence, shutdown));
+                    }
+                    Err(e) => {
+                        // Closing the session releases the lock for the next attempt
+                        warn!(name: "leader.acquire.failed", lock, error = ?e, "failed to start leader term");
+                        let _ = attempt.close().await;
+                    }
+                },
+                Ok(false) => {
+                    debug!(name: "leader.held_elsewhere", lock, "another replica is leader");
+                    conn = Some(attempt);
+                }
+                Err(e) => {
+                    warn!(name: "leader.acquire.failed", lock, error = ?e, "failed to try leader lock");
+                }
+            }
+        }
+    }
+
+    fn lead(&self, conn: PgConnection, fence: Fence, shutdown: &CancellationToken) -> Leadership {
+        let lost = shutdown.child_token();
+        let stop = CancellationToken::new();
+        let keeper = tokio::spawn(keep_lock(
+            conn,
+            self.lock,
+            self.check_interval,
+            lost.clone(),
+            stop.clone(),
+        ));
+
+        Leadership {
+            fence: Some(fence),
+            lost,
+            stop,
+            keeper: Some(keeper),
+        }
+    }
+}
+
+impl Leadership {
+    /// `None` with election disabled, when there's no other replica to fence off
+    pub fn fence(&self) -> Option<Fence> {
+        self.fence
+    }
+
+    /// Cancelled once leadership is lost or the service is shutting down, at which point the
+    /// leader's work must stop
+    pub fn token(&self) -> CancellationToken {
+        self.lost.clone()
+    }
+
+    /// Gives up the lock so a standby can take over without waiting for this session to end
+    pub async fn release(self) {
+        self.stop.cancel();
+        if let Some(keeper) = self.keeper
+            && let Err(e) = keeper.await
+        {
+            warn!(name: "leader.release.failed", error = ?e, "leader lock task failed");
+        }
+    }
+}
+
+/// The term a leader was elected for
+#[derive(Debug, Clone, Copy)]
+pub struct Fence {
+    lock: LeaderLock,
+    term: i64,
+}
+
+#[derive(Debug, Error)]
+pub enum FenceError {
+    #[error("leader term {term} was superseded by term {current:?}")]
+    Superseded { term: i64, current: Option<i64> },
+    #[error("failed to check leader term: {0}")]
+    Query(#[from] sqlx::Error),
+}
+
+impl Fence {
+    /// Fails with [`FenceError::Superseded`] once another replica has been elected since this
+    /// term began. Run inside a transaction, the term stays share-locked until it ends, so no
+    /// replica can be elected before then.
+    pub async fn check<'e, E>(self, executor: E) -> Result<(), FenceError>
+    where
+        E: Executor<'e, Database = Postgres>,
+    {
+        let current: Option<i64> =
+            sqlx::query_scalar("SELECT term FROM leader_terms WHERE lock_key = $1 FOR SHARE")
+                .bind(self.lock.key())
+                .fetch_optional(executor)
+                .await?;
+        if current == Some(self.term) {
+            Ok(())
+        } else {
+            Err(FenceError::Superseded {
+                term: self.term,
+                current,
+            })
+        }
+    }
+}
+
+/// Starts a new term for `lock`, waiting for transactions that hold the previous one to end
+async fn next_term(conn: &mut PgConnection, lock: LeaderLock) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        r"
+        INSERT INTO leader_terms (lock_key, term)
+        VALUES ($1, 1)
+        ON CONFLICT (lock_key) DO UPDATE SET term = leader_terms.term + 1
+        RETURNING term
+        ",
+    )
+    .bind(lock.key())
+    .fetch_one(conn)
+    .await
+}
+
+async fn try_lock(conn: &mut PgConnection, lock: LeaderLock) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+        .bind(lock.key())
+        .fetch_one(conn)
+        .await
+}
+
+/// Whether this session still holds `lock`. A bigint advisory lock key is split across `classid`
+/// (high half) and `objid` (low half) in `pg_locks`.
+async fn holds_lock(conn: &mut PgConnection, lock: LeaderLock) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r"
+        SELECT EXISTS (
+            SELECT 1
+            FROM pg_locks
+            WHERE locktype = 'advisory'
+              AND pid = pg_backend_pid()
+              AND granted
+              AND objsubid = 1
+              AND (classid::bigint << 32) | objid::bigint = $1
+        )
+        ",
+    )
+    .bind(lock.key())
+    .fetch_one(conn)
+    .await
+}
+
+/// Checks the lock every `period` until told to `stop`, cancelling `lost` if the lock or its
+/// connection goes away first
+async fn keep_lock(
+    mut conn: PgConnection,
+    lock: LeaderLock,
+    period: Duration,
+    lost: CancellationToken,
+    stop: CancellationToken,
+) {
+    let name = lock.as_str();
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            () = stop.cancelled() => {
+                if let Err(e) = sqlx::query("SELECT pg_advisory_unlock($1)")
+                    .bind(lock.key())
+                    .execute(&mut conn)
+                    .await
+                {
+                    warn!(name: "leader.release.failed", lock = name, error = ?e, "failed to release leader lock");
+                }
+                let _ = conn.close().await;
+                info!(name: "leader.released", lock = name, "released leadership");
+                return;
+            }
+            _ = ticker.tick() => {
+                match holds_lock(&mut conn, lock).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        warn!(name: "leader.lost", lock = name, "leader lock is no longer held");
+                        break;
+                    }
+                    Err(e) => {
+                        warn!(name: "leader.lost", lock = name, error = ?e, "failed to check leader lock");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    lost.cancel();
+}
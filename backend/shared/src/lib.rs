@@ -1,44 +1,60 @@
+pub mod archive;
+pub mod compression;
+pub mod health;
+pub mod leader;
+pub mod payloads;
+pub mod rate_limit;
+pub mod telemetry;
+#[cfg(feature = "test-database")]
+pub mod test_database;
 pub mod vatsim;
 pub mod vnas;
 
-use crate::error::InitializationError::MissingEnvVar;
 use crate::error::{ConfigError, InitializationError};
+use crate::health::HealthConfig;
+use crate::leader::LeaderElectionConfig;
+use crate::telemetry::TelemetryConfig;
 use crate::vatsim::OauthEnvironment;
 use figment::Figment;
 use figment::providers::{Env, Format, Toml};
-use opentelemetry::global;
-use opentelemetry::trace::TracerProvider;
-use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
-use opentelemetry_otlp::{LogExporterBuilder, MetricExporterBuilder, WithTonicConfig};
-use opentelemetry_resource_detectors::ProcessResourceDetector;
-use opentelemetry_sdk::Resource;
-use opentelemetry_sdk::logs::SdkLoggerProvider;
-use opentelemetry_sdk::metrics::SdkMeterProvider;
-use opentelemetry_sdk::resource::{
-    EnvResourceDetector, ResourceDetector, SdkProvidedResourceDetector,
-};
-use opentelemetry_sdk::trace::SdkTracerProvider;
 use serde::Deserialize;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{Pool, Postgres};
-use std::env;
+use std::path::PathBuf;
 use tokio::signal;
 use tokio_util::sync::CancellationToken;
 use tracing::{info, instrument};
-use tracing_subscriber::fmt::Layer;
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::{EnvFilter, registry};
 
 pub const DATAFEED_QUEUE_NAME: &str = "vnas_stats";
 pub const ENV_VAR_PREFIX: &str = "VNAS_STATS__";
 pub const SETTINGS_FILE: &str = "Settings.toml";
+/// Notified with the `updatedAt` of the latest datafeed whenever the processor applies new ones
+pub const DATAFEED_PROCESSED_CHANNEL: &str = "datafeed_processed";
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub postgres: PostgresConfig,
     pub fetcher: Option<FetcherConfig>,
+    #[serde(default)]
+    pub processor: ProcessorConfig,
     pub oauth: Option<OAuthConfig>,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub payload_storage: PayloadStorageConfig,
+    pub archive: Option<ArchiveConfig>,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub activity: ActivityConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+    #[serde(default)]
+    pub leader_election: LeaderElectionConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -48,7 +64,197 @@ pub struct PostgresConfig {
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct FetcherConfig {
+    /// How often the datafeed is polled until its update cadence is known, and while it's stalled
     pub interval_seconds: u64,
+    /// Time polls to just after the datafeed is next expected to update, based on the intervals
+    /// between recent `updatedAt` values
+    #[serde(default = "default_adaptive")]
+    pub adaptive: bool,
+    /// Upper bound of the exponential backoff between failed fetches
+    #[serde(default = "default_max_backoff_seconds")]
+    pub max_backoff_seconds: u64,
+    #[serde(default)]
+    pub spill: SpillConfig,
+}
+
+impl Default for FetcherConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: 15,
+            adaptive: default_adaptive(),
+            max_backoff_seconds: default_max_backoff_seconds(),
+            spill: SpillConfig::default(),
+        }
+    }
+}
+
+/// Where the fetcher keeps datafeeds it couldn't enqueue while Postgres is unavailable
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SpillConfig {
+    pub directory: PathBuf,
+    /// Once this many datafeeds are spilled, the oldest are dropped to make room
+    pub max_items: usize,
+    /// Once spilled datafeeds take up this many compressed bytes, the oldest are dropped to make
+    /// room
+    pub max_bytes: u64,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::from("spill"),
+            // A day of datafeeds at the 15 second interval
+            max_items: 5760,
+            max_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+const fn default_adaptive() -> bool {
+    true
+}
+
+const fn default_max_backoff_seconds() -> u64 {
+    300
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessorMode {
+    /// Datafeeds are enqueued by `datafeed_fetcher` and picked up via `datafeed_queue`
+    #[default]
+    Queue,
+    /// The processor fetches datafeeds itself every `fetcher.interval_seconds`, so no separate
+    /// fetcher is needed
+    Direct,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ProcessorConfig {
+    pub mode: ProcessorMode,
+    /// How often the processor samples `datafeed_queue` for its lag metrics and readiness
+    pub monitor_interval_seconds: u64,
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        Self {
+            mode: ProcessorMode::default(),
+            monitor_interval_seconds: 15,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// zstd level used for new payloads
+    pub level: i32,
+    /// Compress new payloads with the most recently trained dictionary, if there is one
+    pub use_dictionary: bool,
+    /// How often the processor migrates existing payloads to the current compression settings.
+    /// Disabled if unset.
+    pub recompress_interval_seconds: Option<u64>,
+    /// Number of payloads migrated per recompression run
+    pub recompress_batch_size: i64,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            level: compression::DEFAULT_LEVEL,
+            use_dictionary: false,
+            recompress_interval_seconds: None,
+            recompress_batch_size: 500,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageMode {
+    /// Every datafeed is stored in full
+    Snapshot,
+    /// Datafeeds are stored as deltas against the previous datafeed, with periodic keyframes
+    Delta,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PayloadStorageConfig {
+    pub mode: StorageMode,
+    /// In delta mode, a full snapshot is stored every this many datafeeds
+    pub keyframe_interval: u32,
+}
+
+impl Default for PayloadStorageConfig {
+    fn default() -> Self {
+        Self {
+            mode: StorageMode::Snapshot,
+            // One keyframe an hour at the 15 second datafeed interval
+            keyframe_interval: 240,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ArchiveConfig {
+    /// Where archives are written: a local directory (optionally as a `file://` URL) or
+    /// `s3://bucket/prefix`. S3 credentials, region and endpoint come from the standard `AWS_*`
+    /// environment variables, so any S3-compatible store can be used.
+    pub location: String,
+    /// Datafeeds are kept in Postgres for this many full days before being archived and pruned
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u32,
+    /// Also write a Parquet file of flattened controllers per day, for analytics
+    #[serde(default)]
+    pub parquet: bool,
+}
+
+const fn default_retention_days() -> u32 {
+    30
+}
+
+/// Rolling up runs by default. Nothing is pruned unless its retention is set, since pruning deletes
+/// history that can't be recovered.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// How often the processor rolls up activity stats and session totals, and prunes rows past
+    /// their retention. Disabled if unset.
+    pub interval_seconds: Option<u64>,
+    /// Raw `session_activity_stats` rows are kept this many days, once rolled up. Kept forever if
+    /// unset.
+    pub activity_raw_retention_days: Option<u32>,
+    /// 1-minute activity rollups are kept this many days, once rolled up. Kept forever if unset.
+    /// Hourly and daily rollups are always kept.
+    pub activity_minute_retention_days: Option<u32>,
+    /// Per-ARTCC and per-facility activity snapshots are kept this many days. Kept forever if
+    /// unset.
+    pub activity_artcc_retention_days: Option<u32>,
+    /// `datafeed_messages` rows are kept this many days. Kept forever if unset.
+    pub message_retention_days: Option<u32>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_seconds: Some(300),
+            activity_raw_retention_days: None,
+            activity_minute_retention_days: None,
+            activity_artcc_retention_days: None,
+            message_retention_days: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ActivityConfig {
+    /// Also record active counts per facility with every datafeed, not just per ARTCC
+    pub record_facilities: bool,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -60,27 +266,104 @@ pub struct OAuthConfig {
     pub frontend_login_success_url: String,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// CIDs that are always `data_api` admins, on top of those granted the role, so the first
+    /// admin can grant roles to others
+    pub admin_cids: Vec<u32>,
+    /// Requests per minute an API key can make if it wasn't given its own limit. Only admins can
+    /// give their keys a higher limit than this.
+    pub api_key_rate_limit_per_minute: u32,
+    /// Keys a user can have at once, not counting revoked ones
+    pub max_api_keys_per_user: u32,
+    pub rate_limit: RateLimitConfig,
+    pub cache: CacheConfig,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            admin_cids: Vec::new(),
+            api_key_rate_limit_per_minute: 120,
+            max_api_keys_per_user: 10,
+            rate_limit: RateLimitConfig::default(),
+            cache: CacheConfig::default(),
+        }
+    }
+}
+
+/// Per-IP limits on public `data_api` routes
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub requests_per_minute: u32,
+    /// Requests a client can make at once before being held to `requests_per_minute`
+    pub burst: u32,
+    /// Take the client's IP from the last `X-Forwarded-For` entry, which only a reverse proxy in
+    /// front of the API can be trusted to set
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            requests_per_minute: 60,
+            burst: 20,
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+/// Caching of public `data_api` responses until the next datafeed is processed
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Responses beyond this many aren't cached until the next datafeed clears the cache
+    pub max_entries: usize,
+    /// How long the latest datafeed is trusted without hearing from the processor, in case
+    /// notifications are missed
+    pub max_age_seconds: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: 1_000,
+            max_age_seconds: 60,
+        }
+    }
+}
+
 pub fn load_config() -> Result<Config, ConfigError> {
     Ok(Figment::new()
         .merge(Toml::file(SETTINGS_FILE))
         .merge(Env::prefixed(ENV_VAR_PREFIX).split("__"))
-        .extract::<Config>()?)
+        .extract::<Config>()
+        .map_err(Box::new)?)
 }
 
 pub mod error {
+    use opentelemetry_otlp::ExporterBuildError;
     use thiserror::Error;
-    use tracing::dispatcher::SetGlobalDefaultError;
+    use tracing_subscriber::util::TryInitError;
 
     #[derive(Debug, Error)]
     pub enum ConfigError {
         #[error("failed to load configuration: {0}")]
-        Figment(#[from] figment::Error),
+        Figment(#[from] Box<figment::Error>),
     }
 
     #[derive(Debug, Error)]
     pub enum InitializationError {
         #[error(transparent)]
-        Tracing(#[from] SetGlobalDefaultError),
+        Tracing(#[from] TryInitError),
+        #[error(transparent)]
+        Exporter(#[from] ExporterBuildError),
         #[error(transparent)]
         Config(#[from] ConfigError),
         #[error(transparent)]
@@ -133,83 +416,3 @@ pub async fn shutdown_listener(token: Option<CancellationToken>) {
         token.cancel();
     }
 }
-
-pub fn init_tracing_and_oltp(
-    name: impl ToString,
-) -> Result<(SdkTracerProvider, SdkMeterProvider), InitializationError> {
-    // OpenTelemetry env vars that should be set at a minimum
-    let env_vars = vec![
-        "OTEL_SERVICE_NAME",
-        "OTEL_EXPORTER_OTLP_ENDPOINT",
-        "OTEL_EXPORTER_OTLP_HEADERS",
-    ];
-    for var in env_vars {
-        let _ = env::var(var).map_err(|_| MissingEnvVar(var.to_string()));
-    }
-
-    // tracing_opentelemetry setup for spans
-    let span_exporter = opentelemetry_otlp::SpanExporterBuilder::default()
-        .with_tonic()
-        .with_tls_config(tonic::transport::ClientTlsConfig::new().with_native_roots())
-        .build()
-        .expect("Failed to create OTLP span exporter");
-
-    let detectors: Vec<Box<dyn ResourceDetector>> = vec![
-        Box::new(SdkProvidedResourceDetector),
-        Box::new(EnvResourceDetector::new()),
-        Box::new(ProcessResourceDetector),
-    ];
-    let resource = Resource::builder().with_detectors(&detectors).build();
-
-    let tracer_provider = SdkTracerProvider::builder()
-        .with_resource(resource)
-        .with_batch_exporter(span_exporter)
-        .build();
-    let tracer = tracer_provider.tracer(name.to_string());
-    global::set_tracer_provider(tracer_provider.clone());
-
-    let telemetry_layer = tracing_opentelemetry::layer().with_tracer(tracer);
-
-    // opentelemetry_appender_tracing setup for logs
-    let log_exporter = LogExporterBuilder::default()
-        .with_tonic()
-        .with_tls_config(tonic::transport::ClientTlsConfig::new().with_native_roots())
-        .build()
-        .expect("Failed to create OTLP log exporter");
-
-    let logger_provider = SdkLoggerProvider::builder()
-        .with_batch_exporter(log_exporter)
-        .build();
-
-    let otel_log_layer = OpenTelemetryTracingBridge::new(&logger_provider);
-
-    // Setup for OTel Metrics
-    let meter_exporter = MetricExporterBuilder::new()
-        .with_tonic()
-        .with_tls_config(tonic::transport::ClientTlsConfig::new().with_native_roots())
-        .build()
-        .expect("Failed to create OTLP metric exporter");
-
-    let meter_provider = SdkMeterProvider::builder()
-        .with_periodic_exporter(meter_exporter)
-        .build();
-    global::set_meter_provider(meter_provider.clone());
-
-    // Standard console format and env filter layers
-    let fmt_layer = Layer::new()
-        .compact()
-        .with_file(true)
-        .with_line_number(true);
-
-    let env_filter_layer =
-        EnvFilter::try_from_default_env().expect("failed to get RUST_LOG from env");
-
-    registry()
-        .with(env_filter_layer)
-        .with(fmt_layer)
-        .with(otel_log_layer)
-        .with(telemetry_layer)
-        .init();
-
-    Ok((tracer_provider, meter_provider))
-}
@@ -0,0 +1,287 @@
+use crate::compression::{CompressionError, DictionaryCache};
+use crate::vnas::datafeed::DatafeedRoot;
+use crate::vnas::delta::{DatafeedDelta, apply, diff};
+use crate::{PayloadStorageConfig, StorageMode};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{Executor, Postgres};
+use thiserror::Error;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// `payload_kind` of a payload stored in full. Every delta chain starts at one of these.
+pub const SNAPSHOT: &str = "snapshot";
+/// `payload_kind` of a payload stored as a [`DatafeedDelta`] against `base_payload_id`
+pub const DELTA: &str = "delta";
+
+#[derive(Debug, Error)]
+pub enum PayloadError {
+    #[error(transparent)]
+    Compression(#[from] CompressionError),
+    #[error("payload is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("payload {0} is a delta without a base payload")]
+    MissingBase(Uuid),
+    #[error("delta payload {0} does not apply to its base payload")]
+    InvalidDelta(Uuid),
+    #[error("unknown payload kind {0}")]
+    UnknownKind(String),
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoredPayload {
+    pub id: Uuid,
+    pub updated_at: DateTime<Utc>,
+    pub payload_kind: String,
+    pub base_payload_id: Option<Uuid>,
+    pub payload_compressed: Vec<u8>,
+    pub compression_algo: String,
+    pub dictionary_id: Option<Uuid>,
+}
+
+/// The uncompressed bytes to store for a datafeed, and how to store them
+#[derive(Debug)]
+pub struct EncodedPayload {
+    pub kind: &'static str,
+    pub base_payload_id: Option<Uuid>,
+    pub bytes: Vec<u8>,
+}
+
+/// Decides whether each new datafeed is stored as a snapshot or as a delta against the previously
+/// stored one. In delta mode a snapshot (keyframe) is still written every `keyframe_interval`
+/// payloads, for the first payload after startup, and whenever a payload can't be delta-encoded.
+///
+/// Call [`PayloadWriter::stored`] only once the payload has been committed, so that deltas never
+/// reference a payload that was rolled back.
+#[derive(Debug, Clone)]
+pub struct PayloadWriter {
+    mode: StorageMode,
+    keyframe_interval: u32,
+    previous: Option<(Uuid, DateTime<Utc>, Value)>,
+    since_keyframe: u32,
+}
+
+impl PayloadWriter {
+    pub fn new(config: &PayloadStorageConfig) -> Self {
+        Self {
+            mode: config.mode,
+            keyframe_interval: config.keyframe_interval,
+            previous: None,
+            since_keyframe: 0,
+        }
+    }
+
+    pub fn encode(
+        &self,
+        payload: &Value,
+        updated_at: DateTime<Utc>,
+    ) -> Result<EncodedPayload, serde_json::Error> {
+        if self.mode == StorageMode::Delta
+            && self.since_keyframe + 1 < self.keyframe_interval
+            && let Some((base_id, base_updated_at, base)) = &self.previous
+            && *base_updated_at < updated_at
+            && let Some(delta) = diff(base, payload)
+        {
+            return Ok(EncodedPayload {
+                kind: DELTA,
+                base_payload_id: Some(*base_id),
+                bytes: serde_json::to_vec(&delta)?,
+            });
+        }
+
+        Ok(EncodedPayload {
+            kind: SNAPSHOT,
+            base_payload_id: None,
+            bytes: serde_json::to_vec(payload)?,
+        })
+    }
+
+    /// Records a payload that was stored as `kind`, making it the base of the next delta
+    pub fn stored(&mut self, id: Uuid, updated_at: DateTime<Utc>, payload: Value, kind: &str) {
+        if self.mode == StorageMode::Snapshot {
+            return;
+        }
+        if self
+            .previous
+            .as_ref()
+            .is_some_and(|(_, previous_updated_at, _)| *previous_updated_at >= updated_at)
+        {
+            return;
+        }
+
+        self.since_keyframe = if kind == SNAPSHOT {
+            0
+        } else {
+            self.since_keyframe + 1
+        };
+        self.previous = Some((id, updated_at, payload));
+    }
+}
+
+/// Reads stored payloads back into full datafeeds. Reading payloads in `updated_at` order is
+/// cheap, since each delta is applied to the previously read payload; any other payload is
+/// reconstructed from its keyframe.
+#[derive(Debug, Default)]
+pub struct PayloadReader {
+    dictionaries: DictionaryCache,
+    previous: Option<(Uuid, Value)>,
+}
+
+impl PayloadReader {
+    pub async fn read<'e, E>(
+        &mut self,
+        executor: &mut E,
+        payload: &StoredPayload,
+    ) -> Result<Value, PayloadError>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+    {
+        let value = match payload.payload_kind.as_str() {
+            SNAPSHOT => self.decompress_json(&mut *executor, payload).await?,
+            DELTA => {
+                let base_id = payload
+                    .base_payload_id
+                    .ok_or(PayloadError::MissingBase(payload.id))?;
+                let base = match self.previous.take() {
+                    Some((id, base)) if id == base_id => base,
+                    _ => self.reconstruct(&mut *executor, base_id).await?,
+                };
+                let delta = self.decompress_json(&mut *executor, payload).await?;
+                apply_delta(&base, delta, payload.id)?
+            }
+            other => return Err(PayloadError::UnknownKind(other.to_string())),
+        };
+
+        self.previous = Some((payload.id, value.clone()));
+        Ok(value)
+    }
+
+    async fn decompress_json<'e, E>(
+        &mut self,
+        executor: &mut E,
+        payload: &StoredPayload,
+    ) -> Result<Value, PayloadError>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+    {
+        let bytes = self
+            .dictionaries
+            .decompress(
+                &mut *executor,
+                &payload.payload_compressed,
+                &payload.compression_algo,
+                payload.dictionary_id,
+            )
+            .await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    /// Reconstructs the payload with the given id from its snapshot and the deltas leading up to it
+    async fn reconstruct<'e, E>(
+        &mut self,
+        executor: &mut E,
+        id: Uuid,
+    ) -> Result<Value, PayloadError>
+    where
+        for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+    {
+        let chain = fetch_chain(&mut *executor, id).await?;
+        let mut value = None;
+        for payload in &chain {
+            let decoded = self.decompress_json(&mut *executor, payload).await?;
+            value = Some(match (payload.payload_kind.as_str(), value) {
+                (SNAPSHOT, _) => decoded,
+                (DELTA, Some(base)) => apply_delta(&base, decoded, payload.id)?,
+                (DELTA, None) => return Err(PayloadError::MissingBase(payload.id)),
+                (other, _) => return Err(PayloadError::UnknownKind(other.to_string())),
+            });
+        }
+
+        let value = value.ok_or(PayloadError::MissingBase(id))?;
+        self.previous = Some((id, value.clone()));
+        Ok(value)
+    }
+}
+
+fn apply_delta(base: &Value, delta: Value, id: Uuid) -> Result<Value, PayloadError> {
+    apply(base, &serde_json::from_value::<DatafeedDelta>(delta)?)
+        .ok_or(PayloadError::InvalidDelta(id))
+}
+
+/// Returns the payload with the given id and every payload it transitively depends on, starting
+/// at its snapshot.
+#[instrument(level = "debug", skip(executor))]
+pub async fn fetch_chain<'e, E>(
+    executor: &mut E,
+    id: Uuid,
+) -> Result<Vec<StoredPayload>, PayloadError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    let chain = sqlx::query_as::<_, StoredPayload>(
+        r"
+        WITH RECURSIVE chain AS (
+            SELECT id, updated_at, payload_kind, base_payload_id, payload_compressed,
+                   compression_algo, dictionary_id, 0 AS depth
+            FROM datafeed_payloads
+            WHERE id = $1
+            UNION ALL
+            SELECT p.id, p.updated_at, p.payload_kind, p.base_payload_id, p.payload_compressed,
+                   p.compression_algo, p.dictionary_id, chain.depth + 1
+            FROM datafeed_payloads p
+            JOIN chain ON p.id = chain.base_payload_id
+        )
+        SELECT id, updated_at, payload_kind, base_payload_id, payload_compressed,
+               compression_algo, dictionary_id
+        FROM chain
+        ORDER BY depth DESC
+        ",
+    )
+    .bind(id)
+    .fetch_all(&mut *executor)
+    .await?;
+
+    match chain.first() {
+        Some(first) if first.payload_kind == DELTA => Err(PayloadError::MissingBase(first.id)),
+        _ => Ok(chain),
+    }
+}
+
+/// Reconstructs the raw datafeed stored for `updated_at`, however it was stored
+#[instrument(level = "debug", skip(executor))]
+pub async fn reconstruct_payload<'e, E>(
+    executor: &mut E,
+    updated_at: DateTime<Utc>,
+) -> Result<Option<Value>, PayloadError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    let Some(id) =
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM datafeed_payloads WHERE updated_at = $1")
+            .bind(updated_at)
+            .fetch_optional(&mut *executor)
+            .await?
+    else {
+        return Ok(None);
+    };
+
+    let mut reader = PayloadReader::default();
+    Ok(Some(reader.reconstruct(executor, id).await?))
+}
+
+/// Reconstructs the datafeed stored for `updated_at` as a [`DatafeedRoot`]
+pub async fn reconstruct_datafeed<'e, E>(
+    executor: &mut E,
+    updated_at: DateTime<Utc>,
+) -> Result<Option<DatafeedRoot>, PayloadError>
+where
+    for<'c> &'c mut E: Executor<'c, Database = Postgres>,
+{
+    reconstruct_payload(executor, updated_at)
+        .await?
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(PayloadError::from)
+}
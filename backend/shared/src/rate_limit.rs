@@ -0,0 +1,106 @@
+//! In-process token bucket rate limiting. Limits are per replica, so with several replicas behind a
+//! load balancer a client can get up to that many times its quota.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Buckets are pruned once there are this many, so clients that stop sending requests don't
+/// keep their bucket forever
+const PRUNE_THRESHOLD: usize = 10_000;
+/// Pruning scans every bucket with the lock held, so with many clients it's done at most this
+/// often rather than on every request
+const PRUNE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How many requests a client can make: `per_minute` on average, with up to `burst` at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl Quota {
+    /// A quota whose whole minute can be used at once
+    pub const fn per_minute(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            burst: per_minute,
+        }
+    }
+
+    fn tokens_per_second(self) -> f64 {
+        f64::from(self.per_minute.max(1)) / 60.0
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// When the bucket will have refilled completely, after which it can be pruned
+    full_at: Instant,
+}
+
+/// Token buckets keyed by client. Clones share the same buckets.
+#[derive(Clone)]
+pub struct RateLimiter<K> {
+    buckets: Arc<Mutex<Buckets<K>>>,
+}
+
+struct Buckets<K> {
+    by_key: HashMap<K, Bucket>,
+    last_pruned: Option<Instant>,
+}
+
+impl<K> Default for RateLimiter<K> {
+    fn default() -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                last_pruned: None,
+            })),
+        }
+    }
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    /// Takes a token from `key`'s bucket, or returns how long until one is available
+    pub fn check(&self, key: K, quota: Quota) -> Result<(), Duration> {
+        self.check_at(key, quota, Instant::now())
+    }
+
+    /// Like [`RateLimiter::check`], as of `now`
+    pub fn check_at(&self, key: K, quota: Quota, now: Instant) -> Result<(), Duration> {
+        let burst = f64::from(quota.burst.max(1));
+        let rate = quota.tokens_per_second();
+
+        let mut buckets = self.buckets.lock();
+        if buckets.by_key.len() >= PRUNE_THRESHOLD
+            && buckets
+                .last_pruned
+                .is_none_or(|pruned| now.saturating_duration_since(pruned) >= PRUNE_INTERVAL)
+        {
+            buckets.by_key.retain(|_, bucket| bucket.full_at > now);
+            buckets.last_pruned = Some(now);
+        }
+
+        let bucket = buckets.by_key.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+            full_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        let result = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        };
+        bucket.full_at = now + Duration::from_secs_f64((burst - bucket.tokens) / rate);
+        result
+    }
+}
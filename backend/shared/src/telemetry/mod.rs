@@ -0,0 +1,233 @@
+//! Logging, tracing and metrics setup shared by every binary.
+//!
+//! Console logs are written in every mode, including [`TelemetryExporter::Disabled`]. Traces, OTel logs
+//! and metrics are pushed to a collector with [`TelemetryExporter::Otlp`], while
+//! [`TelemetryExporter::Prometheus`] keeps metrics in-process for [`Telemetry::metrics_router`] to
+//! serve, so no collector is needed.
+
+mod prometheus;
+
+pub use prometheus::PrometheusReader;
+
+use crate::error::InitializationError;
+use axum::Router;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_appender_tracing::layer::OpenTelemetryTracingBridge;
+use opentelemetry_otlp::{
+    LogExporterBuilder, MetricExporterBuilder, SpanExporterBuilder, WithTonicConfig,
+};
+use opentelemetry_resource_detectors::ProcessResourceDetector;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::logs::SdkLoggerProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::resource::{
+    EnvResourceDetector, ResourceDetector, SdkProvidedResourceDetector,
+};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use serde::Deserialize;
+use std::env;
+use tonic::transport::ClientTlsConfig;
+use tracing::warn;
+use tracing_subscriber::fmt::Layer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, registry};
+
+/// Env var the OTLP exporters need to know where to send telemetry
+const OTLP_ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// Log filter used when `RUST_LOG` isn't set
+const DEFAULT_LOG_FILTER: &str = "info";
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TelemetryExporter {
+    /// Traces, logs and metrics are exported to the collector at `OTEL_EXPORTER_OTLP_ENDPOINT`
+    #[default]
+    Otlp,
+    /// Metrics are served at `/metrics` on the health server for Prometheus to scrape
+    Prometheus,
+    /// Only console logs are written
+    Stdout,
+    /// Nothing is exported. Console logs are still written, as they are in every mode.
+    Disabled,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    pub exporter: TelemetryExporter,
+}
+
+/// Providers set up by [`init_telemetry`], to be shut down before exiting so buffered telemetry
+/// is flushed
+#[derive(Default)]
+pub struct Telemetry {
+    tracer_provider: Option<SdkTracerProvider>,
+    logger_provider: Option<SdkLoggerProvider>,
+    meter_provider: Option<SdkMeterProvider>,
+    prometheus: Option<PrometheusReader>,
+}
+
+/// Sets up console logging and the configured exporter for the service called `name`
+pub fn init_telemetry(
+    name: impl ToString,
+    config: &TelemetryConfig,
+) -> Result<Telemetry, InitializationError> {
+    let name = name.to_string();
+    let mut telemetry = Telemetry::default();
+
+    // Standard console format and env filter layers
+    let fmt_layer = Layer::new()
+        .compact()
+        .with_file(true)
+        .with_line_number(true);
+    let env_filter_layer =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let subscriber = registry().with(env_filter_layer).with(fmt_layer);
+
+    match config.exporter {
+        TelemetryExporter::Otlp => {
+            if env::var(OTLP_ENDPOINT_VAR).is_err() {
+                return Err(InitializationError::MissingEnvVar(
+                    OTLP_ENDPOINT_VAR.to_string(),
+                ));
+            }
+            let resource = resource(&name);
+            let tls = ClientTlsConfig::new().with_native_roots();
+
+            // tracing_opentelemetry setup for spans
+            let span_exporter = SpanExporterBuilder::default()
+                .with_tonic()
+                .with_tls_config(tls.clone())
+                .build()?;
+            let tracer_provider = SdkTracerProvider::builder()
+                .with_resource(resource.clone())
+                .with_batch_exporter(span_exporter)
+                .build();
+            let tracer = tracer_provider.tracer(name);
+            global::set_tracer_provider(tracer_provider.clone());
+
+            // opentelemetry_appender_tracing setup for logs
+            let log_exporter = LogExporterBuilder::default()
+                .with_tonic()
+                .with_tls_config(tls.clone())
+                .build()?;
+            let logger_provider = SdkLoggerProvider::builder()
+                .with_resource(resource.clone())
+                .with_batch_exporter(log_exporter)
+                .build();
+
+            // Setup for OTel Metrics
+            let meter_exporter = MetricExporterBuilder::new()
+                .with_tonic()
+                .with_tls_config(tls)
+                .build()?;
+            let meter_provider = SdkMeterProvider::builder()
+                .with_resource(resource)
+                .with_periodic_exporter(meter_exporter)
+                .build();
+            global::set_meter_provider(meter_provider.clone());
+
+            subscriber
+                .with(OpenTelemetryTracingBridge::new(&logger_provider))
+                .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .try_init()?;
+            telemetry.tracer_provider = Some(tracer_provider);
+            telemetry.logger_provider = Some(logger_provider);
+            telemetry.meter_provider = Some(meter_provider);
+        }
+        TelemetryExporter::Prometheus => {
+            let reader = PrometheusReader::default();
+            let meter_provider = SdkMeterProvider::builder()
+                .with_resource(resource(&name))
+                .with_reader(reader.clone())
+                .build();
+            global::set_meter_provider(meter_provider.clone());
+
+            subscriber.try_init()?;
+            telemetry.meter_provider = Some(meter_provider);
+            telemetry.prometheus = Some(reader);
+        }
+        TelemetryExporter::Stdout | TelemetryExporter::Disabled => subscriber.try_init()?,
+    }
+
+    Ok(telemetry)
+}
+
+/// Attributes shared by every signal, including the environment when it's set as
+/// `deployment.environment.name` in `OTEL_RESOURCE_ATTRIBUTES`. Per-signal attributes are kept
+/// bounded, so the environment belongs here rather than on each data point.
+fn resource(name: &str) -> Resource {
+    let detectors: Vec<Box<dyn ResourceDetector>> = vec![
+        Box::new(SdkProvidedResourceDetector),
+        Box::new(EnvResourceDetector::new()),
+        Box::new(ProcessResourceDetector),
+    ];
+    let builder = Resource::builder().with_detectors(&detectors);
+    // `OTEL_SERVICE_NAME` takes precedence, otherwise the binary's name is used
+    if env::var("OTEL_SERVICE_NAME").is_ok() {
+        builder.build()
+    } else {
+        builder.with_service_name(name.to_string()).build()
+    }
+}
+
+impl Telemetry {
+    /// `/metrics` in the Prometheus text format when [`TelemetryExporter::Prometheus`] is
+    /// configured, otherwise no routes
+    pub fn metrics_router<S>(&self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        match &self.prometheus {
+            Some(reader) => Router::new()
+                .route("/metrics", get(metrics))
+                .with_state(reader.clone()),
+            None => Router::new(),
+        }
+    }
+
+    pub fn shutdown(self) {
+        if let Some(tracer_provider) = self.tracer_provider
+            && let Err(e) = tracer_provider.shutdown()
+        {
+            eprintln!("failed to shut down tracer provider: {e:?}");
+        }
+
+        if let Some(logger_provider) = self.logger_provider
+            && let Err(e) = logger_provider.shutdown()
+        {
+            eprintln!("failed to shut down logger provider: {e:?}");
+        }
+
+        if let Some(meter_provider) = self.meter_provider
+            && let Err(e) = meter_provider.shutdown()
+        {
+            eprintln!("failed to shut down meter provider: {e:?}");
+        }
+    }
+}
+
+async fn metrics(State(reader): State<PrometheusReader>) -> impl IntoResponse {
+    match reader.render() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, prometheus::CONTENT_TYPE)],
+            body,
+        ),
+        Err(e) => {
+            warn!(name: "telemetry.prometheus.render", error = ?e, "failed to collect metrics");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                [(header::CONTENT_TYPE, "text/plain")],
+                e.to_string(),
+            )
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Renders metrics in the Prometheus text exposition format, so they can be scraped instead of
+//! pushed to an OTLP collector.
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::metrics::data::{AggregatedMetrics, Metric, MetricData, ResourceMetrics};
+use opentelemetry_sdk::metrics::reader::MetricReader;
+use opentelemetry_sdk::metrics::{InstrumentKind, ManualReader, Pipeline, Temporality};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::iter;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+pub(super) const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Collects metrics on demand rather than on an interval. Clones share the same underlying
+/// reader, so one can be registered with the meter provider and another kept to render scrapes.
+#[derive(Debug, Clone, Default)]
+pub struct PrometheusReader(Arc<ManualReader>);
+
+impl MetricReader for PrometheusReader {
+    fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+        self.0.register_pipeline(pipeline);
+    }
+
+    fn collect(&self, rm: &mut ResourceMetrics) -> OTelSdkResult {
+        self.0.collect(rm)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.0.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.0.shutdown_with_timeout(timeout)
+    }
+
+    fn temporality(&self, kind: InstrumentKind) -> Temporality {
+        self.0.temporality(kind)
+    }
+}
+
+impl PrometheusReader {
+    /// Collects every instrument's current values. Sums and histograms are cumulative since
+    /// startup, as Prometheus expects.
+    pub fn render(&self) -> Result<String, OTelSdkError> {
+        let mut rm = ResourceMetrics::default();
+        self.0.collect(&mut rm)?;
+
+        // Keyed by name so each family is written once, even if several meters share a name
+        let mut families = BTreeMap::new();
+        for scope in rm.scope_metrics() {
+            for metric in scope.metrics() {
+                add_metric(&mut families, metric);
+            }
+        }
+
+        let mut out = String::new();
+        for (name, family) in families {
+            if !family.help.is_empty() {
+                let _ = writeln!(out, "# HELP {name} {}", escape_help(&family.help));
+            }
+            let _ = writeln!(out, "# TYPE {name} {}", family.kind);
+            for sample in family.samples {
+                out.push_str(&sample);
+                out.push('\n');
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+struct Family {
+    kind: &'static str,
+    help: String,
+    samples: Vec<String>,
+}
+
+/// Sample values, formatted the way Prometheus parses them
+trait SampleValue: Copy {
+    fn render(self) -> String;
+}
+
+impl SampleValue for u64 {
+    fn render(self) -> String {
+        self.to_string()
+    }
+}
+
+impl SampleValue for i64 {
+    fn render(self) -> String {
+        self.to_string()
+    }
+}
+
+impl SampleValue for f64 {
+    fn render(self) -> String {
+        if self.is_nan() {
+            "NaN".to_string()
+        } else if self.is_infinite() {
+            if self > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+        } else {
+            self.to_string()
+        }
+    }
+}
+
+fn add_metric(families: &mut BTreeMap<String, Family>, metric: &Metric) {
+    let name = metric_name(metric.name(), metric.unit());
+    let help = metric.description();
+    match metric.data() {
+        AggregatedMetrics::F64(data) => add_data(families, name, help, data),
+        AggregatedMetrics::U64(data) => add_data(families, name, help, data),
+        AggregatedMetrics::I64(data) => add_data(families, name, help, data),
+    }
+}
+
+fn add_data<T: SampleValue>(
+    families: &mut BTreeMap<String, Family>,
+    name: String,
+    help: &str,
+    data: &MetricData<T>,
+) {
+    match data {
+        MetricData::Gauge(gauge) => {
+            let Some(family) = family(families, &name, "gauge", help) else {
+                return;
+            };
+            for point in gauge.data_points() {
+                family
+                    .samples
+                    .push(sample(&name, point.attributes(), None, point.value()));
+            }
+        }
+        MetricData::Sum(sum) => {
+            let (name, kind) = if sum.is_monotonic() {
+                (format!("{name}_total"), "counter")
+            } else {
+                (name, "gauge")
+            };
+            let Some(family) = family(families, &name, kind, help) else {
+                return;
+            };
+            for point in sum.data_points() {
+                family
+                    .samples
+                    .push(sample(&name, point.attributes(), None, point.value()));
+            }
+        }
+        MetricData::Histogram(histogram) => {
+            let Some(family) = family(families, &name, "histogram", help) else {
+                return;
+            };
+            for point in histogram.data_points() {
+                // Bucket counts are per bucket, Prometheus buckets are cumulative
+                let mut cumulative = 0;
+                let bounds = point.bounds().chain(iter::once(f64::INFINITY));
+                for (bound, count) in bounds.zip(point.bucket_counts()) {
+                    cumulative += count;
+                    family.samples.push(sample(
+                        &format!("{name}_bucket"),
+                        point.attributes(),
+                        Some(&bound.render()),
+                        cumulative,
+                    ));
+                }
+                family.samples.push(sample(
+                    &format!("{name}_sum"),
+                    point.attributes(),
+                    None,
+                    point.sum(),
+                ));
+                family.samples.push(sample(
+                    &format!("{name}_count"),
+                    point.attributes(),
+                    None,
+                    point.count(),
+                ));
+            }
+        }
+        // No instrument records exponential histograms
+        MetricData::ExponentialHistogram(_) => {}
+    }
+}
+
+/// The family called `name`, or `None` if one of a different type already has that name
+fn family<'a>(
+    families: &'a mut BTreeMap<String, Family>,
+    name: &str,
+    kind: &'static str,
+    help: &str,
+) -> Option<&'a mut Family> {
+    let family = families.entry(name.to_string()).or_insert_with(|| Family {
+        kind,
+        help: help.to_string(),
+        samples: Vec::new(),
+    });
+    (family.kind == kind).then_some(family)
+}
+
+fn sample<'a>(
+    name: &str,
+    attributes: impl Iterator<Item = &'a KeyValue>,
+    le: Option<&str>,
+    value: impl SampleValue,
+) -> String {
+    let mut labels: Vec<String> = attributes
+        .map(|kv| {
+            format!(
+                "{}=\"{}\"",
+                label_name(kv.key.as_str()),
+                escape_label_value(&kv.value.as_str())
+            )
+        })
+        .collect();
+    if let Some(le) = le {
+        labels.push(format!("le=\"{le}\""));
+    }
+
+    if labels.is_empty() {
+        format!("{name} {}", value.render())
+    } else {
+        format!("{name}{{{}}} {}", labels.join(","), value.render())
+    }
+}
+
+/// `datafeeds.processing.duration` in seconds becomes `datafeeds_processing_duration_seconds`
+fn metric_name(name: &str, unit: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+
+    let suffix = match unit {
+        "s" => "_seconds",
+        "ms" => "_milliseconds",
+        "B" => "_bytes",
+        _ => "",
+    };
+    if !name.ends_with(suffix) {
+        name.push_str(suffix);
+    }
+    name
+}
+
+fn label_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', r"\\").replace('\n', r"\n")
+}
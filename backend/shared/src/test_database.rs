@@ -0,0 +1,132 @@
+//! Throwaway databases for tests that need Postgres, behind the `test-database` feature.
+//!
+//! Tests using them are marked `#[ignore = "needs TEST_DATABASE_URL"]` and run with `cargo test-db`
+//! from `backend`. `TEST_DATABASE_URL` must point at a Postgres 18 server whose user can create
+//! databases.
+
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgConnectOptions, PgPool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// A database created for a single test through `TEST_DATABASE_URL`, with every migration run.
+/// It's dropped along with the value, so a failing test doesn't leave it behind.
+pub struct TestDatabase {
+    url: String,
+    name: String,
+    pub pool: PgPool,
+}
+
+impl TestDatabase {
+    /// # Panics
+    /// If `TEST_DATABASE_URL` isn't set, or the database can't be created and migrated
+    pub async fn create() -> Self {
+        let url = std::env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL should point at a Postgres server to run this test");
+        let admin = PgPool::connect(&url).await.unwrap();
+        let name = format!("test_{}", Uuid::now_v7().simple());
+        sqlx::query(&format!("CREATE DATABASE {name}"))
+            .execute(&admin)
+            .await
+            .unwrap();
+        admin.close().await;
+        let options = PgConnectOptions::from_str(&url).unwrap().database(&name);
+        let db = Self {
+            url,
+            name,
+            pool: PgPool::connect_lazy_with(options),
+        };
+        sqlx::migrate!("./migrations").run(&db.pool).await.unwrap();
+        db
+    }
+
+    /// Inserts the position, callsign and controller sessions of one closed connection, with the
+    /// callsign split the way the processor splits it. The position is `position-{cid}`.
+    pub async fn insert_session(
+        &self,
+        cid: i32,
+        callsign: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) {
+        let parts: Vec<&str> = callsign.split('_').collect();
+        let (prefix, suffix) = (parts[0], parts[parts.len() - 1]);
+        let (position_session_id, callsign_session_id) = (Uuid::now_v7(), Uuid::now_v7());
+        sqlx::query(
+            r"
+            INSERT INTO position_sessions (id, position_id, start_time, end_time, is_active)
+            VALUES ($1, $2, $3, $4, FALSE)
+            ",
+        )
+        .bind(position_session_id)
+        .bind(format!("position-{cid}"))
+        .bind(start)
+        .bind(end)
+        .execute(&self.pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r"
+            INSERT INTO callsign_sessions (id, prefix, suffix, start_time, end_time, is_active)
+            VALUES ($1, $2, $3, $4, $5, FALSE)
+            ",
+        )
+        .bind(callsign_session_id)
+        .bind(prefix)
+        .bind(suffix)
+        .bind(start)
+        .bind(end)
+        .execute(&self.pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            r"
+            INSERT INTO controller_sessions (
+                id, login_time, start_time, end_time, is_active, is_observer, cid, name,
+                user_rating, requested_rating, connected_callsign, primary_position_id,
+                callsign_session_id, position_session_id
+            )
+            VALUES (
+                $1, $2, $2, $3, FALSE, FALSE, $4, 'Test Controller', 'controller1',
+                'controller1', $5, $6, $7, $8
+            )
+            ",
+        )
+        .bind(Uuid::now_v7())
+        .bind(start)
+        .bind(end)
+        .bind(cid)
+        .bind(callsign)
+        .bind(format!("position-{cid}"))
+        .bind(callsign_session_id)
+        .bind(position_session_id)
+        .execute(&self.pool)
+        .await
+        .unwrap();
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let (url, name) = (self.url.clone(), self.name.clone());
+        // The test's runtime can't be blocked on from here, and may be unwinding, so the database
+        // is dropped from a runtime of its own. FORCE closes the test's pool's connections.
+        let dropped = std::thread::spawn(move || -> Result<(), sqlx::Error> {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(async {
+                let admin = PgPool::connect(&url).await?;
+                sqlx::query(&format!("DROP DATABASE IF EXISTS {name} WITH (FORCE)"))
+                    .execute(&admin)
+                    .await?;
+                admin.close().await;
+                Ok(())
+            })
+        })
+        .join();
+        if let Ok(Err(e)) = dropped {
+            eprintln!("couldn't drop test database {}: {e}", self.name);
+        }
+    }
+}
@@ -1,5 +1,64 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+
+/// Declares a string enum that keeps any value it doesn't recognise in an `Unknown` variant instead
+/// of failing to deserialize, so a new value added by vNAS doesn't break parsing of the datafeed.
+macro_rules! lenient_enum {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($variant:ident),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+        $vis enum $name {
+            $($variant,)+
+            Unknown(String),
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => stringify!($variant),)+
+                    Self::Unknown(value) => value,
+                }
+            }
+
+            /// Returns the raw value if it wasn't one of the known variants
+            pub fn as_unknown(&self) -> Option<&str> {
+                match self {
+                    Self::Unknown(value) => Some(value),
+                    _ => None,
+                }
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(value: String) -> Self {
+                match value.as_str() {
+                    $(stringify!($variant) => Self::$variant,)+
+                    _ => Self::Unknown(value),
+                }
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer).map(Self::from)
+            }
+        }
+    };
+}
 
 pub enum VnasEnvironment {
     Live,
@@ -21,11 +80,93 @@ pub const fn datafeed_url(env: VnasEnvironment) -> &'static str {
     }
 }
 
+/// The vNAS controllers datafeed. Controllers are parsed individually, so a single malformed
+/// controller is recorded in `skipped_controllers` rather than failing the whole datafeed.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", from = "RawDatafeedRoot")]
 pub struct DatafeedRoot {
     pub updated_at: DateTime<Utc>,
     pub controllers: Vec<Controller>,
+    #[serde(skip_serializing)]
+    pub skipped_controllers: Vec<SkippedController>,
+}
+
+/// A controller entry that was present in the datafeed but could not be deserialized.
+#[derive(Debug, Clone)]
+pub struct SkippedController {
+    pub index: usize,
+    pub cid: Option<String>,
+    pub callsign: Option<String>,
+    pub error: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawDatafeedRoot {
+    updated_at: DateTime<Utc>,
+    controllers: Vec<Value>,
+}
+
+impl From<RawDatafeedRoot> for DatafeedRoot {
+    fn from(raw: RawDatafeedRoot) -> Self {
+        let mut controllers = Vec::with_capacity(raw.controllers.len());
+        let mut skipped_controllers = Vec::new();
+
+        for (index, value) in raw.controllers.into_iter().enumerate() {
+            let vatsim_str = |field: &str| {
+                value
+                    .get("vatsimData")
+                    .and_then(|v| v.get(field))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            };
+            let (cid, callsign) = (vatsim_str("cid"), vatsim_str("callsign"));
+
+            match serde_json::from_value::<Controller>(value) {
+                Ok(controller) => controllers.push(controller),
+                Err(e) => skipped_controllers.push(SkippedController {
+                    index,
+                    cid,
+                    callsign,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        Self {
+            updated_at: raw.updated_at,
+            controllers,
+            skipped_controllers,
+        }
+    }
+}
+
+impl DatafeedRoot {
+    /// Returns every enum value in the datafeed that wasn't recognised, as `(field, value)` pairs.
+    pub fn unknown_values(&self) -> Vec<(&'static str, &str)> {
+        let mut unknown = Vec::new();
+        for controller in &self.controllers {
+            let vatsim_data = &controller.vatsim_data;
+            let values = [
+                ("role", controller.role.as_unknown()),
+                ("userRating", vatsim_data.user_rating.as_unknown()),
+                ("requestedRating", vatsim_data.requested_rating.as_unknown()),
+                ("facilityType", vatsim_data.facility_type.as_unknown()),
+            ];
+            let position_values = controller
+                .positions
+                .iter()
+                .map(|p| ("positionType", p.position_type.as_unknown()));
+
+            unknown.extend(
+                values
+                    .into_iter()
+                    .chain(position_values)
+                    .filter_map(|(field, value)| value.map(|v| (field, v))),
+            );
+        }
+        unknown
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,35 +183,38 @@ pub struct Controller {
     pub vatsim_data: VatsimData,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
-pub enum Role {
-    Observer,
-    Controller,
-    Student,
-    Instructor,
+lenient_enum! {
+    pub enum Role {
+        Observer,
+        Controller,
+        Student,
+        Instructor,
+    }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
-pub enum PositionType {
-    Artcc,
-    Tracon,
-    Atct,
+lenient_enum! {
+    pub enum PositionType {
+        Artcc,
+        Tracon,
+        Atct,
+    }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
-pub enum UserRating {
-    Observer,
-    Student1,
-    Student2,
-    Student3,
-    Controller1,
-    Controller2,
-    Controller3,
-    Instructor1,
-    Instructor2,
-    Instructor3,
-    Supervisor,
-    Administrator,
+lenient_enum! {
+    pub enum UserRating {
+        Observer,
+        Student1,
+        Student2,
+        Student3,
+        Controller1,
+        Controller2,
+        Controller3,
+        Instructor1,
+        Instructor2,
+        Instructor3,
+        Supervisor,
+        Administrator,
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -86,15 +230,16 @@ pub struct VatsimData {
     pub primary_frequency: i32,
 }
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
-pub enum VatsimFacilityType {
-    Observer,
-    FlightServiceStation,
-    ClearanceDelivery,
-    Ground,
-    Tower,
-    ApproachDeparture,
-    Center,
+lenient_enum! {
+    pub enum VatsimFacilityType {
+        Observer,
+        FlightServiceStation,
+        ClearanceDelivery,
+        Ground,
+        Tower,
+        ApproachDeparture,
+        Center,
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
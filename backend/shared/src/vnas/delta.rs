@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+/// Identifies a controller across consecutive datafeeds. A controller that logs in again gets a new
+/// key, so it is stored as a removal and an addition.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ControllerKey {
+    pub cid: String,
+    pub login_time: String,
+}
+
+impl ControllerKey {
+    pub fn of(controller: &Value) -> Option<Self> {
+        Some(Self {
+            cid: controller.pointer("/vatsimData/cid")?.as_str()?.to_string(),
+            login_time: controller.get("loginTime")?.as_str()?.to_string(),
+        })
+    }
+}
+
+/// The difference between two consecutive raw datafeeds.
+///
+/// Top-level fields other than `controllers` are small and stored in full. Controllers that were
+/// added or changed are stored in full, removed controllers only by key. `order` is only stored if
+/// applying the delta wouldn't otherwise reproduce the order of the controllers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatafeedDelta {
+    pub root: Map<String, Value>,
+    pub removed: Vec<ControllerKey>,
+    pub upserted: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<ControllerKey>>,
+}
+
+/// Computes the delta from `previous` to `current`. Returns `None` if either payload can't be
+/// delta-encoded, i.e. it has no `controllers` array or its controllers aren't uniquely keyed.
+pub fn diff(previous: &Value, current: &Value) -> Option<DatafeedDelta> {
+    let previous_controllers = keyed_controllers(previous)?;
+    let current_controllers = keyed_controllers(current)?;
+    let previous_by_key = previous_controllers
+        .iter()
+        .map(|(key, controller)| (key, *controller))
+        .collect::<HashMap<_, _>>();
+    let current_keys = current_controllers
+        .iter()
+        .map(|(key, _)| key)
+        .collect::<HashSet<_>>();
+
+    let removed = previous_controllers
+        .iter()
+        .filter(|(key, _)| !current_keys.contains(key))
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+    let upserted = current_controllers
+        .iter()
+        .filter(|(key, controller)| previous_by_key.get(key) != Some(controller))
+        .map(|(_, controller)| (*controller).clone())
+        .collect::<Vec<_>>();
+
+    let mut root = current.as_object()?.clone();
+    root.remove("controllers");
+    let mut delta = DatafeedDelta {
+        root,
+        removed,
+        upserted,
+        order: None,
+    };
+
+    let expected_order = current_controllers
+        .iter()
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+    let applied_order = apply_controllers(&previous_controllers, &delta)
+        .iter()
+        .map(|(key, _)| key.clone())
+        .collect::<Vec<_>>();
+    if applied_order != expected_order {
+        delta.order = Some(expected_order);
+    }
+
+    Some(delta)
+}
+
+/// Reconstructs the datafeed that `delta` was computed against `previous` for. Returns `None` if
+/// `previous` can't have been the base of a delta.
+pub fn apply(previous: &Value, delta: &DatafeedDelta) -> Option<Value> {
+    let previous_controllers = keyed_controllers(previous)?;
+    let mut controllers = apply_controllers(&previous_controllers, delta);
+
+    if let Some(order) = &delta.order {
+        let mut by_key = controllers.drain(..).collect::<HashMap<_, _>>();
+        controllers = order
+            .iter()
+            .map(|key| {
+                by_key
+                    .remove(key)
+                    .map(|controller| (key.clone(), controller))
+            })
+            .collect::<Option<Vec<_>>>()?;
+    }
+
+    let mut root = delta.root.clone();
+    root.insert(
+        "controllers".to_string(),
+        Value::Array(controllers.into_iter().map(|(_, c)| c).collect()),
+    );
+    Some(Value::Object(root))
+}
+
+fn keyed_controllers(datafeed: &Value) -> Option<Vec<(ControllerKey, &Value)>> {
+    let controllers = datafeed.get("controllers")?.as_array()?;
+    let mut seen = HashSet::with_capacity(controllers.len());
+    controllers
+        .iter()
+        .map(|controller| {
+            let key = ControllerKey::of(controller)?;
+            seen.insert(key.clone()).then_some((key, controller))
+        })
+        .collect()
+}
+
+/// Removes, replaces in place and appends controllers, in that order
+fn apply_controllers(
+    previous: &[(ControllerKey, &Value)],
+    delta: &DatafeedDelta,
+) -> Vec<(ControllerKey, Value)> {
+    let removed = delta.removed.iter().collect::<HashSet<_>>();
+    let upserted = delta
+        .upserted
+        .iter()
+        .filter_map(|controller| Some((ControllerKey::of(controller)?, controller)))
+        .collect::<Vec<_>>();
+    let upserted_by_key = upserted
+        .iter()
+        .map(|(key, controller)| (key, *controller))
+        .collect::<HashMap<_, _>>();
+    let previous_keys = previous.iter().map(|(key, _)| key).collect::<HashSet<_>>();
+
+    let mut controllers = previous
+        .iter()
+        .filter(|(key, _)| !removed.contains(key))
+        .map(|(key, controller)| {
+            let controller = upserted_by_key.get(key).copied().unwrap_or(controller);
+            (key.clone(), controller.clone())
+        })
+        .collect::<Vec<_>>();
+    controllers.extend(
+        upserted
+            .iter()
+            .filter(|(key, _)| !previous_keys.contains(key))
+            .map(|(key, controller)| (key.clone(), (*controller).clone())),
+    );
+    controllers
+}
@@ -0,0 +1,379 @@
+use crate::vnas::api::ArtccRoot;
+use crate::vnas::datafeed::{Controller, DatafeedRoot};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_path_to_error::Segment;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Upper bound on how many rejected unknown fields are stripped from a single payload before giving
+/// up, so a payload that is nothing like the DTO can't loop for long.
+const MAX_STRIPPED_FIELDS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DriftKind {
+    /// Present in the payload but not modelled by the DTO
+    NewField,
+    /// Modelled by the DTO but absent from the payload
+    MissingField,
+    /// Present in both, but with a JSON type the DTO doesn't produce or accept
+    RetypedField,
+    /// An enum value the DTO doesn't recognise
+    UnknownValue,
+    /// Any other deserialization failure
+    Incompatible,
+}
+
+impl Display for DriftKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NewField => write!(f, "new"),
+            Self::MissingField => write!(f, "missing"),
+            Self::RetypedField => write!(f, "retyped"),
+            Self::UnknownValue => write!(f, "unknown value"),
+            Self::Incompatible => write!(f, "incompatible"),
+        }
+    }
+}
+
+/// A single difference between a payload and a DTO. Array indices in `path` are collapsed to `[]`
+/// so that findings from different elements can be aggregated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub kind: DriftKind,
+    pub path: String,
+    pub detail: String,
+}
+
+/// Compares `value` against the DTO `T`.
+///
+/// The payload is deserialized into `T` and serialized back, and the two JSON trees are compared.
+/// Fields rejected by `#[serde(deny_unknown_fields)]` are reported and stripped so the rest of the
+/// payload can still be compared; any other deserialization failure is reported and ends the check.
+pub fn check<T: DeserializeOwned + Serialize>(value: &Value) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut value = value.clone();
+
+    for _ in 0..MAX_STRIPPED_FIELDS {
+        let err = match serde_path_to_error::deserialize::<_, T>(&value) {
+            Ok(dto) => {
+                match serde_json::to_value(&dto) {
+                    Ok(canonical) => compare(&value, &canonical, "", &mut findings),
+                    Err(e) => findings.push(Finding {
+                        kind: DriftKind::Incompatible,
+                        path: String::new(),
+                        detail: format!("DTO could not be serialized: {e}"),
+                    }),
+                }
+                return findings;
+            }
+            Err(err) => err,
+        };
+
+        let (finding, unknown_field) = classify(&err);
+        findings.push(finding);
+
+        // Strip the rejected field and try again, otherwise there's nothing more we can compare
+        let Some(field) = unknown_field else {
+            return findings;
+        };
+        let segments = err.path().iter().collect::<Vec<_>>();
+        let parent = json_pointer(&segments[..segments.len().saturating_sub(1)]);
+        let removed = value
+            .pointer_mut(&parent)
+            .and_then(Value::as_object_mut)
+            .and_then(|object| object.remove(&field));
+        if removed.is_none() {
+            return findings;
+        }
+    }
+
+    findings
+}
+
+/// Compares a vNAS datafeed against [`DatafeedRoot`]. Controllers are checked individually, in the
+/// same way the datafeed is parsed, and unrecognised enum values are reported.
+pub fn check_datafeed(value: &Value) -> Vec<Finding> {
+    let mut root = value.clone();
+    let controllers = root
+        .get_mut("controllers")
+        .and_then(Value::as_array_mut)
+        .map(std::mem::take)
+        .unwrap_or_default();
+
+    let mut findings = check::<DatafeedRoot>(&root);
+    for controller in &controllers {
+        findings.extend(
+            check::<Controller>(controller)
+                .into_iter()
+                .map(|f| Finding {
+                    path: join_path("controllers[]", &f.path),
+                    ..f
+                }),
+        );
+    }
+
+    if let Ok(datafeed) = serde_json::from_value::<DatafeedRoot>(value.clone()) {
+        findings.extend(
+            datafeed
+                .unknown_values()
+                .into_iter()
+                .map(|(field, value)| Finding {
+                    kind: DriftKind::UnknownValue,
+                    path: unknown_value_path(field),
+                    detail: value.to_string(),
+                }),
+        );
+    }
+
+    findings
+}
+
+/// Compares a response from the vNAS ARTCCs endpoint against [`ArtccRoot`]. Accepts either the full
+/// array or a single ARTCC.
+pub fn check_artccs(value: &Value) -> Vec<Finding> {
+    if value.is_array() {
+        check::<Vec<ArtccRoot>>(value)
+    } else {
+        check::<ArtccRoot>(value)
+    }
+}
+
+fn unknown_value_path(field: &str) -> String {
+    match field {
+        "role" => "controllers[].role".to_string(),
+        "positionType" => "controllers[].positions[].positionType".to_string(),
+        other => format!("controllers[].vatsimData.{other}"),
+    }
+}
+
+/// Maps a deserialization error onto a finding. Also returns the field name when the error was an
+/// unknown field, so the caller can strip it and continue. For unknown fields the error path already
+/// ends at the rejected field.
+fn classify(err: &serde_path_to_error::Error<serde_json::Error>) -> (Finding, Option<String>) {
+    let path = display_path(err.path());
+    let message = err.inner().to_string();
+
+    if let Some(field) = backticked(&message, "unknown field `") {
+        let finding = Finding {
+            kind: DriftKind::NewField,
+            path,
+            detail: "rejected by DTO".to_string(),
+        };
+        return (finding, Some(field));
+    }
+
+    let finding = if let Some(field) = backticked(&message, "missing field `") {
+        Finding {
+            kind: DriftKind::MissingField,
+            path: join_path(&path, &field),
+            detail: "required by DTO".to_string(),
+        }
+    } else if let Some(variant) = backticked(&message, "unknown variant `") {
+        Finding {
+            kind: DriftKind::UnknownValue,
+            path,
+            detail: variant,
+        }
+    } else if message.starts_with("invalid type") {
+        Finding {
+            kind: DriftKind::RetypedField,
+            path,
+            detail: message,
+        }
+    } else {
+        Finding {
+            kind: DriftKind::Incompatible,
+            path,
+            detail: message,
+        }
+    };
+
+    (finding, None)
+}
+
+fn backticked(message: &str, prefix: &str) -> Option<String> {
+    let rest = message.strip_prefix(prefix)?;
+    rest.split('`').next().map(str::to_string)
+}
+
+fn compare(payload: &Value, canonical: &Value, path: &str, findings: &mut Vec<Finding>) {
+    match (payload, canonical) {
+        (Value::Object(payload), Value::Object(canonical)) => {
+            for (key, value) in payload {
+                let field_path = join_path(path, key);
+                match canonical.get(key) {
+                    Some(expected) => compare(value, expected, &field_path, findings),
+                    None => findings.push(Finding {
+                        kind: DriftKind::NewField,
+                        path: field_path,
+                        detail: json_type(value).to_string(),
+                    }),
+                }
+            }
+            for (key, expected) in canonical {
+                if !payload.contains_key(key) {
+                    findings.push(Finding {
+                        kind: DriftKind::MissingField,
+                        path: join_path(path, key),
+                        detail: if expected.is_null() {
+                            "optional in DTO".to_string()
+                        } else {
+                            "defaulted by DTO".to_string()
+                        },
+                    });
+                }
+            }
+        }
+        (Value::Array(payload), Value::Array(canonical)) => {
+            let element_path = format!("{path}[]");
+            for (value, expected) in payload.iter().zip(canonical) {
+                compare(value, expected, &element_path, findings);
+            }
+        }
+        (payload, canonical) if json_type(payload) != json_type(canonical) => {
+            findings.push(Finding {
+                kind: DriftKind::RetypedField,
+                path: path.to_string(),
+                detail: format!(
+                    "payload has {}, DTO produces {}",
+                    json_type(payload),
+                    json_type(canonical)
+                ),
+            });
+        }
+        _ => {}
+    }
+}
+
+const fn json_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn join_path(parent: &str, child: &str) -> String {
+    match (parent.is_empty(), child.is_empty()) {
+        (true, _) => child.to_string(),
+        (false, true) => parent.to_string(),
+        (false, false) if child.starts_with('[') => format!("{parent}{child}"),
+        (false, false) => format!("{parent}.{child}"),
+    }
+}
+
+fn display_path(path: &serde_path_to_error::Path) -> String {
+    path.iter()
+        .fold(String::new(), |acc, segment| match segment {
+            Segment::Seq { .. } => format!("{acc}[]"),
+            Segment::Map { key } => join_path(&acc, key),
+            Segment::Enum { .. } | Segment::Unknown => acc,
+        })
+}
+
+fn json_pointer(segments: &[&Segment]) -> String {
+    segments
+        .iter()
+        .fold(String::new(), |acc, segment| match segment {
+            Segment::Seq { index } => format!("{acc}/{index}"),
+            Segment::Map { key } => format!("{acc}/{}", key.replace('~', "~0").replace('/', "~1")),
+            Segment::Enum { .. } | Segment::Unknown => acc,
+        })
+}
+
+/// How often a finding was seen across all checked payloads.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindingSummary {
+    pub kind: DriftKind,
+    pub path: String,
+    pub occurrences: usize,
+    pub payloads: usize,
+    pub first_seen_in: String,
+    pub example: String,
+}
+
+/// Findings aggregated over many payloads, keyed by kind and path.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftReport {
+    pub payloads_checked: usize,
+    #[serde(serialize_with = "serialize_summaries")]
+    findings: BTreeMap<(DriftKind, String), FindingSummary>,
+}
+
+impl DriftReport {
+    /// Adds the findings from a single payload identified by `source`.
+    pub fn add(&mut self, source: &str, findings: Vec<Finding>) {
+        self.payloads_checked += 1;
+        let mut seen_in_payload = Vec::new();
+
+        for finding in findings {
+            let key = (finding.kind, finding.path);
+            let summary = self
+                .findings
+                .entry(key.clone())
+                .or_insert_with(|| FindingSummary {
+                    kind: key.0,
+                    path: key.1.clone(),
+                    occurrences: 0,
+                    payloads: 0,
+                    first_seen_in: source.to_string(),
+                    example: finding.detail,
+                });
+            summary.occurrences += 1;
+            if !seen_in_payload.contains(&key) {
+                summary.payloads += 1;
+                seen_in_payload.push(key);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn findings(&self) -> impl Iterator<Item = &FindingSummary> {
+        self.findings.values()
+    }
+}
+
+fn serialize_summaries<S: serde::Serializer>(
+    findings: &BTreeMap<(DriftKind, String), FindingSummary>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(findings.values())
+}
+
+impl Display for DriftReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "checked {} payload(s)", self.payloads_checked)?;
+        if self.findings.is_empty() {
+            return writeln!(f, "no drift found");
+        }
+
+        for summary in self.findings.values() {
+            writeln!(
+                f,
+                "{:<13} {} ({} occurrence(s) in {} payload(s), first in {}): {}",
+                summary.kind.to_string(),
+                if summary.path.is_empty() {
+                    "<root>"
+                } else {
+                    &summary.path
+                },
+                summary.occurrences,
+                summary.payloads,
+                summary.first_seen_in,
+                summary.example
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use crate::FetcherConfig;
+use crate::vnas::datafeed::{VnasEnvironment, datafeed_url};
+use chrono::{DateTime, TimeDelta, Utc};
+use rand::Rng;
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::instrument;
+
+#[derive(Error, Debug)]
+pub enum FetchError {
+    #[error(transparent)]
+    Reqwest(#[from] reqwest::Error),
+    #[error(transparent)]
+    Deserialize(#[from] serde_json::Error),
+    #[error(transparent)]
+    TimestampDeserialize(#[from] chrono::format::ParseError),
+    #[error("unable to find or parse updatedAt field in JSON")]
+    MissingUpdatedAt,
+}
+
+#[derive(Debug)]
+pub enum FetchOutcome {
+    /// The datafeed changed since the last fetch, or this is the first fetch
+    Modified {
+        payload: Value,
+        updated_at: DateTime<Utc>,
+    },
+    /// The server answered a conditional request with `304 Not Modified`
+    NotModified,
+}
+
+/// Fetches the live datafeed with conditional requests, sending back the `ETag` and
+/// `Last-Modified` of the previous response so an unchanged datafeed isn't downloaded again
+#[derive(Debug, Clone)]
+pub struct DatafeedFetcher {
+    client: reqwest::Client,
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+}
+
+impl DatafeedFetcher {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self {
+            client,
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    #[instrument(skip(self))]
+    pub async fn fetch(&mut self) -> Result<FetchOutcome, FetchError> {
+        let mut request = self.client.get(datafeed_url(VnasEnvironment::Live));
+        if let Some(etag) = &self.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let resp = request.send().await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            return Ok(FetchOutcome::NotModified);
+        }
+        let resp = resp.error_for_status()?;
+        let etag = resp.headers().get(ETAG).cloned();
+        let last_modified = resp.headers().get(LAST_MODIFIED).cloned();
+
+        let (payload, updated_at) = parse_datafeed(&resp.text().await?)?;
+        // Only remember validators once the body has been read and parsed, so a failed fetch is
+        // retried in full
+        self.etag = etag;
+        self.last_modified = last_modified;
+        Ok(FetchOutcome::Modified {
+            payload,
+            updated_at,
+        })
+    }
+}
+
+/// Parses a datafeed as raw JSON, returning it along with its `updatedAt`
+pub fn parse_datafeed(body: &str) -> Result<(Value, DateTime<Utc>), FetchError> {
+    let value: Value = serde_json::from_str(body)?;
+    if let Some(timestamp_val) = value.get("updatedAt")
+        && let Some(timestamp_str) = timestamp_val.as_str()
+    {
+        let timestamp = DateTime::parse_from_rfc3339(timestamp_str)?;
+        Ok((value, timestamp.with_timezone(&Utc)))
+    } else {
+        Err(FetchError::MissingUpdatedAt)
+    }
+}
+
+/// Number of recent intervals between datafeed updates the cadence is estimated from
+const CADENCE_SAMPLES: usize = 8;
+/// Intervals longer than this are treated as outages rather than the datafeed's cadence
+const MAX_CADENCE: TimeDelta = TimeDelta::minutes(5);
+/// How long after the expected update the datafeed is polled, to allow for it being published late
+const POLL_MARGIN: TimeDelta = TimeDelta::seconds(1);
+/// How soon the datafeed is polled again when it's overdue
+const OVERDUE_RETRY: Duration = Duration::from_secs(2);
+/// Shortest delay between polls
+const MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Decides when the datafeed is polled next.
+///
+/// After a failed fetch, polls back off exponentially with jitter. Otherwise, once the interval
+/// between updates is known, polls are timed to just after the next expected update, and retried
+/// shortly while it's overdue. Until then, or if the datafeed stalls for longer than a full
+/// interval, it's polled every `interval_seconds`.
+#[derive(Debug, Clone)]
+pub struct PollSchedule {
+    interval: Duration,
+    adaptive: bool,
+    max_backoff: Duration,
+    recent_intervals: VecDeque<TimeDelta>,
+    last_updated_at: Option<DateTime<Utc>>,
+    consecutive_failures: u32,
+}
+
+impl PollSchedule {
+    pub fn new(config: &FetcherConfig) -> Self {
+        Self {
+            interval: Duration::from_secs(config.interval_seconds),
+            adaptive: config.adaptive,
+            max_backoff: Duration::from_secs(config.max_backoff_seconds),
+            recent_intervals: VecDeque::with_capacity(CADENCE_SAMPLES),
+            last_updated_at: None,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Records a successful fetch of a datafeed last updated at `updated_at`
+    pub fn record_success(&mut self, updated_at: DateTime<Utc>) {
+        self.consecutive_failures = 0;
+        if let Some(last) = self.last_updated_at {
+            if updated_at <= last {
+                return;
+            }
+            let elapsed = updated_at - last;
+            if elapsed <= MAX_CADENCE {
+                if self.recent_intervals.len() == CADENCE_SAMPLES {
+                    self.recent_intervals.pop_front();
+                }
+                self.recent_intervals.push_back(elapsed);
+            }
+        }
+        self.last_updated_at = Some(updated_at);
+    }
+
+    /// Records a successful fetch that found the datafeed unchanged
+    pub fn record_unchanged(&mut self) {
+        self.consecutive_failures = 0;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// `updatedAt` of the newest datafeed fetched so far
+    pub fn last_updated_at(&self) -> Option<DateTime<Utc>> {
+        self.last_updated_at
+    }
+
+    /// Median interval between recent datafeed updates, once there are enough to tell
+    pub fn cadence(&self) -> Option<TimeDelta> {
+        if self.recent_intervals.len() < 2 {
+            return None;
+        }
+        let mut intervals: Vec<_> = self.recent_intervals.iter().copied().collect();
+        intervals.sort_unstable();
+        Some(intervals[intervals.len() / 2])
+    }
+
+    /// How long to wait before the next poll, as of `now`
+    pub fn next_delay(&self, now: DateTime<Utc>) -> Duration {
+        if self.consecutive_failures > 0 {
+            return self.backoff();
+        }
+        if !self.adaptive {
+            return self.interval;
+        }
+        let (Some(cadence), Some(last_updated_at)) = (self.cadence(), self.last_updated_at) else {
+            return self.interval;
+        };
+
+        let expected = last_updated_at + cadence + POLL_MARGIN;
+        if expected > now {
+            // Bounded in case the upstream clock is ahead of ours
+            let longest = (cadence + POLL_MARGIN).to_std().unwrap_or(self.interval);
+            (expected - now)
+                .to_std()
+                .unwrap_or(self.interval)
+                .clamp(MIN_DELAY, longest.max(MIN_DELAY))
+        } else if now - expected <= cadence {
+            OVERDUE_RETRY
+        } else {
+            // Stalled upstream; don't keep polling every couple of seconds
+            self.interval
+        }
+    }
+
+    /// Exponential backoff from `interval_seconds` up to `max_backoff_seconds`, with "equal
+    /// jitter" so that the delay is somewhere between half and all of it
+    fn backoff(&self) -> Duration {
+        let exponent = self.consecutive_failures.saturating_sub(1).min(16);
+        let backoff = self
+            .interval
+            .max(MIN_DELAY)
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff.max(MIN_DELAY));
+        let half = backoff / 2;
+        half + half.mul_f64(rand::rng().random::<f64>())
+    }
+}
@@ -1,2 +1,5 @@
 pub mod api;
 pub mod datafeed;
+pub mod delta;
+pub mod drift;
+pub mod fetch;
@@ -0,0 +1,80 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::json;
+use shared::ArchiveConfig;
+use shared::archive::{
+    ArchiveStore, ArchivedPayload, decode_payloads, encode_payloads, iter_payloads,
+};
+use uuid::Uuid;
+
+const DATAFEED_FIXTURE: &str = include_str!("fixtures/datafeed.json");
+
+fn payloads(count: u32) -> Vec<ArchivedPayload> {
+    let base = serde_json::from_str::<serde_json::Value>(DATAFEED_FIXTURE).unwrap();
+    (0..count)
+        .map(|i| {
+            let updated_at = format!("2025-12-20T18:00:{:02}Z", i * 15 % 60)
+                .parse::<DateTime<Utc>>()
+                .unwrap();
+            let mut payload = base.clone();
+            payload["updatedAt"] = json!(updated_at);
+            ArchivedPayload {
+                id: Uuid::now_v7(),
+                updated_at,
+                created_at: updated_at,
+                original_size_bytes: i32::try_from(DATAFEED_FIXTURE.len()).unwrap(),
+                payload,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn archive_round_trips() {
+    let payloads = payloads(4);
+    let encoded = encode_payloads(&payloads).unwrap();
+    assert_eq!(decode_payloads(&encoded).unwrap(), payloads);
+    assert_eq!(iter_payloads(&encoded).unwrap().count(), payloads.len());
+}
+
+#[test]
+fn empty_archive_round_trips() {
+    let encoded = encode_payloads(&[]).unwrap();
+    assert!(decode_payloads(&encoded).unwrap().is_empty());
+}
+
+#[test]
+fn archive_keys_are_partitioned_by_day() {
+    let day = NaiveDate::from_ymd_opt(2025, 3, 7).unwrap();
+    assert_eq!(
+        ArchiveStore::datafeeds_key(day),
+        "datafeeds/2025/03/07.ndjson.zst"
+    );
+    assert_eq!(
+        ArchiveStore::controllers_key(day),
+        "controllers/2025/03/07.parquet"
+    );
+}
+
+#[tokio::test]
+async fn local_archive_reads_back_written_day() {
+    let dir = std::env::temp_dir().join(format!("vnas-stats-archive-{}", Uuid::now_v7()));
+    let store = ArchiveStore::from_config(&ArchiveConfig {
+        location: format!("file://{}", dir.display()),
+        retention_days: 30,
+        parquet: false,
+    })
+    .unwrap();
+
+    let day = NaiveDate::from_ymd_opt(2025, 12, 20).unwrap();
+    let payloads = payloads(3);
+    store
+        .put(
+            &ArchiveStore::datafeeds_key(day),
+            encode_payloads(&payloads).unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(store.read_day(day).await.unwrap(), payloads);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,85 @@
+use chrono::Utc;
+use serde_json::json;
+use shared::compression::{
+    Codec, CompressionError, Dictionary, ZSTD, ZSTD_DICT, decompress, train_dictionary,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+const DATAFEED_FIXTURE: &str = include_str!("fixtures/datafeed.json");
+
+/// Consecutive datafeeds only differ slightly, so vary the fixture a little per sample
+fn samples(count: usize) -> Vec<Vec<u8>> {
+    let base = serde_json::from_str::<serde_json::Value>(DATAFEED_FIXTURE).unwrap();
+    (0..count)
+        .map(|i| {
+            let mut sample = base.clone();
+            sample["updatedAt"] = json!(format!("2025-12-20T18:{:02}:{:02}.000Z", i / 60, i % 60));
+            sample["controllers"][0]["vatsimData"]["controllerInfo"] = json!(format!("info {i}"));
+            serde_json::to_vec(&sample).unwrap()
+        })
+        .collect()
+}
+
+fn dictionary(samples: &[Vec<u8>]) -> Dictionary {
+    Dictionary {
+        id: Uuid::now_v7(),
+        dictionary: train_dictionary(samples, 16 * 1024).unwrap(),
+        sample_count: i32::try_from(samples.len()).unwrap(),
+        created_at: Utc::now(),
+    }
+}
+
+#[test]
+fn plain_zstd_round_trips() {
+    let data = DATAFEED_FIXTURE.as_bytes();
+    let compressed = Codec::default().compress(data).unwrap();
+    assert_eq!(compressed.algo, ZSTD);
+    assert_eq!(compressed.dictionary_id, None);
+    assert_eq!(decompress(&compressed.bytes, ZSTD, None).unwrap(), data);
+}
+
+#[test]
+fn dictionary_round_trips_and_compresses_better() {
+    let samples = samples(200);
+    let dictionary = Arc::new(dictionary(&samples));
+    let codec = Codec {
+        level: 3,
+        dictionary: Some(Arc::clone(&dictionary)),
+    };
+
+    let data = &samples[42];
+    let compressed = codec.compress(data).unwrap();
+    assert_eq!(compressed.algo, ZSTD_DICT);
+    assert_eq!(compressed.dictionary_id, Some(dictionary.id));
+    assert_eq!(
+        decompress(&compressed.bytes, ZSTD_DICT, Some(&dictionary)).unwrap(),
+        *data
+    );
+
+    let plain = Codec::default().compress(data).unwrap();
+    assert!(compressed.bytes.len() < plain.bytes.len());
+}
+
+#[test]
+fn dictionary_payload_requires_dictionary() {
+    let samples = samples(200);
+    let codec = Codec {
+        level: 3,
+        dictionary: Some(Arc::new(dictionary(&samples))),
+    };
+    let compressed = codec.compress(&samples[0]).unwrap();
+
+    assert!(matches!(
+        decompress(&compressed.bytes, ZSTD_DICT, None),
+        Err(CompressionError::MissingDictionaryId { .. })
+    ));
+}
+
+#[test]
+fn unknown_algo_is_rejected() {
+    assert!(matches!(
+        decompress(b"", "gzip", None),
+        Err(CompressionError::UnsupportedAlgo(algo)) if algo == "gzip"
+    ));
+}
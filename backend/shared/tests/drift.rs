@@ -0,0 +1,113 @@
+use serde_json::{Value, json};
+use shared::vnas::drift::{DriftKind, DriftReport, Finding, check_artccs, check_datafeed};
+
+fn fixture(name: &str) -> Value {
+    let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+    serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+fn kinds_at<'a>(findings: &'a [Finding], path: &str) -> Vec<&'a DriftKind> {
+    findings
+        .iter()
+        .filter(|f| f.path == path)
+        .map(|f| &f.kind)
+        .collect()
+}
+
+#[test]
+fn fixtures_have_no_drift() {
+    assert_eq!(check_datafeed(&fixture("datafeed.json")), vec![]);
+    assert_eq!(check_artccs(&fixture("artccs.json")), vec![]);
+}
+
+#[test]
+fn datafeed_drift_is_detected() {
+    let mut value = fixture("datafeed.json");
+    value["controllers"][0]["vatsimData"]["pronouns"] = json!("they/them");
+    value["controllers"][1]["positions"][0]
+        .as_object_mut()
+        .unwrap()
+        .remove("starsData");
+    value["controllers"][2]["vatsimData"]["primaryFrequency"] = json!("118.800");
+    value["controllers"][2]["role"] = json!("Mentor");
+
+    let findings = check_datafeed(&value);
+    assert_eq!(
+        kinds_at(&findings, "controllers[].vatsimData.pronouns"),
+        vec![&DriftKind::NewField]
+    );
+    assert_eq!(
+        kinds_at(&findings, "controllers[].positions[].starsData"),
+        vec![&DriftKind::MissingField]
+    );
+    assert_eq!(
+        kinds_at(&findings, "controllers[].vatsimData.primaryFrequency"),
+        vec![&DriftKind::RetypedField]
+    );
+}
+
+#[test]
+fn unknown_datafeed_values_are_reported() {
+    let mut value = fixture("datafeed.json");
+    value["controllers"][0]["role"] = json!("Mentor");
+
+    let findings = check_datafeed(&value);
+    assert_eq!(
+        findings,
+        vec![Finding {
+            kind: DriftKind::UnknownValue,
+            path: "controllers[].role".to_string(),
+            detail: "Mentor".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn artcc_drift_is_detected_past_denied_fields() {
+    let mut value = fixture("artccs.json");
+    value[0]["facility"]["featureFlags"] = json!([]);
+    value[0]["transceivers"][0]["antennaType"] = json!("Omni");
+
+    let findings = check_artccs(&value);
+    assert_eq!(
+        kinds_at(&findings, "[].facility.featureFlags"),
+        vec![&DriftKind::NewField]
+    );
+    assert_eq!(
+        kinds_at(&findings, "[].transceivers[].antennaType"),
+        vec![&DriftKind::NewField]
+    );
+}
+
+#[test]
+fn artcc_retyped_field_is_detected() {
+    let mut value = fixture("artccs.json");
+    value[0]["facility"]["positions"][0]["frequency"] = json!("128.700");
+
+    let findings = check_artccs(&value);
+    assert_eq!(
+        kinds_at(&findings, "[].facility.positions[].frequency"),
+        vec![&DriftKind::RetypedField]
+    );
+}
+
+#[test]
+fn report_aggregates_by_kind_and_path() {
+    let mut first = fixture("datafeed.json");
+    first["controllers"][0]["vatsimData"]["pronouns"] = json!("");
+    first["controllers"][1]["vatsimData"]["pronouns"] = json!("");
+    let mut second = fixture("datafeed.json");
+    second["controllers"][2]["vatsimData"]["pronouns"] = json!("");
+
+    let mut report = DriftReport::default();
+    report.add("first.json", check_datafeed(&first));
+    report.add("second.json", check_datafeed(&second));
+    report.add("clean.json", check_datafeed(&fixture("datafeed.json")));
+
+    assert_eq!(report.payloads_checked, 3);
+    let summaries = report.findings().collect::<Vec<_>>();
+    assert_eq!(summaries.len(), 1);
+    assert_eq!(summaries[0].occurrences, 3);
+    assert_eq!(summaries[0].payloads, 2);
+    assert_eq!(summaries[0].first_seen_in, "first.json");
+}
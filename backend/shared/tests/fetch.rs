@@ -0,0 +1,101 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use shared::FetcherConfig;
+use shared::vnas::fetch::{FetchError, PollSchedule, parse_datafeed};
+use std::time::Duration;
+
+const DATAFEED_FIXTURE: &str = include_str!("fixtures/datafeed.json");
+
+fn at(seconds: i64) -> DateTime<Utc> {
+    "2025-12-20T18:00:00Z".parse::<DateTime<Utc>>().unwrap() + TimeDelta::seconds(seconds)
+}
+
+fn schedule() -> PollSchedule {
+    PollSchedule::new(&FetcherConfig {
+        interval_seconds: 15,
+        adaptive: true,
+        max_backoff_seconds: 120,
+        ..FetcherConfig::default()
+    })
+}
+
+#[test]
+fn parse_datafeed_returns_updated_at() {
+    let (payload, updated_at) = parse_datafeed(DATAFEED_FIXTURE).unwrap();
+    assert_eq!(
+        payload["updatedAt"]
+            .as_str()
+            .unwrap()
+            .parse::<DateTime<Utc>>()
+            .unwrap(),
+        updated_at
+    );
+    assert!(matches!(
+        parse_datafeed(r#"{"controllers": []}"#),
+        Err(FetchError::MissingUpdatedAt)
+    ));
+}
+
+#[test]
+fn polls_at_interval_until_cadence_is_known() {
+    let mut schedule = schedule();
+    assert_eq!(schedule.next_delay(at(0)), Duration::from_secs(15));
+
+    schedule.record_success(at(0));
+    schedule.record_success(at(15));
+    assert_eq!(schedule.cadence(), None);
+    assert_eq!(schedule.next_delay(at(16)), Duration::from_secs(15));
+}
+
+#[test]
+fn aligns_polls_to_upstream_cadence() {
+    let mut schedule = schedule();
+    for i in 0..4 {
+        schedule.record_success(at(i * 10));
+    }
+    // Repeated and outage-length gaps don't skew the estimate
+    schedule.record_success(at(30));
+    schedule.record_success(at(30 + 600));
+    schedule.record_success(at(640));
+    assert_eq!(schedule.cadence(), Some(TimeDelta::seconds(10)));
+
+    // Next update is expected at 650, polled a second later
+    assert_eq!(schedule.next_delay(at(642)), Duration::from_secs(9));
+    // Overdue, so retried soon
+    assert_eq!(schedule.next_delay(at(655)), Duration::from_secs(2));
+    // Stalled for longer than the cadence, so back to the regular interval
+    assert_eq!(schedule.next_delay(at(700)), Duration::from_secs(15));
+}
+
+#[test]
+fn non_adaptive_schedule_uses_interval() {
+    let mut schedule = PollSchedule::new(&FetcherConfig {
+        interval_seconds: 15,
+        adaptive: false,
+        max_backoff_seconds: 120,
+        ..FetcherConfig::default()
+    });
+    for i in 0..4 {
+        schedule.record_success(at(i * 10));
+    }
+    assert_eq!(schedule.next_delay(at(32)), Duration::from_secs(15));
+}
+
+#[test]
+fn backs_off_exponentially_with_jitter_on_failures() {
+    let mut schedule = schedule();
+    for failures in 1..=6u32 {
+        schedule.record_failure();
+        let backoff = Duration::from_secs((15 * 2u64.pow(failures - 1)).min(120));
+        for _ in 0..20 {
+            let delay = schedule.next_delay(at(0));
+            assert!(
+                delay >= backoff / 2 && delay <= backoff,
+                "{delay:?} vs {backoff:?}"
+            );
+        }
+    }
+
+    schedule.record_unchanged();
+    assert_eq!(schedule.consecutive_failures(), 0);
+    assert_eq!(schedule.next_delay(at(0)), Duration::from_secs(15));
+}
@@ -0,0 +1,120 @@
+use chrono::{TimeDelta, Utc};
+use shared::health::{Checks, Health, HealthConfig};
+
+fn health(checks: Checks) -> Health {
+    Health::new(
+        "test",
+        &HealthConfig {
+            max_success_age_seconds: 60,
+            max_lag_seconds: 120,
+            max_queue_age_seconds: 60,
+            ..HealthConfig::default()
+        },
+        checks,
+    )
+}
+
+#[tokio::test]
+async fn ready_while_starting_up() {
+    let health = health(Checks {
+        success: true,
+        lag: true,
+        queue_age: false,
+    });
+    let readiness = health.readiness().await;
+    assert!(readiness.ready, "{:?}", readiness.problems);
+    assert!(readiness.database.is_none());
+    assert!(readiness.lag_seconds.is_none());
+}
+
+#[tokio::test]
+async fn not_ready_once_last_success_is_stale() {
+    let health = health(Checks {
+        success: true,
+        lag: false,
+        queue_age: false,
+    });
+    health.record_success(Utc::now() - TimeDelta::seconds(90));
+    health.record_error("upstream unavailable");
+
+    let readiness = health.readiness().await;
+    assert!(!readiness.ready);
+    assert_eq!(readiness.problems.len(), 1);
+    assert_eq!(
+        readiness.last_error.unwrap().message,
+        "upstream unavailable"
+    );
+
+    health.record_success(Utc::now());
+    assert!(health.readiness().await.ready);
+}
+
+#[tokio::test]
+async fn not_ready_once_datafeed_lags() {
+    let health = health(Checks {
+        success: false,
+        lag: true,
+        queue_age: false,
+    });
+    health.set_datafeed_updated_at(Utc::now() - TimeDelta::seconds(300));
+    health.set_queue_depth(4);
+
+    let readiness = health.readiness().await;
+    assert!(!readiness.ready);
+    assert_eq!(readiness.queue_depth, Some(4));
+    assert!(readiness.lag_seconds.unwrap() >= 300);
+}
+
+#[tokio::test]
+async fn unchecked_staleness_does_not_affect_readiness() {
+    let health = health(Checks::default());
+    health.record_success(Utc::now() - TimeDelta::days(1));
+    health.set_datafeed_updated_at(Utc::now() - TimeDelta::days(1));
+    health.set_detail("dead_letters", 2);
+
+    let readiness = health.readiness().await;
+    assert!(readiness.ready);
+    assert_eq!(readiness.details["dead_letters"], 2);
+}
+
+#[tokio::test]
+async fn not_ready_once_oldest_queued_entry_waits_too_long() {
+    let health = health(Checks {
+        queue_age: true,
+        ..Checks::default()
+    });
+    health.set_oldest_queued_at(Some(Utc::now() - TimeDelta::seconds(10)));
+    assert!(health.readiness().await.ready);
+
+    health.set_oldest_queued_at(Some(Utc::now() - TimeDelta::seconds(90)));
+    let readiness = health.readiness().await;
+    assert!(!readiness.ready);
+    assert!(readiness.oldest_queued_age_seconds.unwrap() >= 90);
+
+    health.set_oldest_queued_at(None);
+    assert!(health.readiness().await.ready);
+}
+
+#[tokio::test]
+async fn standby_is_ready_until_it_takes_over() {
+    let health = health(Checks {
+        success: true,
+        lag: true,
+        queue_age: true,
+    });
+    health.set_standby(true);
+    health.record_success(Utc::now() - TimeDelta::days(1));
+    health.set_datafeed_updated_at(Utc::now() - TimeDelta::days(1));
+    health.set_oldest_queued_at(Some(Utc::now() - TimeDelta::seconds(90)));
+
+    let readiness = health.readiness().await;
+    assert!(readiness.ready, "{:?}", readiness.problems);
+    assert!(readiness.standby);
+
+    // Staleness left over from before the takeover isn't held against the new leader
+    health.set_standby(false);
+    health.set_oldest_queued_at(None);
+    let readiness = health.readiness().await;
+    assert!(readiness.ready, "{:?}", readiness.problems);
+    assert!(!readiness.standby);
+}
@@ -0,0 +1,103 @@
+use serde_json::{Value, json};
+use shared::vnas::datafeed::{DatafeedRoot, Role, UserRating, VatsimFacilityType};
+
+fn controller(cid: &str, callsign: &str) -> Value {
+    json!({
+        "artccId": "ZOA",
+        "primaryFacilityId": "ZOA",
+        "primaryPositionId": "01HGZ7MKTX5V3C5M0MY4G1P1K9",
+        "role": "Controller",
+        "positions": [{
+            "facilityId": "ZOA",
+            "facilityName": "Oakland ARTCC",
+            "positionId": "01HGZ7MKTX5V3C5M0MY4G1P1K9",
+            "positionName": "Oakland Center 33",
+            "positionType": "Artcc",
+            "radioName": "Oakland Center",
+            "defaultCallsign": "OAK_33_CTR",
+            "frequency": 128700000,
+            "isPrimary": true,
+            "isActive": true,
+            "eramData": { "sectorId": "33" },
+            "starsData": null
+        }],
+        "isActive": true,
+        "isObserver": false,
+        "loginTime": "2025-12-20T18:00:00.000Z",
+        "vatsimData": {
+            "cid": cid,
+            "realName": "Test Controller",
+            "controllerInfo": "",
+            "userRating": "Controller1",
+            "requestedRating": "Controller1",
+            "callsign": callsign,
+            "facilityType": "Center",
+            "primaryFrequency": 128700000
+        }
+    })
+}
+
+fn datafeed(controllers: Vec<Value>) -> Value {
+    json!({
+        "updatedAt": "2025-12-20T18:30:00.000Z",
+        "controllers": controllers
+    })
+}
+
+#[test]
+fn unknown_enum_values_are_kept() {
+    let mut c = controller("1234567", "OAK_33_CTR");
+    c["role"] = json!("Mentor");
+    c["vatsimData"]["facilityType"] = json!("Oceanic");
+    c["positions"][0]["positionType"] = json!("Fss");
+
+    let root = serde_json::from_value::<DatafeedRoot>(datafeed(vec![c])).unwrap();
+    let parsed = &root.controllers[0];
+    assert_eq!(parsed.role, Role::Unknown("Mentor".to_string()));
+    assert_eq!(
+        parsed.vatsim_data.facility_type,
+        VatsimFacilityType::Unknown("Oceanic".to_string())
+    );
+    assert_eq!(parsed.vatsim_data.user_rating, UserRating::Controller1);
+    assert_eq!(
+        root.unknown_values(),
+        vec![
+            ("role", "Mentor"),
+            ("facilityType", "Oceanic"),
+            ("positionType", "Fss")
+        ]
+    );
+
+    // Unknown values round-trip unchanged
+    let serialized = serde_json::to_value(parsed).unwrap();
+    assert_eq!(serialized["role"], json!("Mentor"));
+    assert_eq!(serialized["vatsimData"]["userRating"], json!("Controller1"));
+}
+
+#[test]
+fn malformed_controller_is_skipped() {
+    let mut broken = controller("7654321", "SFO_TWR");
+    broken["isActive"] = json!("yes");
+
+    let root = serde_json::from_value::<DatafeedRoot>(datafeed(vec![
+        controller("1234567", "OAK_33_CTR"),
+        broken,
+    ]))
+    .unwrap();
+
+    assert_eq!(root.controllers.len(), 1);
+    assert_eq!(root.controllers[0].vatsim_data.cid, "1234567");
+    assert_eq!(root.skipped_controllers.len(), 1);
+    let skipped = &root.skipped_controllers[0];
+    assert_eq!(skipped.index, 1);
+    assert_eq!(skipped.cid.as_deref(), Some("7654321"));
+    assert_eq!(skipped.callsign.as_deref(), Some("SFO_TWR"));
+}
+
+#[test]
+fn missing_updated_at_still_fails() {
+    let mut value = datafeed(vec![controller("1234567", "OAK_33_CTR")]);
+    value.as_object_mut().unwrap().remove("updatedAt");
+
+    assert!(serde_json::from_value::<DatafeedRoot>(value).is_err());
+}
@@ -0,0 +1,122 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{Value, json};
+use shared::payloads::{DELTA, PayloadWriter, SNAPSHOT};
+use shared::vnas::delta::{DatafeedDelta, apply, diff};
+use shared::{PayloadStorageConfig, StorageMode};
+use uuid::Uuid;
+
+const DATAFEED_FIXTURE: &str = include_str!("fixtures/datafeed.json");
+
+fn fixture() -> Value {
+    serde_json::from_str(DATAFEED_FIXTURE).unwrap()
+}
+
+fn round_trip(previous: &Value, current: &Value) -> DatafeedDelta {
+    let delta = diff(previous, current).unwrap();
+    // Deltas are stored as JSON, so make sure that survives too
+    let delta =
+        serde_json::from_slice::<DatafeedDelta>(&serde_json::to_vec(&delta).unwrap()).unwrap();
+    assert_eq!(apply(previous, &delta).as_ref(), Some(current));
+    delta
+}
+
+#[test]
+fn unchanged_controllers_are_not_stored() {
+    let previous = fixture();
+    let mut current = fixture();
+    current["updatedAt"] = json!("2025-12-20T18:30:15.000Z");
+
+    let delta = round_trip(&previous, &current);
+    assert!(delta.removed.is_empty());
+    assert!(delta.upserted.is_empty());
+    assert_eq!(delta.order, None);
+}
+
+#[test]
+fn added_removed_and_changed_controllers_round_trip() {
+    let previous = fixture();
+    let mut current = fixture();
+    let controllers = current["controllers"].as_array_mut().unwrap();
+    let removed = controllers.remove(2);
+    controllers[0]["vatsimData"]["controllerInfo"] = json!("changed");
+    let mut added = removed.clone();
+    added["vatsimData"]["cid"] = json!("9999999");
+    controllers.push(added);
+
+    let delta = round_trip(&previous, &current);
+    assert_eq!(delta.removed.len(), 1);
+    assert_eq!(delta.upserted.len(), 2);
+    assert_eq!(delta.order, None);
+}
+
+#[test]
+fn reordered_controllers_round_trip() {
+    let previous = fixture();
+    let mut current = fixture();
+    current["controllers"].as_array_mut().unwrap().reverse();
+
+    let delta = round_trip(&previous, &current);
+    assert!(delta.upserted.is_empty());
+    assert!(delta.order.is_some());
+}
+
+#[test]
+fn duplicate_controllers_are_not_delta_encoded() {
+    let previous = fixture();
+    let mut current = fixture();
+    let controllers = current["controllers"].as_array_mut().unwrap();
+    controllers.push(controllers[0].clone());
+
+    assert_eq!(diff(&previous, &current), None);
+}
+
+fn at(seconds: i64) -> DateTime<Utc> {
+    DateTime::<Utc>::UNIX_EPOCH + Duration::seconds(seconds)
+}
+
+#[test]
+fn writer_stores_keyframes_at_interval() {
+    let mut writer = PayloadWriter::new(&PayloadStorageConfig {
+        mode: StorageMode::Delta,
+        keyframe_interval: 3,
+    });
+    let payload = fixture();
+
+    let kinds = (0..7)
+        .map(|i| {
+            let encoded = writer.encode(&payload, at(i * 15)).unwrap();
+            writer.stored(Uuid::now_v7(), at(i * 15), payload.clone(), encoded.kind);
+            encoded.kind
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![SNAPSHOT, DELTA, DELTA, SNAPSHOT, DELTA, DELTA, SNAPSHOT]
+    );
+}
+
+#[test]
+fn writer_stores_out_of_order_payloads_as_snapshots() {
+    let mut writer = PayloadWriter::new(&PayloadStorageConfig {
+        mode: StorageMode::Delta,
+        keyframe_interval: 240,
+    });
+    let payload = fixture();
+    let first = writer.encode(&payload, at(30)).unwrap();
+    writer.stored(Uuid::now_v7(), at(30), payload.clone(), first.kind);
+
+    let earlier = writer.encode(&payload, at(15)).unwrap();
+    assert_eq!(earlier.kind, SNAPSHOT);
+    assert_eq!(earlier.base_payload_id, None);
+}
+
+#[test]
+fn snapshot_mode_never_writes_deltas() {
+    let mut writer = PayloadWriter::new(&PayloadStorageConfig::default());
+    let payload = fixture();
+    for i in 0..3 {
+        let encoded = writer.encode(&payload, at(i * 15)).unwrap();
+        assert_eq!(encoded.kind, SNAPSHOT);
+        writer.stored(Uuid::now_v7(), at(i * 15), payload.clone(), encoded.kind);
+    }
+}
@@ -0,0 +1,47 @@
+use shared::rate_limit::{Quota, RateLimiter};
+use std::time::{Duration, Instant};
+
+#[test]
+fn allows_a_burst_then_limits() {
+    let limiter = RateLimiter::default();
+    let quota = Quota {
+        per_minute: 60,
+        burst: 3,
+    };
+    let now = Instant::now();
+
+    for _ in 0..3 {
+        assert!(limiter.check_at("a", quota, now).is_ok());
+    }
+    let retry_after = limiter.check_at("a", quota, now).unwrap_err();
+    assert_eq!(retry_after, Duration::from_secs(1));
+
+    // Other clients have their own bucket
+    assert!(limiter.check_at("b", quota, now).is_ok());
+}
+
+#[test]
+fn refills_over_time() {
+    let limiter = RateLimiter::default();
+    let quota = Quota::per_minute(2);
+    let now = Instant::now();
+
+    assert!(limiter.check_at("a", quota, now).is_ok());
+    assert!(limiter.check_at("a", quota, now).is_ok());
+    assert!(limiter.check_at("a", quota, now).is_err());
+
+    // One token every 30 seconds
+    let later = now + Duration::from_secs(29);
+    let retry_after = limiter.check_at("a", quota, later).unwrap_err();
+    assert!(retry_after <= Duration::from_secs(1));
+    assert!(
+        limiter
+            .check_at("a", quota, now + Duration::from_secs(30))
+            .is_ok()
+    );
+    assert!(
+        limiter
+            .check_at("a", quota, now + Duration::from_secs(30))
+            .is_err()
+    );
+}
@@ -0,0 +1,66 @@
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::MeterProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use shared::telemetry::PrometheusReader;
+
+fn provider() -> (SdkMeterProvider, PrometheusReader) {
+    let reader = PrometheusReader::default();
+    let provider = SdkMeterProvider::builder()
+        .with_reader(reader.clone())
+        .build();
+    (provider, reader)
+}
+
+#[test]
+fn renders_counters_and_gauges() {
+    let (provider, reader) = provider();
+    let meter = provider.meter("test");
+    let processed = meter.u64_counter("datafeeds.processed").build();
+    processed.add(2, &[KeyValue::new("outcome", "new")]);
+    processed.add(1, &[KeyValue::new("outcome", "duplicate")]);
+    let bytes = meter
+        .u64_counter("datafeeds.processed.bytes.compressed")
+        .with_unit("B")
+        .build();
+    bytes.add(512, &[]);
+    let depth = meter.u64_gauge("queue.depth").build();
+    depth.record(7, &[]);
+
+    let rendered = reader.render().unwrap();
+    assert!(rendered.contains("# TYPE datafeeds_processed_total counter"));
+    assert!(rendered.contains("datafeeds_processed_total{outcome=\"new\"} 2"));
+    assert!(rendered.contains("datafeeds_processed_total{outcome=\"duplicate\"} 1"));
+    assert!(rendered.contains("datafeeds_processed_bytes_compressed_bytes_total 512"));
+    assert!(rendered.contains("# TYPE queue_depth gauge"));
+    assert!(rendered.contains("queue_depth 7"));
+}
+
+#[test]
+fn renders_cumulative_histogram_buckets() {
+    let (provider, reader) = provider();
+    let meter = provider.meter("test");
+    let duration = meter
+        .f64_histogram("db.query.duration")
+        .with_unit("s")
+        .with_boundaries(vec![0.1, 1.0])
+        .build();
+    let attributes = [KeyValue::new("query", "insert \"datafeed\"")];
+    duration.record(0.05, &attributes);
+    duration.record(0.5, &attributes);
+    duration.record(2.0, &attributes);
+
+    let rendered = reader.render().unwrap();
+    let labels = "query=\"insert \\\"datafeed\\\"\"";
+    assert!(rendered.contains("# TYPE db_query_duration_seconds histogram"));
+    assert!(rendered.contains(&format!(
+        "db_query_duration_seconds_bucket{{{labels},le=\"0.1\"}} 1"
+    )));
+    assert!(rendered.contains(&format!(
+        "db_query_duration_seconds_bucket{{{labels},le=\"1\"}} 2"
+    )));
+    assert!(rendered.contains(&format!(
+        "db_query_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 3"
+    )));
+    assert!(rendered.contains(&format!("db_query_duration_seconds_sum{{{labels}}} 2.55")));
+    assert!(rendered.contains(&format!("db_query_duration_seconds_count{{{labels}}} 3")));
+}
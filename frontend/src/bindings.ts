@@ -1,16 +1,78 @@
 // This file has been generated by Specta. DO NOT EDIT.
 
+export type ActivityGroupSeries = {
+  /**
+   * ARTCC id or facility type; `None` for positions without a known facility
+   */
+  key: string | null;
+  observations: string[];
+  max: ActivitySeries<number>;
+  min: ActivitySeries<number>;
+  avg: ActivitySeries<number>;
+};
+
+export type ActivitySeries<T> = {
+  activeControllers: T[];
+  activeCallsigns: T[];
+  activePositions: T[];
+};
+
 export type ActivityTimeSeriesResponse = {
   requestedAt: string;
   lastDatafeedUpdatedAt: string;
   start: string;
   end: string;
+  /**
+   * Root ARTCC the counts are limited to, if one was requested
+   */
+  artcc: string | null;
+  /**
+   * `raw`, or the width of each bucket, e.g. `15m`
+   */
+  resolution: string;
   observations: string[];
+  /**
+   * Raw counts, or the peak count in each bucket
+   */
   activeControllers: number[];
   activeCallsigns: number[];
   activePositions: number[];
+  /**
+   * Lowest count in each bucket; `None` for raw snapshots
+   */
+  min: ActivitySeries<number> | null;
+  /**
+   * Average count in each bucket; `None` for raw snapshots
+   */
+  avg: ActivitySeries<number> | null;
+  /**
+   * Per-group timeseries over the same buckets, if a breakdown was requested
+   */
+  breakdown: ActivityGroupSeries[] | null;
 };
 
+export type ApiKeyResponse = {
+  id: string;
+  name: string;
+  /**
+   * The start of the key, to tell keys apart
+   */
+  prefix: string;
+  scopes: ApiKeyScope[];
+  /**
+   * Requests per minute; `None` for the default limit
+   */
+  rateLimitPerMinute: number | null;
+  createdAt: string;
+  lastUsedAt: string | null;
+  revokedAt: string | null;
+};
+
+/**
+ * What an API key can be used for
+ */
+export type ApiKeyScope = "read" | "manage";
+
 export type CallsignDurationStats = {
   prefix: string;
   suffix: string;
@@ -18,6 +80,88 @@ export type CallsignDurationStats = {
   isActive: boolean | null;
 };
 
+export type ControllerDurationStats = {
+  cid: number;
+  name: string;
+  durationSeconds: number;
+  isActive: boolean | null;
+};
+
+export type ControllerIronMicResponse = {
+  requestedAt: string;
+  lastDatafeedUpdatedAt: string;
+  start: string;
+  end: string;
+  actualElapsedDurationSeconds: number;
+  controllers: ControllerDurationStats[];
+  /**
+   * Exclusions that were applied
+   */
+  exclusions: IronMicExclusion[];
+};
+
+export type CreateApiKeyRequest = {
+  name: string;
+  scopes: ApiKeyScope[];
+  /**
+   * Only admins can go above the default limit
+   */
+  rateLimitPerMinute: number | null;
+};
+
+export type CreateExclusionRequest = {
+  kind: ExclusionKind;
+  value: string;
+  reason: string;
+  /**
+   * Applies until deleted if unset
+   */
+  expiresAt: string | null;
+};
+
+export type CreatedApiKeyResponse = {
+  /**
+   * The key to send as `Authorization: Bearer <key>`. It isn't stored, so it can't be shown
+   * again.
+   */
+  key: string;
+  details: ApiKeyResponse;
+};
+
+/**
+ * What an Iron Mic exclusion's value is matched against
+ */
+export type ExclusionKind = "callsign" | "position" | "cid";
+
+export type ExclusionResponse = {
+  id: string;
+  kind: ExclusionKind;
+  value: string;
+  reason: string;
+  expiresAt: string | null;
+  /**
+   * `None` for exclusions added by migrations
+   */
+  createdBy: number | null;
+  createdAt: string;
+};
+
+export type GrantRoleRequest = {
+  cid: number;
+  role: Role;
+  /**
+   * Required for ARTCC staff, not allowed for admins
+   */
+  artccId: string | null;
+};
+
+export type IronMicExclusion = {
+  kind: ExclusionKind;
+  value: string;
+  reason: string;
+  expiresAt: string | null;
+};
+
 export type IronMicResponse = {
   requestedAt: string;
   lastDatafeedUpdatedAt: string;
@@ -25,4 +169,76 @@ export type IronMicResponse = {
   end: string;
   actualElapsedDurationSeconds: number;
   callsigns: CallsignDurationStats[];
+  /**
+   * Exclusions that were applied
+   */
+  exclusions: IronMicExclusion[];
+};
+
+export type MeResponse = {
+  cid: number;
+  admin: boolean;
+  /**
+   * Root ARTCCs the user is staff of
+   */
+  artccs: string[];
+};
+
+export type PositionDurationStats = {
+  positionId: string;
+  /**
+   * `None` for positions that aren't known from any facility
+   */
+  positionName: string | null;
+  facilityId: string | null;
+  facilityName: string | null;
+  durationSeconds: number;
+  isActive: boolean | null;
+};
+
+export type PositionIronMicResponse = {
+  requestedAt: string;
+  lastDatafeedUpdatedAt: string;
+  start: string;
+  end: string;
+  actualElapsedDurationSeconds: number;
+  positions: PositionDurationStats[];
+  /**
+   * Exclusions that were applied
+   */
+  exclusions: IronMicExclusion[];
+};
+
+/**
+ * A role that can be granted to a user
+ */
+export type Role = "admin" | "artccStaff";
+
+export type RoleGrantResponse = {
+  id: string;
+  cid: number;
+  role: Role;
+  /**
+   * Set for ARTCC staff only
+   */
+  artccId: string | null;
+  grantedBy: number;
+  createdAt: string;
+};
+
+export type SystemStatusResponse = {
+  requestedAt: string;
+  lastDatafeedUpdatedAt: string | null;
+  lastProcessedAt: string | null;
+  /**
+   * Seconds between `last_datafeed_updated_at` and `requested_at`
+   */
+  datafeedLagSeconds: number | null;
+  queueDepth: number;
+  oldestQueuedAt: string | null;
+  /**
+   * Whether the stats shown are behind the live datafeed, so a "data delayed" notice should be
+   * shown
+   */
+  delayed: boolean;
 };